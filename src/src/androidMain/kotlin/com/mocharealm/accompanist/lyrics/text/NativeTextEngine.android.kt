@@ -16,46 +16,87 @@ actual class NativeTextEngine {
     }
 
     actual external fun init(atlasWidth: Int, atlasHeight: Int)
-    actual external fun loadFont(bytes: ByteArray)
-    actual external fun loadFallbackFont(bytes: ByteArray)
+    actual external fun loadFont(bytes: ByteArray, faceIndex: Int)
+    actual external fun loadFallbackFont(bytes: ByteArray, faceIndex: Int)
+    actual external fun listFontFaces(bytes: ByteArray): String
     actual external fun clearFallbackFonts()
+    actual external fun registerFont(bytes: ByteArray, faceIndex: Int): Int
+    actual external fun unregisterFont(fontId: Int): Boolean
+    actual external fun setFontStack(name: String, fontIds: IntArray)
+    actual external fun removeFontStack(name: String): Boolean
+    actual external fun setLocaleFallbacks(locale: String, fontIds: IntArray, stack: String): Boolean
     
     // File descriptor-based font loading (more memory efficient)
-    external fun loadFallbackFontFd(fd: Int): Boolean
+    external fun loadFallbackFontFd(fd: Int, faceIndex: Int = 0): Boolean
+    external fun listFontFacesFd(fd: Int): String
+    external fun registerFontFd(fd: Int, faceIndex: Int = 0): Int
+    external fun loadSystemFonts(configPath: String, fontDirs: Array<String>): Int
 
-    actual external fun processText(text: String, sizeFn: Float, weight: Float): String
+    actual external fun setDefaultLanguage(language: String)
+    actual external fun processText(text: String, sizeFn: Float, weight: Float, options: String): String
+    actual external fun checkCoverage(text: String, options: String, discover: Boolean): String
+    actual external fun processParagraph(text: String, sizePx: Float, maxWidth: Float, weight: Float, options: String): String
+    actual external fun processSyllables(text: String, sizePx: Float, boundaries: IntArray, weight: Float, options: String): String
+    actual external fun processRuby(text: String, sizePx: Float, rubySizePx: Float, annotations: String, weight: Float, options: String): String
+    actual external fun processTextOutlines(text: String, sizePx: Float, weight: Float, options: String): String
+    actual external fun getCaretPositions(text: String, sizePx: Float, weight: Float, options: String): String
+    actual external fun hitTest(text: String, sizePx: Float, x: Float, weight: Float, options: String): String
     actual external fun hasPendingUploads(): Boolean
     actual external fun getPendingUploads(): String
     actual external fun getAtlasSize(): String
+    actual external fun getLayoutCacheStats(): String
+    actual external fun setLayoutCacheCapacity(capacity: Int)
     
     // Zero-copy DirectByteBuffer API (platform-specific, not in expect)
-    external fun processTextDirect(text: String, sizePx: Float, weight: Float, buffer: ByteBuffer): Int
+    external fun processTextDirect(text: String, sizePx: Float, weight: Float, options: String, buffer: ByteBuffer): Int
     external fun getPendingUploadsDirect(buffer: ByteBuffer): Int
     
     // Resource management
     actual external fun destroy()
 }
 
+/**
+ * Builds font stacks from the system font configuration, the way Android's own text stack
+ * falls back: every family and alias in it (`"sans-serif"`, `"serif"`, `"sans-serif-condensed"`, ...)
+ * becomes a font stack of that name, and the `"default"` stack gets the system fallback chain
+ * after its own fallbacks, with per-language chains so that e.g. Japanese lines use the Japanese
+ * CJK font. Font files are only memory-mapped once some text needs them.
+ *
+ * Calling this again replaces the system fonts loaded before.
+ *
+ * @return Number of stacks built, 0 if no font configuration could be read
+ */
+fun NativeTextEngine.loadAndroidSystemFonts(): Int {
+    // font_fallback.xml (Android 15+) lists the full fallback chain; older releases use fonts.xml
+    val config = listOf("/system/etc/font_fallback.xml", "/system/etc/fonts.xml")
+        .map(::File)
+        .firstOrNull { it.canRead() }
+        ?: return 0
+    val fontDirs = arrayOf("/system/fonts", "/product/fonts", "/system_ext/fonts")
+    return loadSystemFonts(config.absolutePath, fontDirs)
+}
+
 /**
  * Loads a fallback font from Android assets using zero-copy file descriptor.
  * More memory efficient than loading entire font into ByteArray.
  * 
  * @param context Android context for accessing assets
  * @param assetPath Path to font file in assets (e.g., "fonts/NotoSansCJK.otf")
+ * @param faceIndex Face to use if the file is a TTC/OTC collection
  * @return true if font loaded successfully
  */
-fun NativeTextEngine.loadFallbackFontFromAsset(context: Context, assetPath: String): Boolean {
+fun NativeTextEngine.loadFallbackFontFromAsset(context: Context, assetPath: String, faceIndex: Int = 0): Boolean {
     return try {
         val afd: AssetFileDescriptor = context.assets.openFd(assetPath)
         val fd = afd.parcelFileDescriptor.fd
-        val result = loadFallbackFontFd(fd)
+        val result = loadFallbackFontFd(fd, faceIndex)
         afd.close()
         result
     } catch (e: Exception) {
         // Asset might be compressed, fall back to ByteArray method
         try {
             val bytes = context.assets.open(assetPath).use { it.readBytes() }
-            loadFallbackFont(bytes)
+            loadFallbackFont(bytes, faceIndex)
             true
         } catch (e2: Exception) {
             false
@@ -67,14 +108,15 @@ fun NativeTextEngine.loadFallbackFontFromAsset(context: Context, assetPath: Stri
  * Loads a fallback font from a file path using zero-copy file descriptor.
  * 
  * @param filePath Absolute path to font file
+ * @param faceIndex Face to use if the file is a TTC/OTC collection
  * @return true if font loaded successfully
  */
-fun NativeTextEngine.loadFallbackFontFromFile(filePath: String): Boolean {
+fun NativeTextEngine.loadFallbackFontFromFile(filePath: String, faceIndex: Int = 0): Boolean {
     return try {
         val file = File(filePath)
         val fis = FileInputStream(file)
         val fd = fis.fd.hashCode() // Note: This is a hack, proper FD would need ParcelFileDescriptor
-        val result = loadFallbackFontFd(fd)
+        val result = loadFallbackFontFd(fd, faceIndex)
         fis.close()
         result
     } catch (e: Exception) {
@@ -5,19 +5,87 @@ actual class NativeTextEngine {
         // TODO: iOS/macOS native implementation via cinterop
     }
     
-    actual fun loadFont(bytes: ByteArray) {
+    actual fun loadFont(bytes: ByteArray, faceIndex: Int) {
         // TODO: iOS/macOS native implementation via cinterop
     }
     
-    actual fun loadFallbackFont(bytes: ByteArray) {
+    actual fun loadFallbackFont(bytes: ByteArray, faceIndex: Int) {
         // TODO: iOS/macOS native implementation via cinterop
     }
     
+    actual fun listFontFaces(bytes: ByteArray): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "[]"
+    }
+    
     actual fun clearFallbackFonts() {
         // TODO: iOS/macOS native implementation via cinterop
     }
     
-    actual fun processText(text: String, sizeFn: Float, weight: Float): String {
+    actual fun registerFont(bytes: ByteArray, faceIndex: Int): Int {
+        // TODO: iOS/macOS native implementation via cinterop
+        return -1
+    }
+    
+    actual fun unregisterFont(fontId: Int): Boolean {
+        // TODO: iOS/macOS native implementation via cinterop
+        return false
+    }
+    
+    actual fun setFontStack(name: String, fontIds: IntArray) {
+        // TODO: iOS/macOS native implementation via cinterop
+    }
+    
+    actual fun removeFontStack(name: String): Boolean {
+        // TODO: iOS/macOS native implementation via cinterop
+        return false
+    }
+    
+    actual fun setLocaleFallbacks(locale: String, fontIds: IntArray, stack: String): Boolean {
+        // TODO: iOS/macOS native implementation via cinterop
+        return false
+    }
+    
+    actual fun setDefaultLanguage(language: String) {
+        // TODO: iOS/macOS native implementation via cinterop
+    }
+    
+    actual fun processText(text: String, sizeFn: Float, weight: Float, options: String): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun checkCoverage(text: String, options: String, discover: Boolean): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun processParagraph(text: String, sizePx: Float, maxWidth: Float, weight: Float, options: String): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun processSyllables(text: String, sizePx: Float, boundaries: IntArray, weight: Float, options: String): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun processRuby(text: String, sizePx: Float, rubySizePx: Float, annotations: String, weight: Float, options: String): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun processTextOutlines(text: String, sizePx: Float, weight: Float, options: String): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun getCaretPositions(text: String, sizePx: Float, weight: Float, options: String): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun hitTest(text: String, sizePx: Float, x: Float, weight: Float, options: String): String {
         // TODO: iOS/macOS native implementation via cinterop
         return "{}"
     }
@@ -37,6 +105,15 @@ actual class NativeTextEngine {
         return """{"width":2048,"height":2048}"""
     }
     
+    actual fun getLayoutCacheStats(): String {
+        // TODO: iOS/macOS native implementation via cinterop
+        return "{}"
+    }
+    
+    actual fun setLayoutCacheCapacity(capacity: Int) {
+        // TODO: iOS/macOS native implementation via cinterop
+    }
+    
     // Resource management
     actual fun destroy() {
         // TODO: iOS/macOS native implementation via cinterop
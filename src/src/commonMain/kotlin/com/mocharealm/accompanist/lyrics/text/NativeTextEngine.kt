@@ -39,21 +39,88 @@ expect class NativeTextEngine() {
     /**
      * Loads the primary font for text rendering.
      * 
-     * @param bytes Raw bytes of the font file (TTF/OTF)
+     * @param bytes Raw bytes of the font file (TTF/OTF, or a TTC/OTC collection)
+     * @param faceIndex Face to use from a collection, see [listFontFaces]; 0 for single fonts
      */
-    fun loadFont(bytes: ByteArray)
+    fun loadFont(bytes: ByteArray, faceIndex: Int = 0)
     /**
      * Loads a fallback font for missing glyphs.
      * Fallback fonts are tried in order when the primary font
      * doesn't contain a glyph.
      * 
-     * @param bytes Raw bytes of the fallback font file (TTF/OTF)
+     * @param bytes Raw bytes of the fallback font file (TTF/OTF, or a TTC/OTC collection)
+     * @param faceIndex Face to use from a collection, see [listFontFaces]; 0 for single fonts
      */
-    fun loadFallbackFont(bytes: ByteArray)
+    fun loadFallbackFont(bytes: ByteArray, faceIndex: Int = 0)
+    /**
+     * Lists the faces in a font file, e.g. the weights or languages bundled in a collection
+     * such as NotoSansCJK-Regular.ttc.
+     *
+     * @param bytes Raw bytes of the font file
+     * @return JSON array with one object per face: `index` (to pass as `faceIndex`), `family`,
+     * `subfamily`, `postscript_name`, `weight`, `italic` and `is_variable`. A single TTF/OTF has
+     * one face; the array is empty if the data isn't a font
+     */
+    fun listFontFaces(bytes: ByteArray): String
     /**
      * Clears all loaded fallback fonts.
      */
     fun clearFallbackFonts()
+    /**
+     * Registers a font under a stable ID without putting it in any stack, for use with
+     * [setFontStack]. IDs are never reused, and every stack renders into the same atlas.
+     *
+     * @param bytes Raw bytes of the font file (TTF/OTF, or a TTC/OTC collection)
+     * @param faceIndex Face to use from a collection, see [listFontFaces]; 0 for single fonts
+     * @return The font's ID, or -1 if the data isn't a font
+     */
+    fun registerFont(bytes: ByteArray, faceIndex: Int = 0): Int
+    /**
+     * Unloads a font registered with [registerFont] and takes it out of every stack.
+     *
+     * @param fontId ID returned by [registerFont]
+     * @return false if there was no font with that ID
+     */
+    fun unregisterFont(fontId: Int): Boolean
+    /**
+     * Creates or replaces a named font stack, selected with the `font_stack` layout option.
+     * The first font sets the text and the rest are its fallback chain, tried in order for
+     * characters it lacks. The `"default"` stack is the one [loadFont] and [loadFallbackFont] fill.
+     *
+     * @param name Stack name, e.g. `"translation"`; empty for the `"default"` stack
+     * @param fontIds IDs from [registerFont], primary first; unknown IDs are skipped
+     */
+    fun setFontStack(name: String, fontIds: IntArray)
+    /**
+     * Removes a named font stack. Its fonts stay registered.
+     *
+     * @param name Stack name passed to [setFontStack]; empty for the `"default"` stack
+     * @return false if there was no stack with that name
+     */
+    fun removeFontStack(name: String): Boolean
+    /**
+     * Sets the fallback chain a font stack uses for text in a language or script, tried before
+     * its general fallbacks, so Han characters come from the right regional font, e.g.
+     * `"ja"` → Noto Sans JP and `"zh-Hant"` → Noto Sans TC. The line's language is the
+     * `fallback_language` or `language` option, else [setDefaultLanguage].
+     *
+     * Tags may name a language (`"ja"`), a language with a script or region (`"zh-Hant"`,
+     * `"zh-HK"`) or just a script (`"und-Hant"`); the most specific matching rule wins.
+     * `"zh-TW"`, `"zh-HK"` and `"zh-MO"` count as Traditional Chinese, other `"zh"` as Simplified.
+     *
+     * @param locale BCP-47 language/script tag the chain applies to
+     * @param fontIds IDs from [registerFont] in the order to try them; empty removes the chain
+     * @param stack Stack to set the chain on, see [setFontStack]; empty for the `"default"` stack
+     * @return false if [locale] isn't a valid tag
+     */
+    fun setLocaleFallbacks(locale: String, fontIds: IntArray, stack: String = "default"): Boolean
+    /**
+     * Sets the default BCP-47 language tag used for shaping (e.g. "ja", "zh-Hans", "zh-Hant", "ko").
+     * Fonts such as Noto Sans CJK pick locale-specific glyph forms from it.
+     *
+     * @param language Language tag, or an empty string to clear the default
+     */
+    fun setDefaultLanguage(language: String)
     /**
      * Processes text and returns layout information as JSON.
      * This also generates SDF glyphs for any new characters.
@@ -61,9 +128,159 @@ expect class NativeTextEngine() {
      * @param text The text to layout
      * @param sizeFn Font size in pixels
      * @param weight Font weight (100-900, default 400)
-     * @return JSON string containing layout result with glyph positions and atlas rects
+     * @param options Layout options as a JSON object, empty for defaults. Supported keys:
+     * - `direction`: paragraph base direction, one of `"auto"` (default), `"ltr"`, `"rtl"`
+     * - `language`: BCP-47 language tag, overrides [setDefaultLanguage] for this call
+     * - `features`: OpenType features in HarfBuzz syntax, e.g. `["-liga", "palt", "ss01", "kern[2:4]=0"]`;
+     *   ranges are code point (not UTF-16) offsets into [text]
+     * - `orientation`: `"horizontal"` (default) or `"vertical"`; vertical text runs down a single
+     *   column (upright CJK, Latin turned sideways) and positions are measured from the column's
+     *   top-left corner, with `is_vertical` set in the result
+     * - `letter_spacing`: extra space after every grapheme cluster, as px (`2` or `"2px"`) or em (`"0.05em"`);
+     *   negative values tighten. Not applied to cursive scripts such as Arabic
+     * - `word_spacing`: extra space after word separators (spaces), same units
+     * - `instance`: named instance of a variable font, by its `fvar` name or PostScript name
+     *   (e.g. `"Condensed Bold"`)
+     * - `variations`: variable font axis values by tag, e.g. `{"wdth": 75, "slnt": -10, "GRAD": 50}`;
+     *   applied to every font that has the axis and clamped to its range. [weight] sets `wght` and
+     *   `opsz` follows [sizeFn] unless the instance or these values say otherwise
+     * - `style`: `"normal"` (default), `"italic"` or `"oblique"`; uses the font's `ital`/`slnt` axes,
+     *   or slants the glyphs synthetically when the font is upright. Likewise a [weight] of 600 or
+     *   more is emboldened synthetically (widening advances) on static fonts lighter than that
+     * - `font_stack`: name of a stack set with [setFontStack] to lay the text out with, instead of
+     *   the `"default"` one (also used when the name is unknown)
+     * - `fallback_language`: BCP-47 tag choosing the stack's chain from [setLocaleFallbacks] when it
+     *   should differ from `language`
+     * @return JSON string containing layout result with glyph positions and atlas rects, plus
+     * `ink_bounds`: the tight outline bounds (left, top, right, bottom) of each glyph relative to
+     * its position, y down, without the SDF padding; `ink_top`/`ink_bottom` of the whole line in the
+     * same frame as the positions; and the primary font's `cap_height`, `x_height`, `line_gap`,
+     * `underline_position`/`underline_thickness`, `strikeout_position`/`strikeout_thickness`,
+     * `typo_ascent`/`typo_descent`/`typo_line_gap` and `win_ascent`/`win_descent` (up from the baseline).
+     * Characters no font of the stack has a glyph for are drawn as the primary font's .notdef and
+     * listed in `uncovered_codepoints`, with their offsets in `uncovered_offsets` (code points)
+     * and `uncovered_offsets_utf16`
+     */
+    fun processText(text: String, sizeFn: Float, weight: Float = 400f, options: String = ""): String
+    /**
+     * Checks which characters the font stack can render, without laying the text out or
+     * generating glyphs, so a line can get a fallback font or be drawn by the platform instead.
+     * By default nothing changes: the stack is checked as it is.
+     *
+     * @param text The text to check
+     * @param options Layout options as a JSON object, see [processText]; `font_stack`,
+     *   `language` and `fallback_language` pick the fonts
+     * @param discover On desktop with font discovery turned on, first add system fonts found
+     *   for the missing characters to the stack, as [processText] would
+     * @return JSON string with `uncovered_codepoints`, `uncovered_offsets` (code points) and
+     * `uncovered_offsets_utf16` of the characters no font has a glyph for; all empty when the
+     * stack covers the whole text
+     */
+    fun checkCoverage(text: String, options: String = "", discover: Boolean = false): String
+    /**
+     * Lays out a paragraph wrapped to [maxWidth], breaking lines at Unicode (UAX #14)
+     * line break opportunities. Generates SDF glyphs like [processText].
+     *
+     * @param text The paragraph text; `\n` forces a line break
+     * @param sizePx Font size in pixels
+     * @param maxWidth Maximum line width in pixels
+     * @param weight Font weight (100-900, default 400)
+     * @param options Layout options as a JSON object, see [processText] (`orientation` is ignored)
+     * @return JSON string with the layout result fields for all lines (positions are relative
+     * to each line's left edge and shifted down by the line's top), plus `line_height` and
+     * `lines`: [{text_start, text_end, text_start_utf16, text_end_utf16, glyph_start, glyph_end,
+     * width, top, baseline}]. A line's visible glyphs span 0 to `width`; trailing spaces hang
+     * past its end, which is left of 0 in right-to-left lines
      */
-    fun processText(text: String, sizeFn: Float, weight: Float = 400f): String
+    fun processParagraph(text: String, sizePx: Float, maxWidth: Float, weight: Float = 400f, options: String = ""): String
+    /**
+     * Shapes a whole lyric line at once and reports where each syllable landed, so kerning,
+     * ligatures, Arabic joining and Indic conjuncts work across syllable boundaries.
+     * Generates SDF glyphs like [processText].
+     *
+     * @param text The full line text (all syllables concatenated)
+     * @param sizePx Font size in pixels
+     * @param boundaries UTF-16 offsets into [text] where one syllable ends and the next begins
+     * (n - 1 offsets for n syllables)
+     * @param weight Font weight (100-900, default 400)
+     * @param options Layout options as a JSON object, see [processText] (horizontal only)
+     * @return JSON string with the layout result fields for the whole line plus `syllables`:
+     * [{text_start, text_end, text_start_utf16, text_end_utf16, glyph_ranges, x, width}].
+     * `glyph_ranges` holds (start, end) glyph index pairs in visual order, more than one when
+     * bidi reordering splits a syllable. `x` and `width` include trailing spaces; a ligature
+     * split across syllables has its glyph in the first one and its width shared between them
+     */
+    fun processSyllables(text: String, sizePx: Float, boundaries: IntArray, weight: Float = 400f, options: String = ""): String
+    /**
+     * Lays out text with ruby annotations (furigana, pinyin) above it. Generates SDF glyphs
+     * for both layers like [processText].
+     *
+     * A ruby wider than its base widens the spacing of the base clusters; a narrower one is
+     * centred over them or, with `"align": "distribute"`, spread evenly across them.
+     *
+     * @param text The base text
+     * @param sizePx Font size of the base text in pixels
+     * @param rubySizePx Font size of the ruby text in pixels
+     * @param annotations JSON array of `{start, end, text, align}`, where `start`/`end` are
+     * code point offsets into [text] and `align` is `"center"` (default) or `"distribute"`
+     * @param weight Font weight (100-900, default 400)
+     * @param options Layout options as a JSON object, see [processText] (horizontal only;
+     * `features` apply to the base text)
+     * @return JSON string with two layout results, `base` and `ruby`, drawn from the same top-left
+     * origin (base positions are already shifted down by `ruby_height`), plus `ruby_height`,
+     * `total_width`, `total_height` and `spans`: [{start, end, x, width, glyph_start, glyph_end,
+     * uncovered_codepoints, uncovered_offsets, uncovered_offsets_utf16}]. A span's uncovered
+     * offsets index its annotation's `text`; the `ruby` layer's own uncovered lists are empty
+     */
+    fun processRuby(text: String, sizePx: Float, rubySizePx: Float, annotations: String, weight: Float = 400f, options: String = ""): String
+    /**
+     * Lays out text like [processText] but returns vector outlines instead of atlas entries,
+     * for sizes too large for the atlas or for exporting paths. Nothing is rasterized.
+     *
+     * @param text The text to layout
+     * @param sizePx Font size in pixels
+     * @param weight Font weight (100-900, default 400), applied to variable fonts
+     * @param options Layout options as a JSON object, see [processText]
+     * @return JSON string with `glyph_count`, `glyph_ids`, `clusters_utf16`, `total_width`,
+     * `total_height`, `ascent`, `descent`, `is_vertical` and the flattened paths: `verbs`
+     * (0 = move, 1 = line, 2 = quad, 3 = cubic, 4 = close, taking 1, 1, 2, 3 and 0 points),
+     * `points` (x, y pairs in px, y down, with the baseline at `ascent` for horizontal text),
+     * and `verb_starts`/`point_starts` giving each glyph's range plus a final end entry
+     */
+    fun processTextOutlines(text: String, sizePx: Float, weight: Float = 400f, options: String = ""): String
+    /**
+     * Maps text offsets to x positions on a single horizontal line. Only shapes the text,
+     * no glyphs are generated.
+     *
+     * A caret sits at the leading edge of the grapheme after it (its right edge inside RTL runs),
+     * and the end caret at the trailing edge of the last grapheme. Offsets inside a grapheme or
+     * surrogate pair snap to its start; ligatures get carets between their components from the
+     * font's GDEF table, or split evenly when it has none.
+     *
+     * @param text The line text
+     * @param sizePx Font size in pixels
+     * @param weight Font weight (100-900, default 400)
+     * @param options Layout options as a JSON object, see [processText] (horizontal only)
+     * @return JSON string with `carets` (caret x for every UTF-16 offset from 0 to `text.length`
+     * inclusive), `total_width` and `is_rtl`
+     */
+    fun getCaretPositions(text: String, sizePx: Float, weight: Float = 400f, options: String = ""): String
+    /**
+     * Maps an x position on a single horizontal line to the nearest caret offset, for example
+     * a tap. Only shapes the text, no glyphs are generated.
+     *
+     * The grapheme (or ligature component) under [x] decides: its logical start or end, whichever
+     * edge is nearer. Positions beyond the line hit its outermost grapheme.
+     *
+     * @param text The line text
+     * @param sizePx Font size in pixels
+     * @param x Position relative to the line's left edge in pixels
+     * @param weight Font weight (100-900, default 400)
+     * @param options Layout options as a JSON object, see [processText] (horizontal only)
+     * @return JSON string with `offset` (code points), `offset_utf16`, `caret_x` (the edge the
+     * caret snapped to) and `is_rtl` (direction of the hit grapheme)
+     */
+    fun hitTest(text: String, sizePx: Float, x: Float, weight: Float = 400f, options: String = ""): String
     /**
      * Checks if there are pending glyph uploads.
      * @return true if new glyphs were generated and need to be uploaded to the atlas
@@ -81,6 +298,20 @@ expect class NativeTextEngine() {
      * @return JSON object: {width: N, height: N}
      */
     fun getAtlasSize(): String
+    /**
+     * Gets the counters of the layout cache, which lets repeated [processText] and
+     * [processSyllables] calls with the same text, size, weight, options (and boundaries) skip
+     * shaping. Changing fonts or the default language clears it,
+     * and a cached layout whose glyphs were evicted from the atlas is laid out again.
+     * @return JSON object: {hits: N, misses: N, invalidations: N, entries: N, capacity: N}
+     */
+    fun getLayoutCacheStats(): String
+    /**
+     * Sets how many layouts [processText] and [processSyllables] keep between them (default 256),
+     * dropping the least recently used.
+     * @param capacity Number of layouts, 0 to turn the cache off
+     */
+    fun setLayoutCacheCapacity(capacity: Int)
     
     // Resource management
     /**
@@ -411,8 +411,10 @@ private fun DrawScope.drawGlyphsFromLayout(
         // But we have SDF padding included in bearingY, so:
         //   bitmap top = baseline - bearingY - atlasH
         // In screen coords (y down): subtract moves up
+        // Vertical layouts are positioned from the column's top-left corner instead
+        val baselineY = if (layout.is_vertical) 0f else layout.ascent
         val destX = baseOffset.x + glyphX + bearingX
-        val destY = baseOffset.y + layout.ascent + glyphY - bearingY - atlasH
+        val destY = baseOffset.y + baselineY + glyphY - bearingY - atlasH
         
         val destOffset = Offset(destX, destY)
         val destSize = Size(atlasW, atlasH)
@@ -509,10 +511,6 @@ fun KaraokeLineText(
                 baseStyle.copy(textDirection = TextDirection.Content)
             }
 
-            val spaceWidth = remember(textMeasurer, textStyle) {
-                textMeasurer.measure(" ", textStyle).size.width.toFloat()
-            }
-
             val processedSyllables = remember(line.syllables, line.alignment) {
                 if (line.alignment == KaraokeAlignment.End) {
                     line.syllables.dropLastWhile { it.content.isBlank() }
@@ -549,7 +547,6 @@ fun KaraokeLineText(
                         textMeasurer = textMeasurer,
                         style = textStyle,
                         isAccompanimentLine = line.isAccompaniment,
-                        spaceWidth = spaceWidth,
                         fontFamilyResolver = fontFamilyResolver,
                         density = density,
                         nativeEngine = nativeEngine,
@@ -184,16 +184,10 @@ fun KaraokeLyricsView(
             val accompanimentStyle =
                 stableAccompanimentTextStyle.copy(textDirection = TextDirection.Content)
 
-            val normalSpaceWidth = textMeasurer.measure(" ", normalStyle).size.width.toFloat()
-            val accompanimentSpaceWidth =
-                textMeasurer.measure(" ", accompanimentStyle).size.width.toFloat()
-
             lyrics.lines.forEachIndexed { index, line ->
                 if (!isActive) return@forEachIndexed
                 if (line is KaraokeLine) {
                     val style = if (line.isAccompaniment) accompanimentStyle else normalStyle
-                    val spaceWidth =
-                        if (line.isAccompaniment) accompanimentSpaceWidth else normalSpaceWidth
 
                     val processedSyllables = if (line.alignment == KaraokeAlignment.End) {
                         line.syllables.dropLastWhile { it.content.isBlank() }
@@ -206,7 +200,6 @@ fun KaraokeLyricsView(
                         textMeasurer = textMeasurer,
                         style = style,
                         isAccompanimentLine = line.isAccompaniment,
-                        spaceWidth = spaceWidth,
                         fontFamilyResolver = fontFamilyResolver,
                         density = density,
                         nativeEngine = nativeEngine,
@@ -29,6 +29,12 @@ import kotlin.math.pow
  * @param total_height Total height (bounding box height).
  * @param ascent Font ascent (distance from baseline to top).
  * @param descent Font descent (distance from baseline to bottom).
+ * @param clusters_utf16 UTF-16 offset of the source cluster for each glyph.
+ * @param is_vertical Whether the glyphs run down a column; positions are then relative to its top-left corner.
+ * @param ink_bounds Tight outline bounds (left, top, right, bottom) of each glyph relative to its position, y down.
+ * @param metrics Cap height, x-height, decoration and typo/win metrics of the primary font.
+ * @param ink_top Top of the glyphs' ink in the frame of [positions]; may sit above the ascent (stacked marks).
+ * @param ink_bottom Bottom of the glyphs' ink; equal to [ink_top] when nothing is inked.
  */
 @Stable
 data class NativeLayoutResult(
@@ -40,12 +46,58 @@ data class NativeLayoutResult(
     val total_width: Float,
     val total_height: Float,
     val ascent: Float,
-    val descent: Float
+    val descent: Float,
+    val clusters_utf16: List<Int> = emptyList(),
+    val is_vertical: Boolean = false,
+    val ink_bounds: List<Float> = emptyList(),
+    val metrics: NativeFontMetrics = NativeFontMetrics(),
+    val ink_top: Float = 0f,
+    val ink_bottom: Float = 0f
 ) {
     val size: IntSize get() = IntSize(total_width.toInt(), total_height.toInt())
     val firstBaseline: Float get() = ascent
 }
 
+/**
+ * Font-wide metrics of a layout's primary font, in pixels. Like ascent and descent they are
+ * measured up from the baseline, so descents and underline positions are usually negative;
+ * decoration positions are the top edge of the stroke.
+ */
+@Stable
+data class NativeFontMetrics(
+    val cap_height: Float = 0f,
+    val x_height: Float = 0f,
+    val line_gap: Float = 0f,
+    val underline_position: Float = 0f,
+    val underline_thickness: Float = 0f,
+    val strikeout_position: Float = 0f,
+    val strikeout_thickness: Float = 0f,
+    val typo_ascent: Float = 0f,
+    val typo_descent: Float = 0f,
+    val typo_line_gap: Float = 0f,
+    val win_ascent: Float = 0f,
+    val win_descent: Float = 0f
+)
+
+private fun parseFontMetrics(json: String): NativeFontMetrics {
+    fun field(name: String) =
+        Regex(""""$name"\s*:\s*([\d.eE+-]+)""").find(json)?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
+    return NativeFontMetrics(
+        cap_height = field("cap_height"),
+        x_height = field("x_height"),
+        line_gap = field("line_gap"),
+        underline_position = field("underline_position"),
+        underline_thickness = field("underline_thickness"),
+        strikeout_position = field("strikeout_position"),
+        strikeout_thickness = field("strikeout_thickness"),
+        typo_ascent = field("typo_ascent"),
+        typo_descent = field("typo_descent"),
+        typo_line_gap = field("typo_line_gap"),
+        win_ascent = field("win_ascent"),
+        win_descent = field("win_descent")
+    )
+}
+
 // Helper to parse JSON (MVP hack: use regex or assume strict format from Rust)
 // Robust way: kotlinx.serialization.
 // For now, I will use a dummy function that needs implementation or manual parsing.
@@ -62,18 +114,26 @@ fun parseRustResult(json: String): NativeLayoutResult {
         val totalHeightMatch = Regex(""""total_height"\s*:\s*([\d.]+)""").find(json)
         val ascentMatch = Regex(""""ascent"\s*:\s*([\d.]+)""").find(json)
         val descentMatch = Regex(""""descent"\s*:\s*([\d.-]+)""").find(json)
+        val isVerticalMatch = Regex(""""is_vertical"\s*:\s*(true|false)""").find(json)
+        val inkTopMatch = Regex(""""ink_top"\s*:\s*([\d.eE+-]+)""").find(json)
+        val inkBottomMatch = Regex(""""ink_bottom"\s*:\s*([\d.eE+-]+)""").find(json)
         
         val glyphCount = glyphCountMatch?.groupValues?.get(1)?.toIntOrNull() ?: 0
         val totalWidth = totalWidthMatch?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
         val totalHeight = totalHeightMatch?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
         val ascent = ascentMatch?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
         val descent = descentMatch?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
+        val isVertical = isVerticalMatch?.groupValues?.get(1) == "true"
+        val inkTop = inkTopMatch?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
+        val inkBottom = inkBottomMatch?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
         
         // Extract arrays
         val glyphIdsMatch = Regex(""""glyph_ids"\s*:\s*\[([\d,\s]*)\]""").find(json)
         val positionsMatch = Regex(""""positions"\s*:\s*\[([\d.,\s-]*)\]""").find(json)
         val atlasRectsMatch = Regex(""""atlas_rects"\s*:\s*\[([\d.,\s-]*)\]""").find(json)
         val glyphOffsetsMatch = Regex(""""glyph_offsets"\s*:\s*\[([\d.,\s-]*)\]""").find(json)
+        val clustersUtf16Match = Regex(""""clusters_utf16"\s*:\s*\[([\d,\s]*)\]""").find(json)
+        val inkBoundsMatch = Regex(""""ink_bounds"\s*:\s*\[([\d.eE,\s+-]*)\]""").find(json)
         
         val glyphIds = glyphIdsMatch?.groupValues?.get(1)
             ?.split(",")
@@ -95,6 +155,16 @@ fun parseRustResult(json: String): NativeLayoutResult {
             ?.mapNotNull { it.trim().toFloatOrNull() }
             ?: emptyList()
         
+        val clustersUtf16 = clustersUtf16Match?.groupValues?.get(1)
+            ?.split(",")
+            ?.mapNotNull { it.trim().toIntOrNull() }
+            ?: emptyList()
+
+        val inkBounds = inkBoundsMatch?.groupValues?.get(1)
+            ?.split(",")
+            ?.mapNotNull { it.trim().toFloatOrNull() }
+            ?: emptyList()
+
         return NativeLayoutResult(
             glyph_count = glyphCount,
             glyph_ids = glyphIds,
@@ -104,13 +174,134 @@ fun parseRustResult(json: String): NativeLayoutResult {
             total_width = totalWidth,
             total_height = totalHeight,
             ascent = ascent,
-            descent = descent
+            descent = descent,
+            clusters_utf16 = clustersUtf16,
+            is_vertical = isVertical,
+            ink_bounds = inkBounds,
+            metrics = parseFontMetrics(json),
+            ink_top = inkTop,
+            ink_bottom = inkBottom
         )
     } catch (e: Exception) {
         // Fallback to empty on parse error
         return NativeLayoutResult(0, emptyList(), emptyList(), emptyList(), emptyList(), 0f, 0f, 0f, 0f)
     }
 }
+/**
+ * Top and bottom of the ink of the given glyphs, in the frame of [NativeLayoutResult.positions].
+ * Both are 0 when none of them is inked (or the engine sent no ink bounds).
+ */
+private fun NativeLayoutResult.inkExtent(indices: List<Int>): Pair<Float, Float> {
+    var extent: Pair<Float, Float>? = null
+    for (i in indices) {
+        if (ink_bounds.size < i * 4 + 4) continue
+        val y = positions[i * 2 + 1]
+        val top = y + ink_bounds[i * 4 + 1]
+        val bottom = y + ink_bounds[i * 4 + 3]
+        if (bottom <= top) continue
+        extent = extent?.let { minOf(it.first, top) to maxOf(it.second, bottom) } ?: (top to bottom)
+    }
+    return extent ?: (0f to 0f)
+}
+
+/**
+ * Extracts the glyphs belonging to the UTF-16 range [start, end) of the source text
+ * as a standalone layout whose origin is the left edge of the range.
+ *
+ * Width is measured up to the first glyph of the next cluster, so characters inside
+ * a ligature (which own no glyph) come back empty with zero width.
+ */
+fun NativeLayoutResult.sliceByCluster(start: Int, end: Int): NativeLayoutResult {
+    val indices = (0 until glyph_count).filter { clusters_utf16.getOrNull(it) in start until end }
+    if (indices.isEmpty()) {
+        return NativeLayoutResult(0, emptyList(), emptyList(), emptyList(), emptyList(), 0f, total_height, ascent, descent, metrics = metrics)
+    }
+    val startX = positions[indices.first() * 2]
+    val nextIndex = (indices.last() + 1 until glyph_count).firstOrNull { clusters_utf16[it] >= end }
+    val endX = nextIndex?.let { positions[it * 2] } ?: total_width
+    val (inkTop, inkBottom) = inkExtent(indices)
+
+    return NativeLayoutResult(
+        glyph_count = indices.size,
+        glyph_ids = indices.map { glyph_ids[it] },
+        positions = indices.flatMap { listOf(positions[it * 2] - startX, positions[it * 2 + 1]) },
+        atlas_rects = indices.flatMap { i -> (0 until 4).map { atlas_rects[i * 4 + it] } },
+        glyph_offsets = indices.flatMap { listOf(glyph_offsets[it * 2], glyph_offsets[it * 2 + 1]) },
+        total_width = endX - startX,
+        total_height = total_height,
+        ascent = ascent,
+        descent = descent,
+        clusters_utf16 = indices.map { clusters_utf16[it] - start },
+        ink_bounds = if (ink_bounds.isEmpty()) emptyList() else indices.flatMap { i -> (0 until 4).map { ink_bounds[i * 4 + it] } },
+        metrics = metrics,
+        ink_top = inkTop,
+        ink_bottom = inkBottom
+    )
+}
+
+/**
+ * Where one syllable landed in a line shaped by [NativeTextEngine.processSyllables].
+ *
+ * @param text_start_utf16 UTF-16 offset of the syllable in the line text.
+ * @param text_end_utf16 UTF-16 offset past the end of the syllable.
+ * @param x Left edge of the syllable in the line, trailing spaces included.
+ * @param width Advance width of the syllable, trailing spaces included.
+ */
+@Stable
+data class NativeSyllableSpan(
+    val text_start_utf16: Int,
+    val text_end_utf16: Int,
+    val x: Float,
+    val width: Float
+)
+
+fun parseSyllableSpans(json: String): List<NativeSyllableSpan> {
+    // Syllable objects hold arrays of their own, so match whole objects up to the closing bracket
+    val syllablesJson = Regex(""""syllables"\s*:\s*\[((?:\{[^}]*\},?)*)\]""").find(json)?.groupValues?.get(1)
+        ?: return emptyList()
+    fun Regex.floatIn(obj: String) = find(obj)?.groupValues?.get(1)?.toFloatOrNull() ?: 0f
+    val startRegex = Regex(""""text_start_utf16"\s*:\s*(\d+)""")
+    val endRegex = Regex(""""text_end_utf16"\s*:\s*(\d+)""")
+    val xRegex = Regex(""""x"\s*:\s*([\d.eE+-]+)""")
+    val widthRegex = Regex(""""width"\s*:\s*([\d.eE+-]+)""")
+    return Regex("""\{[^}]*\}""").findAll(syllablesJson).map { match ->
+        val obj = match.value
+        NativeSyllableSpan(
+            text_start_utf16 = startRegex.floatIn(obj).toInt(),
+            text_end_utf16 = endRegex.floatIn(obj).toInt(),
+            x = xRegex.floatIn(obj),
+            width = widthRegex.floatIn(obj)
+        )
+    }.toList()
+}
+
+/**
+ * Extracts a syllable's glyphs from a whole-line layout. Unlike [sliceByCluster], the origin
+ * and width come from the engine, so kerning, ligatures split across syllables and trailing
+ * spaces are all accounted for.
+ */
+fun NativeLayoutResult.sliceSyllable(span: NativeSyllableSpan): NativeLayoutResult {
+    val start = span.text_start_utf16
+    val indices = (0 until glyph_count).filter { clusters_utf16.getOrNull(it) in start until span.text_end_utf16 }
+    val (inkTop, inkBottom) = inkExtent(indices)
+    return NativeLayoutResult(
+        glyph_count = indices.size,
+        glyph_ids = indices.map { glyph_ids[it] },
+        positions = indices.flatMap { listOf(positions[it * 2] - span.x, positions[it * 2 + 1]) },
+        atlas_rects = indices.flatMap { i -> (0 until 4).map { atlas_rects[i * 4 + it] } },
+        glyph_offsets = indices.flatMap { listOf(glyph_offsets[it * 2], glyph_offsets[it * 2 + 1]) },
+        total_width = span.width,
+        total_height = total_height,
+        ascent = ascent,
+        descent = descent,
+        clusters_utf16 = indices.map { clusters_utf16[it] - start },
+        ink_bounds = if (ink_bounds.isEmpty()) emptyList() else indices.flatMap { i -> (0 until 4).map { ink_bounds[i * 4 + it] } },
+        metrics = metrics,
+        ink_top = inkTop,
+        ink_bottom = inkBottom
+    )
+}
+
 /**
  * Represents the layout information for a single karaoke syllable.
  * This includes the text layout from the native engine, as well as animation metadata.
@@ -195,7 +386,6 @@ fun groupIntoWords(syllables: List<KaraokeSyllable>): List<List<KaraokeSyllable>
  * @param textMeasurer Helper for measuring constraint spaces (space char).
  * @param style Text style to use.
  * @param isAccompanimentLine Whether this is a backing vocal line.
- * @param spaceWidth Pre-measured width of a space character.
  * @param fontFamilyResolver Resolver for fonts.
  * @param density Screen density.
  * @param nativeEngine The native text engine for layout.
@@ -207,7 +397,6 @@ fun measureSyllablesAndDetermineAnimation(
     textMeasurer: TextMeasurer,
     style: TextStyle,
     isAccompanimentLine: Boolean,
-    spaceWidth: Float,
     fontFamilyResolver: FontFamily.Resolver,
     density: Density,
     nativeEngine: NativeTextEngine,
@@ -217,6 +406,23 @@ fun measureSyllablesAndDetermineAnimation(
     val words = groupIntoWords(syllables)
     val fastCharAnimationThresholdMs = 200f
 
+    // Font size? style.fontSize. Assuming pixel size is needed.
+    // style.fontSize.value needs density.
+    val fontSizePx = if (style.fontSize.isSp) style.fontSize.value * density.fontScale * density.density else style.fontSize.value
+    val fontWeight = style.fontWeight?.weight?.toFloat() ?: 400f
+
+    // NATIVE ENGINE CALL: shape the whole line once so kerning, ligatures and joining
+    // carry across syllable boundaries, then cut it back into syllables
+    val lineText = syllables.joinToString("") { it.content }
+    val boundaries = syllables.dropLast(1)
+        .runningFold(0) { offset, syllable -> offset + syllable.content.length }
+        .drop(1)
+        .toIntArray()
+    val lineJson = nativeEngine.processSyllables(lineText, fontSizePx, boundaries, fontWeight)
+    val lineLayout = parseRustResult(lineJson)
+    val syllableSpans = parseSyllableSpans(lineJson)
+    var syllableIndex = 0
+
     val initialLayouts = words.flatMapIndexed { wordIndex, word ->
         val wordContent = word.joinToString("") { it.content }
         val wordDuration = if (word.isNotEmpty()) word.last().end - word.first().start else 0
@@ -232,37 +438,18 @@ fun measureSyllablesAndDetermineAnimation(
                     && !isAccompanimentLine
 
         word.map { syllable ->
-            // NATIVE ENGINE CALL
-            // Font size? style.fontSize. Assuming pixel size is needed.
-            // style.fontSize.value needs density.
-            val densityVal = density.density
-            val fontSizePx = if (style.fontSize.isSp) style.fontSize.value * density.fontScale * density.density else style.fontSize.value
-            val fontWeight = style.fontWeight?.weight?.toFloat() ?: 400f
-            
-            val jsonResult = nativeEngine.processText(syllable.content, fontSizePx, fontWeight)
-            var layoutResult = parseRustResult(jsonResult)
-
-            // --- Fix: 修正尾部空格宽度丢失 ---
-            var layoutWidth = layoutResult.total_width
-            if (syllable.content.endsWith(" ")) {
-                 val trimmedJson = nativeEngine.processText(syllable.content.trimEnd(), fontSizePx, fontWeight)
-                 val trimmedLayout = parseRustResult(trimmedJson)
-                 val trimmedWidth = trimmedLayout.total_width
-                 
-                if (layoutWidth <= trimmedWidth) {
-                    val spaceCount = syllable.content.length - syllable.content.trimEnd().length
-                    layoutWidth = trimmedWidth + (spaceWidth * spaceCount)
-                    // Update layoutResult width
-                    layoutResult = layoutResult.copy(total_width = layoutWidth)
-                }
+            // Trailing spaces are part of the syllable's span, so its width needs no fixing up
+            val span = syllableSpans.getOrNull(syllableIndex++)
+            val layoutResult = if (span != null) {
+                lineLayout.sliceSyllable(span)
+            } else {
+                parseRustResult(nativeEngine.processText(syllable.content, fontSizePx, fontWeight))
             }
-            // -----------------------------
 
-            // 新增：如果需要高级动画，预先测量每个字符
+            // 新增：如果需要高级动画，按字形簇从整个音节的排版中切出每个字符
             val (charLayouts, charBounds) = if (useAwesomeAnimation) {
-                val layouts = syllable.content.map { char ->
-                     val cJson = nativeEngine.processText(char.toString(), fontSizePx, fontWeight)
-                     parseRustResult(cJson)
+                val layouts = syllable.content.indices.map { charIndex ->
+                    layoutResult.sliceByCluster(charIndex, charIndex + 1)
                 }
                 // Calculate bounds from layouts
                 var xOffset = 0f
@@ -281,7 +468,7 @@ fun measureSyllablesAndDetermineAnimation(
                 layoutResult = layoutResult,
                 wordId = wordIndex,
                 useAwesomeAnimation = useAwesomeAnimation,
-                width = layoutWidth, // 使用修正后的宽度
+                width = layoutResult.total_width,
                 charLayouts = charLayouts,      // 存入缓存
                 charOriginalBounds = charBounds,
                 firstBaseline = layoutResult.firstBaseline
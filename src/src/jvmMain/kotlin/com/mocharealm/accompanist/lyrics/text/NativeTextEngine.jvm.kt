@@ -18,16 +18,49 @@ actual class NativeTextEngine {
     }
 
     actual external fun init(atlasWidth: Int, atlasHeight: Int)
-    actual external fun loadFont(bytes: ByteArray)
-    actual external fun loadFallbackFont(bytes: ByteArray)
+    actual external fun loadFont(bytes: ByteArray, faceIndex: Int)
+    actual external fun loadFallbackFont(bytes: ByteArray, faceIndex: Int)
+    actual external fun listFontFaces(bytes: ByteArray): String
     actual external fun clearFallbackFonts()
-    actual external fun processText(text: String, sizeFn: Float, weight: Float): String
+    actual external fun registerFont(bytes: ByteArray, faceIndex: Int): Int
+    actual external fun unregisterFont(fontId: Int): Boolean
+    actual external fun setFontStack(name: String, fontIds: IntArray)
+    actual external fun removeFontStack(name: String): Boolean
+    actual external fun setLocaleFallbacks(locale: String, fontIds: IntArray, stack: String): Boolean
+
+    // Fallback discovery among the installed fonts (platform-specific, not in expect)
+    /**
+     * Turns fallback discovery on or off (off by default). While on, characters that no
+     * font of the stack covers are looked up among the fonts in [fontDirs], and the fonts
+     * found are added to the stack's fallbacks.
+     *
+     * Turning it on scans [fontDirs] before returning (only files changed since the cached
+     * index was written are read again), so call it off the UI thread.
+     *
+     * @param fontDirs Directories to scan recursively; empty for the system and user font
+     *   directories of the platform
+     * @param cachePath File to keep the font index in between runs; empty for the
+     *   platform cache directory
+     */
+    external fun setFontDiscovery(enabled: Boolean, fontDirs: Array<String> = emptyArray(), cachePath: String = "")
+
+    actual external fun setDefaultLanguage(language: String)
+    actual external fun processText(text: String, sizeFn: Float, weight: Float, options: String): String
+    actual external fun checkCoverage(text: String, options: String, discover: Boolean): String
+    actual external fun processParagraph(text: String, sizePx: Float, maxWidth: Float, weight: Float, options: String): String
+    actual external fun processSyllables(text: String, sizePx: Float, boundaries: IntArray, weight: Float, options: String): String
+    actual external fun processRuby(text: String, sizePx: Float, rubySizePx: Float, annotations: String, weight: Float, options: String): String
+    actual external fun processTextOutlines(text: String, sizePx: Float, weight: Float, options: String): String
+    actual external fun getCaretPositions(text: String, sizePx: Float, weight: Float, options: String): String
+    actual external fun hitTest(text: String, sizePx: Float, x: Float, weight: Float, options: String): String
     actual external fun hasPendingUploads(): Boolean
     actual external fun getPendingUploads(): String
     actual external fun getAtlasSize(): String
+    actual external fun getLayoutCacheStats(): String
+    actual external fun setLayoutCacheCapacity(capacity: Int)
     
     // Zero-copy DirectByteBuffer API (platform-specific, not in expect)
-    external fun processTextDirect(text: String, sizePx: Float, weight: Float, buffer: ByteBuffer): Int
+    external fun processTextDirect(text: String, sizePx: Float, weight: Float, options: String, buffer: ByteBuffer): Int
     external fun getPendingUploadsDirect(buffer: ByteBuffer): Int
     
     // Resource management
@@ -0,0 +1,507 @@
+use crate::variation::AxisValue;
+use roxmltree::{Document, Node};
+use rustybuzz::ttf_parser::Tag;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Weight Android sets text in when nothing asks for another
+const DEFAULT_WEIGHT: u16 = 400;
+
+/// Design of a fallback family for scripts that come in two (e.g. tall "elegant" vs
+/// "compact" Arabic or Thai). Android uses compact unless `elegantTextHeight` is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Elegant,
+    Compact,
+}
+
+/// A `<font>` of a family: a file name relative to the font directories, plus its face.
+#[derive(Clone, Debug)]
+pub struct SystemFont {
+    pub file: String,
+    pub index: u32, // Face within a TTC/OTC collection
+    pub weight: u16,
+    pub italic: bool,
+    pub axes: Vec<AxisValue>, // `<axis tag stylevalue>` values the entry is defined at
+    pub fallback_for: Option<String>, // Named family this font stands in for, e.g. "serif"
+}
+
+/// A `<family>`. Named ones ("sans-serif", "serif", ...) set text; unnamed ones are the
+/// fallback chain, in file order.
+#[derive(Clone, Debug)]
+pub struct SystemFamily {
+    pub name: Option<String>,
+    pub langs: Vec<String>, // BCP-47 tags from `lang`, e.g. ["zh-Hant", "zh-Bopo"]
+    pub variant: Option<Variant>,
+    pub fonts: Vec<SystemFont>,
+}
+
+/// An `<alias>`: another name for a family, optionally at a fixed weight
+/// (e.g. "sans-serif-medium" → "sans-serif" at 500).
+#[derive(Clone, Debug)]
+pub struct SystemAlias {
+    pub name: String,
+    pub to: String,
+    pub weight: Option<u16>,
+}
+
+/// Contents of Android's `/system/etc/fonts.xml` (or `font_fallback.xml`).
+#[derive(Clone, Debug, Default)]
+pub struct FontsXml {
+    pub families: Vec<SystemFamily>,
+    pub aliases: Vec<SystemAlias>,
+}
+
+/// One face of one font file, resolved against the font directories, with the axis
+/// values its entry sets (e.g. `wdth` 75 for a condensed family)
+#[derive(Clone, Debug, PartialEq)]
+pub struct FontFile {
+    pub path: PathBuf,
+    pub index: u32,
+    pub axes: Vec<AxisValue>,
+}
+
+/// A stack built for a named family or alias: its own font, the fallback chain, and the
+/// fallbacks to try first per language tag.
+#[derive(Clone, Debug)]
+pub struct SystemStack {
+    pub name: String,
+    pub primary: FontFile,
+    pub fallbacks: Vec<FontFile>,
+    pub locale_fallbacks: Vec<(String, Vec<FontFile>)>,
+}
+
+impl FontsXml {
+    /// Parse the `familyset` document. Unknown elements and attributes are ignored;
+    /// a `family-list` counts as one family with the fonts of all the families in it.
+    pub fn parse(xml: &str) -> Result<Self, roxmltree::Error> {
+        let document = Document::parse(xml)?;
+        let mut config = FontsXml::default();
+        for node in document.root_element().children().filter(Node::is_element) {
+            match node.tag_name().name() {
+                "family" => config.families.push(parse_family(node, &[node])),
+                "family-list" => {
+                    let members: Vec<_> = node
+                        .children()
+                        .filter(|child| child.has_tag_name("family"))
+                        .collect();
+                    config.families.push(parse_family(node, &members));
+                }
+                "alias" => {
+                    if let (Some(name), Some(to)) = (node.attribute("name"), node.attribute("to")) {
+                        config.aliases.push(SystemAlias {
+                            name: name.to_string(),
+                            to: to.to_string(),
+                            weight: node.attribute("weight").and_then(|w| w.parse().ok()),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(config)
+    }
+
+    /// Build a stack for every named family and alias whose font exists in `font_dirs`
+    /// (tried in order for each file), in file order, so the first one is the system
+    /// default.
+    ///
+    /// Each stack gets the upright font nearest its weight from every fallback family of
+    /// the right `variant`, preferring fonts marked `fallbackFor` the stack's family over
+    /// unmarked ones. Fallback families with a `lang` also go into the locale chain of each
+    /// of their tags. Files that don't exist are skipped.
+    pub fn stacks(&self, variant: Variant, font_dirs: &[PathBuf]) -> Vec<SystemStack> {
+        let mut resolved: HashMap<String, Option<PathBuf>> = HashMap::new();
+        let mut resolve = |font: &SystemFont| {
+            resolved
+                .entry(font.file.clone())
+                .or_insert_with(|| {
+                    let path = font_dirs
+                        .iter()
+                        .map(|dir| dir.join(&font.file))
+                        .find(|path| path.is_file());
+                    if path.is_none() {
+                        debug!("System font {} not found", font.file);
+                    }
+                    path
+                })
+                .clone()
+                .map(|path| FontFile {
+                    path,
+                    index: font.index,
+                    axes: font.axes.clone(),
+                })
+        };
+
+        let named = self
+            .families
+            .iter()
+            .filter_map(|family| Some((family.name.as_deref()?, family, DEFAULT_WEIGHT)));
+        let aliased = self.aliases.iter().filter_map(|alias| {
+            let family = self
+                .families
+                .iter()
+                .find(|family| family.name.as_deref() == Some(alias.to.as_str()))?;
+            Some((
+                alias.name.as_str(),
+                family,
+                alias.weight.unwrap_or(DEFAULT_WEIGHT),
+            ))
+        });
+        let fallback_families: Vec<_> = self
+            .families
+            .iter()
+            .filter(|family| family.name.is_none())
+            .filter(|family| family.variant.is_none_or(|v| v == variant))
+            .collect();
+
+        let mut stacks = Vec::new();
+        for (name, family, weight) in named.chain(aliased) {
+            let own_fonts = family.fonts.iter().filter(|f| f.fallback_for.is_none());
+            let Some(primary) = pick(own_fonts, weight, &mut resolve) else {
+                continue;
+            };
+            let base_name = family.name.as_deref().unwrap_or(name);
+
+            let mut fallbacks = Vec::new();
+            let mut locale_fallbacks: Vec<(String, Vec<FontFile>)> = Vec::new();
+            for fallback in &fallback_families {
+                let for_family = fallback
+                    .fonts
+                    .iter()
+                    .filter(|f| f.fallback_for.as_deref() == Some(base_name));
+                let font = pick(for_family, weight, &mut resolve).or_else(|| {
+                    let general = fallback.fonts.iter().filter(|f| f.fallback_for.is_none());
+                    pick(general, weight, &mut resolve)
+                });
+                let Some(font) = font else {
+                    continue;
+                };
+
+                for lang in &fallback.langs {
+                    match locale_fallbacks.iter_mut().find(|(tag, _)| tag == lang) {
+                        Some((_, chain)) => chain.push(font.clone()),
+                        None => locale_fallbacks.push((lang.clone(), vec![font.clone()])),
+                    }
+                }
+                if !fallbacks.contains(&font) {
+                    fallbacks.push(font);
+                }
+            }
+
+            stacks.push(SystemStack {
+                name: name.to_string(),
+                primary,
+                fallbacks,
+                locale_fallbacks,
+            });
+        }
+        stacks
+    }
+}
+
+/// Read and parse a fonts.xml file, logging why it couldn't be
+pub fn load(path: &Path) -> Option<FontsXml> {
+    let xml = std::fs::read_to_string(path)
+        .map_err(|e| warn!("Failed to read {}: {}", path.display(), e))
+        .ok()?;
+    FontsXml::parse(&xml)
+        .map_err(|e| warn!("Failed to parse {}: {}", path.display(), e))
+        .ok()
+}
+
+fn parse_family(node: Node, members: &[Node]) -> SystemFamily {
+    let variant = match node.attribute("variant") {
+        Some("elegant") => Some(Variant::Elegant),
+        Some("compact") => Some(Variant::Compact),
+        _ => None,
+    };
+    let fonts = members
+        .iter()
+        .flat_map(|member| member.children())
+        .filter(|child| child.has_tag_name("font"))
+        .filter_map(parse_font)
+        .collect();
+    SystemFamily {
+        name: node.attribute("name").map(str::to_string),
+        langs: node
+            .attribute("lang")
+            .map(|langs| langs.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+        variant,
+        fonts,
+    }
+}
+
+fn parse_font(node: Node) -> Option<SystemFont> {
+    // The file name is the element's own text; `<axis>` children sit in between
+    let file: String = node
+        .children()
+        .filter(Node::is_text)
+        .filter_map(|text| text.text())
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if file.is_empty() {
+        return None;
+    }
+    let axes: Vec<AxisValue> = node
+        .children()
+        .filter(|child| child.has_tag_name("axis"))
+        .filter_map(|axis| {
+            let tag = Tag::from_bytes_lossy(axis.attribute("tag")?.as_bytes());
+            Some((tag, axis.attribute("stylevalue")?.parse().ok()?))
+        })
+        .collect();
+    let axis_weight = axes
+        .iter()
+        .find(|(tag, _)| tag == &Tag::from_bytes(b"wght"))
+        .map(|&(_, value)| value as u16);
+
+    Some(SystemFont {
+        index: node
+            .attribute("index")
+            .and_then(|i| i.parse().ok())
+            .unwrap_or(0),
+        weight: node
+            .attribute("weight")
+            .and_then(|w| w.parse().ok())
+            .or(axis_weight)
+            .unwrap_or(DEFAULT_WEIGHT),
+        italic: node.attribute("style") == Some("italic"),
+        fallback_for: node.attribute("fallbackFor").map(str::to_string),
+        file,
+        axes,
+    })
+}
+
+/// The upright font nearest `weight` among those whose file exists
+fn pick<'a>(
+    fonts: impl Iterator<Item = &'a SystemFont>,
+    weight: u16,
+    resolve: &mut impl FnMut(&SystemFont) -> Option<FontFile>,
+) -> Option<FontFile> {
+    fonts
+        .filter_map(|font| Some((font, resolve(font)?)))
+        .min_by_key(|(font, _)| (font.italic, font.weight.abs_diff(weight)))
+        .map(|(_, file)| file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONTS_XML: &str = include_str!("../tests/fixtures/fonts.xml");
+
+    fn tag(tag: &[u8; 4]) -> Tag {
+        Tag::from_bytes(tag)
+    }
+
+    /// Font directories with an empty stub for every file the fixture names, except
+    /// NotoColorEmoji.ttf and Missing.ttf. Roboto is in both, to check the first dir wins.
+    fn font_dirs() -> (tempfile::TempDir, Vec<PathBuf>) {
+        let root = tempfile::tempdir().unwrap();
+        let dirs = vec![root.path().join("system"), root.path().join("product")];
+        let files: [(usize, &str); 8] = [
+            (0, "Roboto-Regular.ttf"),
+            (0, "NotoSerif-Regular.ttf"),
+            (0, "NotoNaskhArabic-Regular.ttf"),
+            (0, "NotoSansArabicUI-Regular.ttf"),
+            (0, "NotoSansThai-Regular.ttf"),
+            (0, "NotoSansThai-Bold.ttf"),
+            (1, "NotoSansCJK-Regular.ttc"),
+            (1, "Roboto-Regular.ttf"),
+        ];
+        for (dir, file) in files {
+            std::fs::create_dir_all(&dirs[dir]).unwrap();
+            std::fs::write(dirs[dir].join(file), b"").unwrap();
+        }
+        (root, dirs)
+    }
+
+    #[test]
+    fn parses_families_and_aliases() {
+        let config = FontsXml::parse(FONTS_XML).unwrap();
+        let names: Vec<Option<&str>> = config.families.iter().map(|f| f.name.as_deref()).collect();
+        assert_eq!(
+            names,
+            [
+                Some("sans-serif"),
+                Some("sans-serif-condensed"),
+                Some("serif"),
+                Some("missing"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+
+        let aliases: Vec<(&str, &str, Option<u16>)> = config
+            .aliases
+            .iter()
+            .map(|a| (a.name.as_str(), a.to.as_str(), a.weight))
+            .collect();
+        assert_eq!(
+            aliases,
+            [
+                ("sans-serif-thin", "sans-serif", Some(100)),
+                ("sans-serif-bold", "sans-serif", Some(700)),
+                ("arial", "sans-serif", None),
+                ("dangling", "no-such-family", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_fonts() {
+        let config = FontsXml::parse(FONTS_XML).unwrap();
+        let sans = &config.families[0].fonts;
+        assert_eq!(sans.len(), 3);
+        // The file name is the text around the <axis> children, trimmed
+        assert!(sans.iter().all(|f| f.file == "Roboto-Regular.ttf"));
+        assert_eq!((sans[0].weight, sans[0].italic), (100, false));
+        assert_eq!(
+            sans[1].axes,
+            [
+                (tag(b"ital"), 0.0),
+                (tag(b"wdth"), 100.0),
+                (tag(b"wght"), 400.0)
+            ]
+        );
+        // No weight attribute: the wght axis gives it
+        assert_eq!((sans[2].weight, sans[2].italic), (700, true));
+
+        let arabic: Vec<Option<Variant>> =
+            config.families[4..6].iter().map(|f| f.variant).collect();
+        assert_eq!(arabic, [Some(Variant::Elegant), Some(Variant::Compact)]);
+
+        // A family-list is one family holding the fonts of all its members
+        let thai = &config.families[6];
+        assert_eq!(thai.langs, ["und-Thai"]);
+        let thai_fonts: Vec<(&str, u16)> = thai
+            .fonts
+            .iter()
+            .map(|f| (f.file.as_str(), f.weight))
+            .collect();
+        assert_eq!(
+            thai_fonts,
+            [
+                ("NotoSansThai-Regular.ttf", 400),
+                ("NotoSansThai-Bold.ttf", 700)
+            ]
+        );
+
+        let cjk = &config.families[7].fonts;
+        assert_eq!((cjk[0].index, cjk[0].fallback_for.as_deref()), (2, None));
+        assert_eq!(
+            (cjk[1].index, cjk[1].fallback_for.as_deref()),
+            (3, Some("serif"))
+        );
+        assert_eq!(config.families[8].langs, ["ja", "ja-Latn"]);
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        assert!(FontsXml::parse("<familyset><family>").is_err());
+    }
+
+    #[test]
+    fn builds_stacks() {
+        let (_root, dirs) = font_dirs();
+        let config = FontsXml::parse(FONTS_XML).unwrap();
+        let stacks = config.stacks(Variant::Compact, &dirs);
+        let file = |dir: usize, name: &str, index: u32, axes: &[(&[u8; 4], f32)]| FontFile {
+            path: dirs[dir].join(name),
+            index,
+            axes: axes.iter().map(|&(t, v)| (tag(t), v)).collect(),
+        };
+
+        // Named families in file order, then aliases; "missing" has no file and "dangling"
+        // no family
+        let names: Vec<&str> = stacks.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "sans-serif",
+                "sans-serif-condensed",
+                "serif",
+                "sans-serif-thin",
+                "sans-serif-bold",
+                "arial"
+            ]
+        );
+
+        let roboto = |wght: f32| {
+            file(
+                0,
+                "Roboto-Regular.ttf",
+                0,
+                &[(b"ital", 0.0), (b"wdth", 100.0), (b"wght", wght)],
+            )
+        };
+        let arabic_ui = file(0, "NotoSansArabicUI-Regular.ttf", 0, &[]);
+        let thai = file(0, "NotoSansThai-Regular.ttf", 0, &[]);
+        let thai_bold = file(0, "NotoSansThai-Bold.ttf", 0, &[]);
+        let cjk = |index| file(1, "NotoSansCJK-Regular.ttc", index, &[]);
+
+        let sans = &stacks[0];
+        assert_eq!(sans.primary, roboto(400.0));
+        // The elegant Arabic and the missing emoji font are left out
+        assert_eq!(
+            sans.fallbacks,
+            [arabic_ui.clone(), thai.clone(), cjk(2), cjk(0)]
+        );
+        assert_eq!(
+            sans.locale_fallbacks,
+            [
+                ("und-Arab".to_string(), vec![arabic_ui.clone()]),
+                ("und-Thai".to_string(), vec![thai.clone()]),
+                ("zh-Hans".to_string(), vec![cjk(2)]),
+                ("ja".to_string(), vec![cjk(0)]),
+                ("ja-Latn".to_string(), vec![cjk(0)]),
+            ]
+        );
+
+        assert_eq!(
+            stacks[1].primary,
+            file(0, "Roboto-Regular.ttf", 0, &[(b"wdth", 75.0)])
+        );
+        // Fonts marked fallbackFor="serif" win over the general ones in serif stacks
+        assert_eq!(stacks[2].primary, file(0, "NotoSerif-Regular.ttf", 0, &[]));
+        assert_eq!(stacks[2].fallbacks[2], cjk(3));
+        assert_eq!(stacks[2].locale_fallbacks[2].1, [cjk(3)]);
+        // Aliases pick the upright font nearest their weight, from fallbacks as well
+        assert_eq!(stacks[3].primary, roboto(100.0));
+        assert_eq!(stacks[4].primary, roboto(400.0));
+        assert_eq!(stacks[4].fallbacks[1], thai_bold);
+        assert_eq!(stacks[5].primary, sans.primary);
+        assert_eq!(stacks[5].fallbacks, sans.fallbacks);
+
+        let elegant = config.stacks(Variant::Elegant, &dirs);
+        assert_eq!(
+            elegant[0].fallbacks[0],
+            file(0, "NotoNaskhArabic-Regular.ttf", 0, &[])
+        );
+    }
+
+    #[test]
+    fn skips_stacks_without_files() {
+        let config = FontsXml::parse(FONTS_XML).unwrap();
+        let empty = tempfile::tempdir().unwrap();
+        assert!(config
+            .stacks(Variant::Compact, &[empty.path().to_path_buf()])
+            .is_empty());
+    }
+
+    #[test]
+    fn load_reports_unreadable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load(&dir.path().join("fonts.xml")).is_none());
+        let bad = dir.path().join("bad.xml");
+        std::fs::write(&bad, "<familyset>").unwrap();
+        assert!(load(&bad).is_none());
+    }
+}
@@ -1,6 +1,7 @@
+use crate::synthesis::Synthesis;
 use std::collections::HashMap;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Rect {
     pub x: u32,
     pub y: u32,
@@ -14,28 +15,25 @@ pub struct GlyphInfo {
     pub rect: Rect,
     pub x_bearing: f32,
     pub y_bearing: f32,
-    pub last_used: u64, // LRU timestamp
+    pub ink_bounds: [f32; 4], // left, top, right, bottom from the glyph origin, y down
+    pub last_used: u64,       // LRU timestamp
 }
 
-/// Cache key for glyphs including weight for variable font support
-pub type GlyphCacheKey = (usize, u16, u32, u32); // (font_id, glyph_id, size_px, weight)
+/// Cache key for glyphs including the variable font axes and synthesis they were rendered with
+pub type GlyphCacheKey = (usize, u16, u32, u32, Synthesis, bool); // (font_id, glyph_id, size_px, variation_id, synthesis, sideways)
 
 /// Block-based allocation unit
 #[derive(Clone, Copy, Debug)]
 struct Block {
-    x: u32,
-    y: u32,
     is_free: bool,
 }
 
 pub struct AtlasManager {
-    pub width: u32,
-    pub height: u32,
     block_size: u32,
     blocks_per_row: u32,
     blocks_per_col: u32,
     blocks: Vec<Block>,
-    // Mapping from (FontID, GlyphID, FontSize, Weight) -> GlyphInfo
+    // Mapping from (FontID, GlyphID, SizePx, VariationID, Synthesis, Sideways) -> GlyphInfo
     glyph_cache: HashMap<GlyphCacheKey, GlyphInfo>,
     // Reverse mapping: block index -> glyph key (for eviction)
     block_to_glyph: HashMap<usize, GlyphCacheKey>,
@@ -50,20 +48,9 @@ impl AtlasManager {
         let blocks_per_col = height / block_size;
         let total_blocks = (blocks_per_row * blocks_per_col) as usize;
 
-        let mut blocks = Vec::with_capacity(total_blocks);
-        for row in 0..blocks_per_col {
-            for col in 0..blocks_per_row {
-                blocks.push(Block {
-                    x: col * block_size,
-                    y: row * block_size,
-                    is_free: true,
-                });
-            }
-        }
+        let blocks = vec![Block { is_free: true }; total_blocks];
 
         Self {
-            width,
-            height,
             block_size,
             blocks_per_row,
             blocks_per_col,
@@ -74,15 +61,26 @@ impl AtlasManager {
         }
     }
 
-    /// Get glyph info with weight support and update LRU timestamp
-    pub fn get_glyph_info_with_weight(
+    /// Get glyph info for a set of variation coordinates (interned by `TextEngine`,
+    /// 0 = font defaults) and update LRU timestamp.
+    /// `sideways` selects the bitmap rotated for vertical lines.
+    pub fn get_glyph_info_with_variation(
         &mut self,
         font_id: usize,
         glyph_id: u16,
         size_px: u32,
-        weight: u32,
+        variation_id: u32,
+        synthesis: Synthesis,
+        sideways: bool,
     ) -> Option<GlyphInfo> {
-        let key = (font_id, glyph_id, size_px, weight);
+        let key = (
+            font_id,
+            glyph_id,
+            size_px,
+            variation_id,
+            synthesis,
+            sideways,
+        );
         if let Some(info) = self.glyph_cache.get_mut(&key) {
             self.access_counter += 1;
             info.last_used = self.access_counter;
@@ -92,7 +90,7 @@ impl AtlasManager {
         }
     }
 
-    /// Legacy method - uses default weight of 400
+    /// Legacy method - uses the font's default variation, unsynthesized
     #[allow(dead_code)]
     pub fn get_glyph_info(
         &mut self,
@@ -100,7 +98,14 @@ impl AtlasManager {
         glyph_id: u16,
         size_px: u32,
     ) -> Option<GlyphInfo> {
-        self.get_glyph_info_with_weight(font_id, glyph_id, size_px, 400)
+        self.get_glyph_info_with_variation(
+            font_id,
+            glyph_id,
+            size_px,
+            0,
+            Synthesis::default(),
+            false,
+        )
     }
 
     /// Legacy method for backward compatibility
@@ -113,8 +118,8 @@ impl AtlasManager {
     /// Allocate space for a glyph, evicting LRU glyphs if necessary
     pub fn allocate(&mut self, width: u32, height: u32) -> Option<Rect> {
         // Calculate how many blocks we need
-        let blocks_needed_x = (width + self.block_size - 1) / self.block_size;
-        let blocks_needed_y = (height + self.block_size - 1) / self.block_size;
+        let blocks_needed_x = width.div_ceil(self.block_size);
+        let blocks_needed_y = height.div_ceil(self.block_size);
 
         // Try to find contiguous free blocks
         if let Some(rect) = self.find_free_blocks(blocks_needed_x, blocks_needed_y) {
@@ -190,8 +195,8 @@ impl AtlasManager {
 
             if let Some(info) = self.glyph_cache.remove(&key) {
                 // Free the blocks used by this glyph
-                let blocks_x = (info.rect.width + self.block_size - 1) / self.block_size;
-                let blocks_y = (info.rect.height + self.block_size - 1) / self.block_size;
+                let blocks_x = info.rect.width.div_ceil(self.block_size);
+                let blocks_y = info.rect.height.div_ceil(self.block_size);
                 let start_col = info.rect.x / self.block_size;
                 let start_row = info.rect.y / self.block_size;
 
@@ -211,23 +216,33 @@ impl AtlasManager {
         }
     }
 
-    /// Cache glyph with weight support (for variable fonts)
-    pub fn cache_glyph_with_weight(
+    /// Cache glyph for a set of variation coordinates (for variable fonts)
+    #[allow(clippy::too_many_arguments)]
+    pub fn cache_glyph_with_variation(
         &mut self,
         font_id: usize,
         glyph_id: u16,
         size_px: u32,
-        weight: u32,
+        variation_id: u32,
+        synthesis: Synthesis,
+        sideways: bool,
         mut info: GlyphInfo,
     ) {
         self.access_counter += 1;
         info.last_used = self.access_counter;
 
-        let key = (font_id, glyph_id, size_px, weight);
+        let key = (
+            font_id,
+            glyph_id,
+            size_px,
+            variation_id,
+            synthesis,
+            sideways,
+        );
 
         // Store block -> glyph mapping for eviction
-        let blocks_x = (info.rect.width + self.block_size - 1) / self.block_size;
-        let blocks_y = (info.rect.height + self.block_size - 1) / self.block_size;
+        let blocks_x = info.rect.width.div_ceil(self.block_size);
+        let blocks_y = info.rect.height.div_ceil(self.block_size);
         let start_col = info.rect.x / self.block_size;
         let start_row = info.rect.y / self.block_size;
 
@@ -241,19 +256,17 @@ impl AtlasManager {
         self.glyph_cache.insert(key, info);
     }
 
-    /// Legacy method - uses default weight of 400
+    /// Legacy method - uses the font's default variation, unsynthesized
     #[allow(dead_code)]
     pub fn cache_glyph(&mut self, font_id: usize, glyph_id: u16, size_px: u32, info: GlyphInfo) {
-        self.cache_glyph_with_weight(font_id, glyph_id, size_px, 400, info);
-    }
-
-    /// Clear all cached data
-    pub fn clear(&mut self) {
-        for block in &mut self.blocks {
-            block.is_free = true;
-        }
-        self.glyph_cache.clear();
-        self.block_to_glyph.clear();
-        self.access_counter = 0;
+        self.cache_glyph_with_variation(
+            font_id,
+            glyph_id,
+            size_px,
+            0,
+            Synthesis::default(),
+            false,
+            info,
+        );
     }
 }
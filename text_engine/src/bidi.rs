@@ -0,0 +1,142 @@
+use serde::Deserialize;
+use unicode_bidi::{BidiInfo, Level};
+
+/// Base direction requested for a paragraph.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParagraphDirection {
+    /// Resolve from the first strong character (UAX #9 rules P2/P3), LTR if there is none
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+impl ParagraphDirection {
+    fn default_level(self) -> Option<Level> {
+        match self {
+            ParagraphDirection::Auto => None,
+            ParagraphDirection::Ltr => Some(Level::ltr()),
+            ParagraphDirection::Rtl => Some(Level::rtl()),
+        }
+    }
+}
+
+/// A maximal run of characters sharing one embedding level.
+#[derive(Clone, Copy, Debug)]
+pub struct BidiRun {
+    pub start: usize, // Char offset of the first char (inclusive)
+    pub end: usize,   // Char offset past the last char (exclusive)
+    pub level: u8,    // Embedding level; odd levels are RTL
+}
+
+impl BidiRun {
+    pub fn is_rtl(&self) -> bool {
+        self.level % 2 == 1
+    }
+}
+
+/// Split text into directional runs using the Unicode Bidirectional Algorithm.
+///
+/// Runs are returned in visual order (left to right) and each paragraph is
+/// reordered on its own, so a multi-line string keeps its line order.
+/// The second value is true when the (first) paragraph resolved to RTL.
+pub fn visual_runs(text: &str, direction: ParagraphDirection) -> (Vec<BidiRun>, bool) {
+    let bidi_info = BidiInfo::new(text, direction.default_level());
+
+    let base_rtl = bidi_info
+        .paragraphs
+        .first()
+        .map(|p| p.level.is_rtl())
+        .unwrap_or(false);
+
+    // unicode-bidi works in UTF-8 byte offsets, the engine in char offsets
+    let mut byte_to_char = vec![0usize; text.len() + 1];
+    let mut char_count = 0;
+    for (byte_idx, ch) in text.char_indices() {
+        for slot in &mut byte_to_char[byte_idx..byte_idx + ch.len_utf8()] {
+            *slot = char_count;
+        }
+        char_count += 1;
+    }
+    byte_to_char[text.len()] = char_count;
+
+    let mut runs = Vec::new();
+    for para in &bidi_info.paragraphs {
+        let (levels, level_runs) = bidi_info.visual_runs(para, para.range.clone());
+        for range in level_runs {
+            if range.is_empty() {
+                continue;
+            }
+            runs.push(BidiRun {
+                start: byte_to_char[range.start],
+                end: byte_to_char[range.end],
+                level: levels[range.start].number(),
+            });
+        }
+    }
+
+    (runs, base_rtl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (start, end, level) of each run
+    fn runs(text: &str, direction: ParagraphDirection) -> (Vec<(usize, usize, u8)>, bool) {
+        let (runs, rtl) = visual_runs(text, direction);
+        let runs = runs.iter().map(|r| (r.start, r.end, r.level)).collect();
+        (runs, rtl)
+    }
+
+    #[test]
+    fn resolves_the_direction_from_the_first_strong_char() {
+        assert_eq!(
+            runs("abc", ParagraphDirection::Auto),
+            (vec![(0, 3, 0)], false)
+        );
+        assert_eq!(
+            runs("אבג", ParagraphDirection::Auto),
+            (vec![(0, 3, 1)], true)
+        );
+        // Digits and spaces aren't strong, so the Hebrew decides
+        assert!(runs("12 אב", ParagraphDirection::Auto).1);
+        assert!(!runs("", ParagraphDirection::Auto).1);
+    }
+
+    #[test]
+    fn returns_runs_in_visual_order() {
+        // Offsets are chars: each Hebrew letter is two bytes
+        assert_eq!(
+            runs("ab אב cd", ParagraphDirection::Ltr),
+            (vec![(0, 3, 0), (3, 5, 1), (5, 8, 0)], false)
+        );
+        // An RTL paragraph puts the Latin run that comes last logically on the left
+        assert_eq!(
+            runs("אב cd", ParagraphDirection::Rtl),
+            (vec![(3, 5, 2), (0, 3, 1)], true)
+        );
+    }
+
+    #[test]
+    fn explicit_direction_overrides_the_text() {
+        assert_eq!(
+            runs("abc", ParagraphDirection::Rtl),
+            (vec![(0, 3, 2)], true)
+        );
+        assert_eq!(
+            runs("אב", ParagraphDirection::Ltr),
+            (vec![(0, 2, 1)], false)
+        );
+    }
+
+    #[test]
+    fn reorders_each_paragraph_on_its_own() {
+        // The second paragraph resolves to LTR and stays after the first
+        assert_eq!(
+            runs("אב\nab", ParagraphDirection::Auto),
+            (vec![(0, 3, 1), (3, 5, 0)], true)
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use crate::bidi;
+use crate::core::{LayoutOptions, TextEngine};
+use crate::grapheme;
+use crate::vertical::Orientation;
+use rustybuzz::ttf_parser::{Face, GlyphId, Tag};
+use serde::Serialize;
+
+/// Caret positions for every offset of a laid-out line.
+#[derive(Serialize, Default)]
+pub struct CaretResult {
+    pub carets: Vec<f32>, // Caret x for each UTF-16 offset from 0 to the text length (inclusive)
+    pub total_width: f32,
+    pub is_rtl: bool,
+}
+
+/// The caret offset closest to a point on a laid-out line.
+#[derive(Serialize, Default)]
+pub struct HitTestResult {
+    pub offset: u32, // Char offset of the caret
+    pub offset_utf16: u32,
+    pub caret_x: f32, // The edge of the hit grapheme the caret snapped to
+    pub is_rtl: bool, // Direction of the grapheme under x
+}
+
+/// The smallest caret stop: a grapheme, or one component of a ligature glyph.
+struct CaretSegment {
+    start: usize, // Char offset of the first char (inclusive)
+    end: usize,   // Char offset past the last char
+    left: f32,
+    right: f32,
+    rtl: bool,
+}
+
+impl CaretSegment {
+    /// x of the caret before the segment in logical order
+    fn leading(&self) -> f32 {
+        if self.rtl {
+            self.right
+        } else {
+            self.left
+        }
+    }
+
+    /// x of the caret after the segment in logical order
+    fn trailing(&self) -> f32 {
+        if self.rtl {
+            self.left
+        } else {
+            self.right
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+/// Index of a glyph in an OpenType coverage table.
+fn coverage_index(coverage: &[u8], glyph_id: u16) -> Option<usize> {
+    let count = read_u16(coverage, 2)? as usize;
+    match read_u16(coverage, 0)? {
+        1 => (0..count).find(|&i| read_u16(coverage, 4 + i * 2) == Some(glyph_id)),
+        2 => (0..count).find_map(|i| {
+            let record = 4 + i * 6;
+            let first = read_u16(coverage, record)?;
+            let last = read_u16(coverage, record + 2)?;
+            let start_index = read_u16(coverage, record + 4)?;
+            (first..=last)
+                .contains(&glyph_id)
+                .then(|| start_index as usize + (glyph_id - first) as usize)
+        }),
+        _ => None,
+    }
+}
+
+/// Caret positions inside a ligature glyph from the GDEF `LigCaretList`, in font units
+/// from the glyph origin, sorted left to right.
+///
+/// Only coordinate carets (formats 1 and 3, device tables ignored) are read; a glyph
+/// with contour-point carets returns None like one without carets.
+fn ligature_carets(face: &Face, glyph_id: u16) -> Option<Vec<i16>> {
+    gdef_ligature_carets(face.raw_face().table(Tag::from_bytes(b"GDEF"))?, glyph_id)
+}
+
+/// `ligature_carets` on the raw GDEF table
+fn gdef_ligature_carets(gdef: &[u8], glyph_id: u16) -> Option<Vec<i16>> {
+    let list_offset = read_u16(gdef, 8)? as usize;
+    if list_offset == 0 {
+        return None;
+    }
+    let list = gdef.get(list_offset..)?;
+
+    let coverage = list.get(read_u16(list, 0)? as usize..)?;
+    let index = coverage_index(coverage, glyph_id)?;
+    if index >= read_u16(list, 2)? as usize {
+        return None;
+    }
+    let lig_glyph = list.get(read_u16(list, 4 + index * 2)? as usize..)?;
+
+    let caret_count = read_u16(lig_glyph, 0)? as usize;
+    let mut carets = Vec::with_capacity(caret_count);
+    for i in 0..caret_count {
+        let caret = lig_glyph.get(read_u16(lig_glyph, 2 + i * 2)? as usize..)?;
+        match read_u16(caret, 0)? {
+            1 | 3 => carets.push(read_u16(caret, 2)? as i16),
+            _ => return None,
+        }
+    }
+    carets.sort_unstable();
+    Some(carets)
+}
+
+impl TextEngine {
+    /// Shape a line and cut it into caret segments (graphemes, with ligatures split at
+    /// their GDEF carets or evenly), in logical order.
+    fn caret_segments(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        options: &LayoutOptions,
+    ) -> (Vec<CaretSegment>, f32, bool) {
+        let options = &LayoutOptions {
+            orientation: Orientation::Horizontal,
+            ..options.clone()
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let char_count = chars.len();
+        let shaped = self.shape_text(text, size_px, weight, options);
+
+        // Extent of every cluster, indexed by the char offset it starts at, plus its
+        // glyphs with their pen positions for ligature carets
+        let mut cluster_extents: Vec<Option<(f32, f32)>> = vec![None; char_count];
+        let mut cluster_glyphs: Vec<Vec<(usize, f32)>> = vec![Vec::new(); char_count];
+        let mut x_cursor = 0.0f32;
+        for (i, glyph) in shaped.glyphs.iter().enumerate() {
+            let cluster = glyph.cluster as usize;
+            let (left, right) = cluster_extents[cluster].unwrap_or((x_cursor, x_cursor));
+            cluster_extents[cluster] =
+                Some((left.min(x_cursor), right.max(x_cursor + glyph.x_advance)));
+            cluster_glyphs[cluster].push((i, x_cursor));
+            x_cursor += glyph.x_advance;
+        }
+
+        let (bidi_runs, _) = bidi::visual_runs(text, options.direction);
+        let mut rtl = vec![false; char_count];
+        for run in &bidi_runs {
+            rtl[run.start..run.end].fill(run.is_rtl());
+        }
+
+        let mut grapheme_start = vec![false; char_count];
+        for (start, _) in grapheme::cluster_ranges(&chars) {
+            grapheme_start[start] = true;
+        }
+
+        let faces = self.font_faces(&shaped.fonts);
+        let cluster_starts: Vec<usize> = (0..char_count)
+            .filter(|&i| cluster_extents[i].is_some())
+            .collect();
+        let mut segments = Vec::with_capacity(char_count);
+        for (k, &cluster) in cluster_starts.iter().enumerate() {
+            let end = cluster_starts.get(k + 1).copied().unwrap_or(char_count);
+            let (left, right) = cluster_extents[cluster].unwrap_or_default();
+            // Chars before the first cluster (if any) join it
+            let start = if k == 0 { 0 } else { cluster };
+
+            let mut pieces: Vec<usize> = (start + 1..end).filter(|&i| grapheme_start[i]).collect();
+            pieces.insert(0, start);
+            pieces.push(end);
+            let piece_count = pieces.len() - 1;
+
+            // Interior boundaries left to right: the ligature's own carets when it has
+            // exactly one per boundary, otherwise an even split
+            let mut boundaries: Vec<f32> = (1..piece_count)
+                .map(|j| left + (right - left) * j as f32 / piece_count as f32)
+                .collect();
+            if let ([(glyph_idx, pen_x)], true) = (&cluster_glyphs[cluster][..], piece_count > 1) {
+                let glyph = &shaped.glyphs[*glyph_idx];
+                if let Some(face) = faces.get(glyph.font_index) {
+                    if let Some(carets) = ligature_carets(face, glyph.glyph_id)
+                        .filter(|carets| carets.len() == piece_count - 1)
+                    {
+                        // Synthetic bold and spacing widen the glyph past its font advance,
+                        // so the carets stretch with it
+                        let scale = size_px / face.units_per_em() as f32;
+                        let font_advance = face
+                            .glyph_hor_advance(GlyphId(glyph.glyph_id))
+                            .map_or(0.0, |advance| advance as f32 * scale);
+                        if font_advance > 0.0 {
+                            let scale = scale * glyph.x_advance / font_advance;
+                            let origin = pen_x + glyph.x_offset;
+                            boundaries =
+                                carets.iter().map(|&c| origin + c as f32 * scale).collect();
+                        }
+                    }
+                }
+            }
+
+            let mut edges = boundaries;
+            edges.insert(0, left);
+            edges.push(right);
+            for j in 0..piece_count {
+                // RTL components run right to left across the glyph
+                let slot = if rtl[start] { piece_count - 1 - j } else { j };
+                segments.push(CaretSegment {
+                    start: pieces[j],
+                    end: pieces[j + 1],
+                    left: edges[slot],
+                    right: edges[slot + 1],
+                    rtl: rtl[start],
+                });
+            }
+        }
+
+        (segments, x_cursor, shaped.is_rtl)
+    }
+
+    /// Caret x for every UTF-16 offset of a horizontal line, for mapping text offsets
+    /// to positions.
+    ///
+    /// A caret sits at the leading edge of the grapheme after it (the right edge in RTL
+    /// runs) and the end-of-text caret at the trailing edge of the last one. Offsets
+    /// inside a grapheme or surrogate pair snap to its start, while ligatures take
+    /// a caret between their components from GDEF when the font has one.
+    pub fn process_carets(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        options: &LayoutOptions,
+    ) -> CaretResult {
+        if !self.has_font(options) || text.is_empty() {
+            return CaretResult {
+                carets: vec![0.0],
+                ..Default::default()
+            };
+        }
+
+        let (segments, total_width, is_rtl) = self.caret_segments(text, size_px, weight, options);
+
+        let char_count = text.chars().count();
+        let mut char_carets = vec![0.0f32; char_count + 1];
+        for segment in &segments {
+            char_carets[segment.start..segment.end].fill(segment.leading());
+        }
+        if let Some(last) = segments.last() {
+            char_carets[char_count] = last.trailing();
+        }
+
+        let mut carets = Vec::with_capacity(text.len() + 1);
+        for (i, ch) in text.chars().enumerate() {
+            for _ in 0..ch.len_utf16() {
+                carets.push(char_carets[i]);
+            }
+        }
+        carets.push(char_carets[char_count]);
+
+        CaretResult {
+            carets,
+            total_width,
+            is_rtl,
+        }
+    }
+
+    /// Find the caret offset closest to `x` on a horizontal line, for mapping a tap
+    /// to a text offset.
+    ///
+    /// The grapheme (or ligature component) under `x` decides the offset: its logical
+    /// start or end, whichever edge is nearer. Points past either end of the line hit
+    /// the visually outermost grapheme.
+    pub fn hit_test(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        x: f32,
+        options: &LayoutOptions,
+    ) -> HitTestResult {
+        if !self.has_font(options) || text.is_empty() {
+            return HitTestResult::default();
+        }
+
+        let (segments, _, _) = self.caret_segments(text, size_px, weight, options);
+
+        // Segments with no width can't be hit
+        let hit = segments
+            .iter()
+            .filter(|s| s.right > s.left)
+            .min_by(|a, b| {
+                let distance = |s: &CaretSegment| (s.left - x).max(x - s.right).max(0.0);
+                distance(a).total_cmp(&distance(b))
+            })
+            .or(segments.first());
+        let Some(hit) = hit else {
+            return HitTestResult::default();
+        };
+
+        let on_left = x < (hit.left + hit.right) / 2.0;
+        let caret_x = if on_left { hit.left } else { hit.right };
+        // The left edge is the logical start of an LTR grapheme but the end of an RTL one
+        let offset = if on_left != hit.rtl {
+            hit.start
+        } else {
+            hit.end
+        };
+
+        let offset_utf16 = text
+            .chars()
+            .take(offset)
+            .map(|ch| ch.len_utf16() as u32)
+            .sum();
+        HitTestResult {
+            offset: offset as u32,
+            offset_utf16,
+            caret_x,
+            is_rtl: hit.rtl,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_font::TestFont;
+
+    fn bytes(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn coverage_format_1() {
+        let coverage = bytes(&[1, 3, 10, 20, 30]);
+        assert_eq!(coverage_index(&coverage, 10), Some(0));
+        assert_eq!(coverage_index(&coverage, 30), Some(2));
+        assert_eq!(coverage_index(&coverage, 25), None);
+    }
+
+    #[test]
+    fn coverage_format_2() {
+        // Ranges 100..=105 from index 0 and 60000..=60010 from index 6000
+        let coverage = bytes(&[2, 2, 100, 105, 0, 60000, 60010, 6000]);
+        assert_eq!(coverage_index(&coverage, 100), Some(0));
+        assert_eq!(coverage_index(&coverage, 103), Some(3));
+        // start_index + glyph_id doesn't fit in a u16
+        assert_eq!(coverage_index(&coverage, 60005), Some(6005));
+        assert_eq!(coverage_index(&coverage, 106), None);
+        assert_eq!(coverage_index(&bytes(&[3, 0]), 100), None);
+    }
+
+    /// GDEF 1.0 whose LigCaretList covers glyph 50 (coordinate carets at 300 and 150)
+    /// and glyph 70 (a contour-point caret)
+    fn gdef() -> Vec<u8> {
+        let header = [1, 0, 0, 0, 12, 0];
+        let list = [8, 2, 16, 30];
+        let coverage = [1, 2, 50, 70];
+        let lig_50 = [2, 6, 10, 1, 300, 1, 150];
+        let lig_70 = [1, 4, 2, 3];
+        bytes(&[&header[..], &list, &coverage, &lig_50, &lig_70].concat())
+    }
+
+    #[test]
+    fn ligature_carets_from_gdef() {
+        let gdef = gdef();
+        assert_eq!(gdef_ligature_carets(&gdef, 50), Some(vec![150, 300]));
+        assert_eq!(gdef_ligature_carets(&gdef, 70), None);
+        assert_eq!(gdef_ligature_carets(&gdef, 60), None);
+    }
+
+    #[test]
+    fn ligature_carets_without_list() {
+        assert_eq!(gdef_ligature_carets(&bytes(&[1, 0, 0, 0, 0, 0]), 50), None);
+        assert_eq!(gdef_ligature_carets(&[], 50), None);
+    }
+
+    /// An engine whose font has 500 unit wide Latin and Hebrew chars and an 800 unit
+    /// "fi" ligature with a GDEF caret at 300
+    fn engine() -> TextEngine {
+        let font = TestFont::new("Test")
+            .chars("fi abאבג", 500)
+            .ligature("fi", 800, &[300])
+            .build();
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(font, 0);
+        engine
+    }
+
+    fn carets(text: &str, size_px: f32, weight: f32) -> Vec<f32> {
+        engine()
+            .process_carets(text, size_px, weight, &LayoutOptions::default())
+            .carets
+    }
+
+    #[test]
+    fn orders_rtl_carets_right_to_left() {
+        assert_eq!(carets("אבג", 20.0, 400.0), [30.0, 20.0, 10.0, 0.0]);
+        // The Hebrew run sits right of "ab" and runs right to left inside it
+        assert_eq!(carets("abאב", 20.0, 400.0), [0.0, 10.0, 40.0, 30.0, 20.0]);
+    }
+
+    #[test]
+    fn splits_ligatures_at_their_gdef_carets() {
+        // "fi" is one 16 px glyph after a 10 px "a", with its caret 6 px in
+        assert_eq!(carets("afi", 20.0, 400.0), [0.0, 10.0, 16.0, 26.0]);
+        let hit = engine().hit_test("afi", 20.0, 400.0, 17.0, &LayoutOptions::default());
+        assert_eq!((hit.offset, hit.caret_x), (2, 16.0));
+    }
+
+    #[test]
+    fn stretches_ligature_carets_with_synthetic_bold() {
+        // At 48 px the regular glyphs are 24 px wide, plus 2 px each when emboldened
+        let carets = carets("afi", 48.0, 700.0);
+        let fi_width = 38.4 + 2.0;
+        let expected = [0.0, 26.0, 26.0 + 14.4 * fi_width / 38.4, 26.0 + fi_width];
+        for (caret, expected) in carets.iter().zip(expected) {
+            assert!((caret - expected).abs() < 1e-3, "{:?}", carets);
+        }
+    }
+}
@@ -1,22 +1,228 @@
-use crate::atlas::{AtlasManager, Rect};
-use crate::font::FontWrapper;
-use rustybuzz::{Face, UnicodeBuffer};
+use crate::android_fonts::{self, FontFile, Variant};
+use crate::atlas::{AtlasManager, GlyphCacheKey, GlyphInfo, Rect};
+use crate::bidi::{self, BidiRun, ParagraphDirection};
+use crate::emoji::{self, Presentation};
+use crate::font::{self, FontWrapper, GlyphOutline};
+use crate::font_discovery::FontDiscovery;
+use crate::grapheme;
+use crate::layout_cache::{
+    LayoutCache, LayoutCacheKey, LayoutCacheStats, DEFAULT_LAYOUT_CACHE_CAPACITY,
+};
+use crate::locale::Locale;
+use crate::metrics::FontMetrics;
+use crate::registry::{FontId, FontRegistry, StackFaces, DEFAULT_FONT_STACK};
+use crate::script;
+use crate::spacing::{self, Spacing};
+use crate::syllable::SyllableSpan;
+use crate::synthesis::Synthesis;
+use crate::variation::{self, AxisValue, FontStyle, Variations};
+use crate::vertical::{self, Orientation};
+use rustybuzz::{Direction, Face, Feature, Language, UnicodeBuffer};
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, Default)]
 pub struct LayoutResult {
     pub glyph_count: usize,
     // Flat arrays for JNI transfer
     pub glyph_ids: Vec<u16>,
-    pub positions: Vec<f32>,     // x, y interleaved (relative to baseline)
-    pub atlas_rects: Vec<f32>,   // u, v, w, h in atlas
+    pub positions: Vec<f32>, // x, y interleaved (relative to baseline; vertical: x from the column's left edge, y from its top)
+    pub atlas_rects: Vec<f32>, // u, v, w, h in atlas
     pub glyph_offsets: Vec<f32>, // x_offset, y_offset interleaved (bearing from glyph origin to bitmap top-left)
-    pub font_indices: Vec<u8>,   // Which font each glyph comes from (0 = primary, 1+ = fallback)
+    pub ink_bounds: Vec<f32>, // left, top, right, bottom of each glyph's outline from its position, y down
+    pub font_indices: Vec<u8>, // Which font of the stack each glyph comes from (0 = primary, 1+ = fallback)
+    pub clusters: Vec<u32>,    // Char offset of the cluster each glyph belongs to
+    pub clusters_utf16: Vec<u32>, // Same cluster as a UTF-16 code unit offset (for JVM strings)
     pub total_width: f32,
     pub total_height: f32,
     pub ascent: f32,
     pub descent: f32,
+    pub is_rtl: bool,      // Resolved base direction of the paragraph
+    pub is_vertical: bool, // Glyphs run down a column: total_width is the column width
+    #[serde(flatten)]
+    pub metrics: FontMetrics, // Cap height, x-height, decorations, typo/win metrics of the primary font
+    pub ink_top: f32, // Top of the glyphs' ink, in the frame of `positions` (y down)
+    pub ink_bottom: f32, // Bottom of the glyphs' ink; equal to ink_top when nothing is inked
+    #[serde(flatten)]
+    pub uncovered: UncoveredChars, // Chars no font of the stack has a glyph for (drawn as .notdef)
+}
+
+/// Characters no font of a stack can render, in text order.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct UncoveredChars {
+    pub uncovered_codepoints: Vec<u32>,
+    pub uncovered_offsets: Vec<u32>,       // Char offset of each one
+    pub uncovered_offsets_utf16: Vec<u32>, // Same offset in UTF-16 code units (for JVM strings)
+}
+
+impl UncoveredChars {
+    /// The chars of `text` at `offsets` (char offsets, ascending)
+    pub(crate) fn from_offsets(text: &str, offsets: &[usize]) -> Self {
+        let mut uncovered = Self::default();
+        let mut wanted = offsets.iter().peekable();
+        let mut utf16_offset = 0;
+        for (i, ch) in text.chars().enumerate() {
+            if wanted.next_if_eq(&&i).is_some() {
+                uncovered.uncovered_codepoints.push(ch as u32);
+                uncovered.uncovered_offsets.push(i as u32);
+                uncovered.uncovered_offsets_utf16.push(utf16_offset);
+            }
+            utf16_offset += ch.len_utf16() as u32;
+        }
+        uncovered
+    }
+
+    /// Append the chars of a layout of a substring that starts at `offset`/`offset_utf16`
+    pub(crate) fn append(&mut self, other: &Self, offset: u32, offset_utf16: u32) {
+        self.uncovered_codepoints
+            .extend(&other.uncovered_codepoints);
+        self.uncovered_offsets
+            .extend(other.uncovered_offsets.iter().map(|o| o + offset));
+        self.uncovered_offsets_utf16.extend(
+            other
+                .uncovered_offsets_utf16
+                .iter()
+                .map(|o| o + offset_utf16),
+        );
+    }
+}
+
+impl LayoutResult {
+    /// Grow the ink extent to also cover [top, bottom]; an empty range is ignored.
+    pub(crate) fn union_ink(&mut self, top: f32, bottom: f32) {
+        if bottom <= top {
+            return;
+        }
+        if self.ink_bottom <= self.ink_top {
+            (self.ink_top, self.ink_bottom) = (top, bottom);
+        } else {
+            self.ink_top = self.ink_top.min(top);
+            self.ink_bottom = self.ink_bottom.max(bottom);
+        }
+    }
+}
+
+/// Per-call layout options. Every field has a default, so callers on the
+/// JNI/C side may pass any subset as JSON (or nothing at all).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct LayoutOptions {
+    pub direction: ParagraphDirection,
+    /// BCP-47 language tag (e.g. "ja", "zh-Hant"), overrides the engine default
+    pub language: Option<String>,
+    /// OpenType features in HarfBuzz syntax, e.g. "-liga", "palt", "ss01", "kern[2:4]=0".
+    /// Ranges are char offsets into the text.
+    pub features: Vec<String>,
+    /// "vertical" lays the text out top to bottom in a single column
+    pub orientation: Orientation,
+    /// Extra space after every cluster, in px (number or "2px") or em ("0.05em"); may be negative
+    pub letter_spacing: Spacing,
+    /// Extra space after word separators (spaces), on top of any letter spacing
+    pub word_spacing: Spacing,
+    /// Variable font axis values by tag, e.g. {"wdth": 75, "slnt": -10}, for every font that
+    /// has the axis. Overrides the weight, the automatic `opsz` and the named instance.
+    pub variations: Variations,
+    /// Named instance from `fvar`, by name or PostScript name (e.g. "Condensed Bold")
+    pub instance: Option<String>,
+    /// "italic" or "oblique" slant the text, through font axes or synthetic oblique
+    pub style: FontStyle,
+    /// Named family stack to set the text in (see `TextEngine::set_font_stack`), else "default"
+    pub font_stack: Option<String>,
+    /// BCP-47 tag choosing the stack's locale fallback chain (see
+    /// `TextEngine::set_locale_fallbacks`) when it should differ from `language`
+    pub fallback_language: Option<String>,
+}
+
+impl LayoutOptions {
+    /// Parse options passed as a JSON object over JNI or the C API.
+    /// Empty or malformed input falls back to the defaults.
+    pub fn from_json(json: &str) -> Self {
+        if json.trim().is_empty() {
+            return Self::default();
+        }
+        serde_json::from_str(json).unwrap_or_else(|e| {
+            warn!("Invalid layout options {}: {}", json, e);
+            Self::default()
+        })
+    }
+
+    /// Parse `features` into rustybuzz features, skipping (and logging) invalid entries.
+    fn shaping_features(&self) -> Vec<Feature> {
+        self.features
+            .iter()
+            .filter_map(|f| match f.parse::<Feature>() {
+                Ok(feature) => Some(feature),
+                Err(e) => {
+                    warn!("Ignoring OpenType feature {:?}: {}", f, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Options for laying out the chars [start, end) of the text on their own: ranged
+    /// features are clipped to the range and shifted to count from its start, and dropped
+    /// when they miss it. Features without a range stay as they are.
+    pub(crate) fn for_range(&self, start: usize, end: usize) -> LayoutOptions {
+        let (start, end) = (start as u32, end as u32);
+        let features = self
+            .features
+            .iter()
+            .filter_map(|setting| {
+                // Invalid entries are left for `shaping_features` to report
+                let Ok(feature) = setting.parse::<Feature>() else {
+                    return Some(setting.clone());
+                };
+                if feature.start == 0 && feature.end == u32::MAX {
+                    return Some(setting.clone());
+                }
+                let (clipped_start, clipped_end) = (feature.start.max(start), feature.end.min(end));
+                (clipped_start < clipped_end).then(|| {
+                    format!(
+                        "{}[{}:{}]={}",
+                        feature.tag,
+                        clipped_start - start,
+                        clipped_end - start,
+                        feature.value
+                    )
+                })
+            })
+            .collect();
+        LayoutOptions {
+            features,
+            ..self.clone()
+        }
+    }
+}
+
+/// A glyph after shaping, before atlas lookup. Sizes are in pixels.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ShapedGlyph {
+    pub glyph_id: u16,
+    pub font_index: usize,
+    pub cluster: u32, // Char offset into the shaped text
+    pub x_advance: f32,
+    pub y_advance: f32, // Down the column in vertical layout, 0 otherwise
+    pub x_offset: f32,
+    pub y_offset: f32,
+    pub sideways: bool, // Rotated 90° clockwise within a vertical line
+}
+
+/// Output of the shaping stage: glyphs in visual order plus the line metrics.
+pub(crate) struct ShapedText {
+    pub glyphs: Vec<ShapedGlyph>,
+    pub ascent: f32,
+    pub descent: f32,
+    pub height: f32,
+    pub is_rtl: bool,
+    pub is_vertical: bool,
+    pub metrics: FontMetrics,
+    pub variations: Vec<Vec<AxisValue>>, // Axis values per font index, for rendering what was shaped
+    pub synthesis: Vec<Synthesis>,       // Faux bold/oblique per font index
+    pub fonts: Vec<FontId>,              // Registry ID of each font index (the stack used)
+    pub uncovered: Vec<usize>,           // Char offsets no font of the stack has a glyph for
 }
 
 #[derive(Clone)]
@@ -28,21 +234,32 @@ pub struct PendingUpload {
     pub data: Vec<u8>, // RGBA data
 }
 
-/// A run of consecutive characters that share the same font.
+/// A run of consecutive characters that share the same font, script and direction.
 struct TextRun {
     chars: Vec<char>,
+    start: usize,      // Char offset of the first char in the source text
     font_index: usize, // 0 = primary, 1+ = fallback
+    script: Option<unicode_script::Script>,
+    rtl: bool,
+    upright: bool, // Shaped top to bottom (vertical layout only)
 }
 
 pub struct TextEngine {
     atlas: AtlasManager,
-    // Primary font
-    font: Option<FontWrapper>,
-    font_data: Vec<u8>,
-    // Fallback fonts (system fonts, etc.)
-    fallback_fonts: Vec<FontWrapper>,
-    fallback_font_data: Vec<Vec<u8>>,
+    // Every loaded font by stable ID, and the family stacks built from them
+    fonts: FontRegistry,
     pending_uploads: Vec<PendingUpload>,
+    // BCP-47 tag used when a call doesn't pass its own language
+    default_language: Option<String>,
+    // Finished `process_text` layouts; cleared whenever fonts or the language change
+    layout_cache: LayoutCache,
+    // Fonts `load_system_fonts` registered, replaced when it's called again
+    system_fonts: Vec<FontId>,
+    // Finds fallbacks among the installed fonts for characters no stack font covers;
+    // off until `set_font_discovery` turns it on
+    discovery: Option<FontDiscovery>,
+    // Interned variation coordinates (tag, f32 bits) for atlas keys; 0 = font defaults
+    variation_ids: HashMap<Vec<(u32, u32)>, u32>,
     pub atlas_width: u32,
     pub atlas_height: u32,
 }
@@ -51,44 +268,56 @@ impl TextEngine {
     pub fn new(atlas_width: u32, atlas_height: u32) -> Self {
         Self {
             atlas: AtlasManager::new(atlas_width, atlas_height),
-            font: None,
-            font_data: Vec::new(),
-            fallback_fonts: Vec::new(),
-            fallback_font_data: Vec::new(),
+            fonts: FontRegistry::new(),
             pending_uploads: Vec::new(),
+            default_language: None,
+            layout_cache: LayoutCache::new(DEFAULT_LAYOUT_CACHE_CAPACITY),
+            system_fonts: Vec::new(),
+            discovery: None,
+            variation_ids: HashMap::new(),
             atlas_width,
             atlas_height,
         }
     }
 
-    pub fn load_font(&mut self, font_bytes: Vec<u8>) {
+    /// Load the primary font of the default stack, replacing the previous one.
+    /// `face_index` picks a face of a TTC/OTC collection (see `font::list_faces`);
+    /// use 0 for single fonts.
+    pub fn load_font(&mut self, font_bytes: Vec<u8>, face_index: u32) {
         // Init FontWrapper for primary font
-        info!("Loading PRIMARY font: {} bytes", font_bytes.len());
-        if let Some(wrapper) = FontWrapper::from_bytes(&font_bytes, 0) {
-            self.font = Some(wrapper);
-            info!("PRIMARY font loaded successfully");
-        } else {
-            warn!("ERROR: Failed to load primary font!");
-        }
-        self.font_data = font_bytes;
+        info!(
+            "Loading PRIMARY font: {} bytes, face {}",
+            font_bytes.len(),
+            face_index
+        );
+        let primary = match FontWrapper::from_bytes(&font_bytes, self.fonts.next_id(), face_index) {
+            Some(wrapper) => {
+                info!("PRIMARY font loaded successfully");
+                Some(self.fonts.register(wrapper, true))
+            }
+            None => {
+                warn!("ERROR: Failed to load primary font!");
+                None
+            }
+        };
+        self.fonts
+            .edit_stack(DEFAULT_FONT_STACK, |stack| stack.primary = primary);
+        self.layout_cache.clear();
     }
 
-    /// Load a fallback font (e.g., system font for missing glyphs)
-    pub fn load_fallback_font(&mut self, font_bytes: Vec<u8>) {
-        let font_id = self.fallback_fonts.len() + 1; // 0 is primary
+    /// Load a fallback font (e.g., system font for missing glyphs) at the end of the
+    /// default stack, from face `face_index` of a collection
+    pub fn load_fallback_font(&mut self, font_bytes: Vec<u8>, face_index: u32) {
+        let font_id = self.fonts.next_id();
         info!(
-            "Loading FALLBACK font #{}: {} bytes",
+            "Loading FALLBACK font #{}: {} bytes, face {}",
             font_id,
-            font_bytes.len()
+            font_bytes.len(),
+            face_index
         );
-        if let Some(wrapper) = FontWrapper::from_bytes(&font_bytes, font_id) {
-            self.fallback_fonts.push(wrapper);
-            self.fallback_font_data.push(font_bytes);
-            info!(
-                "FALLBACK font #{} loaded, total fallbacks: {}",
-                font_id,
-                self.fallback_fonts.len()
-            );
+        if let Some(wrapper) = FontWrapper::from_bytes(&font_bytes, font_id, face_index) {
+            self.add_default_fallback(wrapper);
+            info!("FALLBACK font #{} loaded", font_id);
         } else {
             warn!("ERROR: Failed to load fallback font #{}!", font_id);
         }
@@ -97,51 +326,25 @@ impl TextEngine {
     /// Load a fallback font from a file descriptor using memory mapping.
     /// This is more memory-efficient as it doesn't copy the entire font into RAM.
     /// The fd is duplicated internally so the caller can close it after this call.
+    /// `face_index` picks a face of a TTC/OTC collection, 0 for single fonts.
     #[cfg(unix)]
-    pub fn load_fallback_font_from_fd(&mut self, fd: i32) -> bool {
-        use memmap2::Mmap;
-        use std::os::unix::io::FromRawFd;
-
-        let font_id = self.fallback_fonts.len() + 1;
+    pub fn load_fallback_font_from_fd(&mut self, fd: i32, face_index: u32) -> bool {
+        let font_id = self.fonts.next_id();
         #[cfg(debug_assertions)]
         eprintln!(
-            "[TextEngine] load_fallback_font_from_fd #{}: fd={}",
-            font_id, fd
+            "[TextEngine] load_fallback_font_from_fd #{}: fd={} face={}",
+            font_id, fd, face_index
         );
 
-        // Duplicate the FD so we own it
-        let dup_fd = unsafe { libc::dup(fd) };
-        if dup_fd < 0 {
-            #[cfg(debug_assertions)]
-            eprintln!("[TextEngine] Failed to dup fd!");
+        let Some(mmap) = font::map_fd(fd) else {
             return false;
-        }
-
-        // Create a File from the duplicated FD
-        let file = unsafe { std::fs::File::from_raw_fd(dup_fd) };
-
-        // Memory-map the file
-        let mmap = match unsafe { Mmap::map(&file) } {
-            Ok(m) => m,
-            Err(e) => {
-                #[cfg(debug_assertions)]
-                eprintln!("[TextEngine] Failed to mmap: {:?}", e);
-                return false;
-            }
         };
 
         // Create FontWrapper from mmap
-        if let Some(wrapper) = FontWrapper::from_mmap(mmap, font_id) {
-            // We need to keep the font data reference for rustybuzz shaping
-            // Since FontWrapper now owns the mmap, we store an empty vec as placeholder
-            self.fallback_font_data.push(Vec::new());
-            self.fallback_fonts.push(wrapper);
+        if let Some(wrapper) = FontWrapper::from_mmap(mmap, font_id, face_index) {
+            self.add_default_fallback(wrapper);
             #[cfg(debug_assertions)]
-            eprintln!(
-                "[TextEngine] Fallback font #{} loaded via mmap, total: {}",
-                font_id,
-                self.fallback_fonts.len()
-            );
+            eprintln!("[TextEngine] Fallback font #{} loaded via mmap", font_id);
             true
         } else {
             #[cfg(debug_assertions)]
@@ -150,10 +353,219 @@ impl TextEngine {
         }
     }
 
-    /// Clear all fallback fonts
+    fn add_default_fallback(&mut self, wrapper: FontWrapper) {
+        let id = self.fonts.register(wrapper, true);
+        self.fonts
+            .edit_stack(DEFAULT_FONT_STACK, |stack| stack.fallbacks.push(id));
+        self.layout_cache.clear();
+    }
+
+    /// Clear all fallback fonts of the default stack
     pub fn clear_fallback_fonts(&mut self) {
-        self.fallback_fonts.clear();
-        self.fallback_font_data.clear();
+        self.fonts
+            .edit_stack(DEFAULT_FONT_STACK, |stack| stack.fallbacks.clear());
+        self.layout_cache.clear();
+    }
+
+    /// Register a font for use in stacks, returning its stable ID, or None if it doesn't
+    /// parse. `face_index` picks a face of a TTC/OTC collection.
+    pub fn register_font(&mut self, font_bytes: Vec<u8>, face_index: u32) -> Option<FontId> {
+        let wrapper = FontWrapper::from_bytes(&font_bytes, self.fonts.next_id(), face_index)?;
+        let id = self.fonts.register(wrapper, false);
+        info!("Registered font #{}: {} bytes", id, font_bytes.len());
+        Some(id)
+    }
+
+    /// Register a font from a file descriptor without copying it, see `register_font`
+    #[cfg(unix)]
+    pub fn register_font_from_fd(&mut self, fd: i32, face_index: u32) -> Option<FontId> {
+        let mmap = font::map_fd(fd)?;
+        let wrapper = FontWrapper::from_mmap(mmap, self.fonts.next_id(), face_index)?;
+        let id = self.fonts.register(wrapper, false);
+        info!("Registered font #{} from fd {}", id, fd);
+        Some(id)
+    }
+
+    /// Remove a registered font from the engine and from every stack that uses it
+    pub fn unregister_font(&mut self, id: FontId) -> bool {
+        let removed = self.fonts.unregister(id);
+        if removed {
+            self.layout_cache.clear();
+        }
+        removed
+    }
+
+    /// Create or replace a named family stack: the first font is the primary one and the
+    /// rest are its fallbacks, in order. Layouts pick a stack with `LayoutOptions::font_stack`;
+    /// "default" is the stack the `load_*` calls build.
+    pub fn set_font_stack(&mut self, name: &str, fonts: &[FontId]) {
+        info!("Font stack {:?}: {:?}", name, fonts);
+        self.fonts.set_stack(name, fonts);
+        self.layout_cache.clear();
+    }
+
+    /// Remove a named family stack; its fonts stay registered
+    pub fn remove_font_stack(&mut self, name: &str) -> bool {
+        let removed = self.fonts.remove_stack(name);
+        if removed {
+            self.layout_cache.clear();
+        }
+        removed
+    }
+
+    /// Build font stacks from an Android font config (`/system/etc/fonts.xml`, or
+    /// `font_fallback.xml` on Android 15+), looking for the files in `font_dirs` in order.
+    ///
+    /// Every named family and alias ("sans-serif", "serif", "sans-serif-condensed", ...)
+    /// becomes a stack of that name with the system fallback chain and a locale chain for
+    /// each `lang` the fallback families declare, so e.g. Japanese lines take Han from the
+    /// Japanese face of Noto Sans CJK. The default stack gets the first family's fallbacks
+    /// and locale chains after its own, and that family's font if it has no primary.
+    ///
+    /// Files are registered by path and only memory-mapped once a layout needs them.
+    /// Calling this again replaces the fonts of the previous call. Returns the number of
+    /// stacks built, 0 if the config can't be read.
+    pub fn load_system_fonts(&mut self, config_path: &Path, font_dirs: &[PathBuf]) -> usize {
+        let Some(config) = android_fonts::load(config_path) else {
+            return 0;
+        };
+        let stacks = config.stacks(Variant::Compact, font_dirs);
+
+        for id in std::mem::take(&mut self.system_fonts) {
+            self.fonts.unregister(id);
+        }
+        let mut registered: Vec<(FontFile, FontId)> = Vec::new();
+        let mut register = |file: &FontFile| match registered.iter().find(|(f, _)| f == file) {
+            Some(&(_, id)) => id,
+            None => {
+                let id = self
+                    .fonts
+                    .register_path(file.path.clone(), file.index, file.axes.clone());
+                registered.push((file.clone(), id));
+                id
+            }
+        };
+
+        let mut built = Vec::with_capacity(stacks.len());
+        for stack in &stacks {
+            let fonts: Vec<FontId> = std::iter::once(&stack.primary)
+                .chain(&stack.fallbacks)
+                .map(&mut register)
+                .collect();
+            let locale_fallbacks: Vec<(&str, Vec<FontId>)> = stack
+                .locale_fallbacks
+                .iter()
+                .map(|(tag, files)| (tag.as_str(), files.iter().map(&mut register).collect()))
+                .collect();
+            built.push((stack.name.as_str(), fonts, locale_fallbacks));
+        }
+        self.system_fonts = registered.into_iter().map(|(_, id)| id).collect();
+
+        for (name, fonts, locale_fallbacks) in &built {
+            self.fonts.set_stack(name, fonts);
+            for (tag, chain) in locale_fallbacks {
+                self.fonts.set_locale_fallbacks(name, tag, chain);
+            }
+        }
+        if let Some((_, fonts, locale_fallbacks)) = built.first() {
+            self.fonts.edit_stack(DEFAULT_FONT_STACK, |stack| {
+                stack.primary.get_or_insert(fonts[0]);
+                stack.fallbacks.extend_from_slice(&fonts[1..]);
+                for (tag, chain) in locale_fallbacks {
+                    stack.extend_locale_fallbacks(tag, chain);
+                }
+            });
+        }
+        self.layout_cache.clear();
+
+        info!(
+            "Loaded {} system font stacks ({} fonts) from {}",
+            built.len(),
+            self.system_fonts.len(),
+            config_path.display()
+        );
+        built.len()
+    }
+
+    /// Turn fallback discovery on (with `Some`) or off. While on, characters no font of the
+    /// stack covers are looked up in the discovery's font index, and the faces found are
+    /// appended to the stack's fallbacks. Off by default.
+    ///
+    /// Build the index with `FontDiscovery::build_index` before passing it in (off the
+    /// engine lock); otherwise the first missing character builds it in the middle of a
+    /// layout. Fonts found earlier stay in their stacks when discovery is turned off.
+    pub fn set_font_discovery(&mut self, discovery: Option<FontDiscovery>) {
+        self.discovery = discovery;
+        // Cached layouts may have missing characters discovery would now find fonts for
+        self.layout_cache.clear();
+    }
+
+    /// Fonts of the stack `options` selects, primary first; empty when it has no primary.
+    /// Unknown stack names fall back to the default stack. The stack's locale chain for the
+    /// line's language (`fallback_language`, else the shaping language) comes before its
+    /// general fallbacks.
+    pub(crate) fn stack_fonts(&self, options: &LayoutOptions) -> Vec<FontId> {
+        let stack = self.fonts.stack(self.stack_name(options));
+        let locale = options
+            .fallback_language
+            .as_deref()
+            .or(options.language.as_deref())
+            .or(self.default_language.as_deref())
+            .and_then(Locale::parse);
+        stack
+            .map(|stack| stack.fonts_for(locale.as_ref()))
+            .unwrap_or_default()
+    }
+
+    /// Name of the stack `options` selects: its `font_stack` if that exists, else the default
+    fn stack_name<'a>(&self, options: &'a LayoutOptions) -> &'a str {
+        match options.font_stack.as_deref() {
+            Some(name) if self.fonts.stack(name).is_some() => name,
+            Some(name) => {
+                warn!("Unknown font stack {:?}, using the default", name);
+                DEFAULT_FONT_STACK
+            }
+            None => DEFAULT_FONT_STACK,
+        }
+    }
+
+    /// Set the fallback chain a stack uses for text in a language or script, tried before
+    /// its general fallbacks, e.g. "ja" → Noto Sans JP, "zh-Hant" → Noto Sans TC. Tags may
+    /// name a language ("ja"), language and script or region ("zh-Hant", "zh-HK") or just a
+    /// script ("und-Hant" or "Hant"); the most specific matching rule wins, and "zh-TW",
+    /// "zh-HK" and "zh-MO" count as Traditional Chinese. An empty chain removes the rule.
+    /// Returns false if the tag doesn't parse.
+    pub fn set_locale_fallbacks(&mut self, stack: &str, tag: &str, fonts: &[FontId]) -> bool {
+        info!(
+            "Locale fallbacks {:?} of stack {:?}: {:?}",
+            tag, stack, fonts
+        );
+        let set = self.fonts.set_locale_fallbacks(stack, tag, fonts);
+        if set {
+            self.layout_cache.clear();
+        } else {
+            warn!("Ignoring locale fallbacks for invalid tag {:?}", tag);
+        }
+        set
+    }
+
+    /// Set the BCP-47 language tag used for shaping when a call doesn't specify one.
+    /// Selects locale-specific glyph forms (`locl`), e.g. Japanese vs Chinese Han glyphs.
+    pub fn set_default_language(&mut self, language: Option<String>) {
+        info!("Default language: {:?}", language);
+        self.default_language = language;
+        self.layout_cache.clear();
+    }
+
+    /// Resolve the shaping language from the per-call tag or the engine default.
+    /// Accepts POSIX-style separators ("ja_JP") as Android's `Locale.toString()` produces them.
+    fn resolve_language(&self, options: &LayoutOptions) -> Option<Language> {
+        options
+            .language
+            .as_deref()
+            .or(self.default_language.as_deref())
+            .map(|tag| tag.trim().replace('_', "-"))
+            .and_then(|tag| tag.parse::<Language>().ok())
     }
 
     pub fn get_pending_uploads(&mut self) -> Vec<PendingUpload> {
@@ -164,6 +576,11 @@ impl TextEngine {
         !self.pending_uploads.is_empty()
     }
 
+    /// Whether the font stack `options` selects has a primary font
+    pub fn has_font(&self, options: &LayoutOptions) -> bool {
+        !self.stack_fonts(options).is_empty()
+    }
+
     pub fn get_atlas_size(&self) -> (u32, u32) {
         (self.atlas_width, self.atlas_height)
     }
@@ -172,68 +589,302 @@ impl TextEngine {
     /// Call this when switching fonts or to free memory.
     pub fn clear(&mut self) {
         self.atlas = AtlasManager::new(self.atlas_width, self.atlas_height);
-        self.font = None;
-        self.font_data = Vec::new();
-        self.fallback_fonts.clear();
-        self.fallback_font_data.clear();
+        self.fonts.clear();
+        self.system_fonts.clear();
+        if let Some(discovery) = &mut self.discovery {
+            discovery.reset();
+        }
         self.pending_uploads.clear();
+        self.layout_cache.clear();
+        self.variation_ids.clear();
     }
 
-    pub fn process_text(&mut self, text: &str, size_px: f32, weight: f32) -> LayoutResult {
-        let empty_result = LayoutResult {
-            glyph_count: 0,
-            glyph_ids: vec![],
-            positions: vec![],
-            atlas_rects: vec![],
-            glyph_offsets: vec![],
-            font_indices: vec![],
-            total_width: 0.0,
-            total_height: 0.0,
-            ascent: 0.0,
-            descent: 0.0,
-        };
+    /// Hit/miss counters and size of the layout cache
+    pub fn layout_cache_stats(&self) -> LayoutCacheStats {
+        self.layout_cache.stats()
+    }
+
+    /// Change how many layouts `process_text` and `process_syllables` keep (0 turns the
+    /// cache off)
+    pub fn set_layout_cache_capacity(&mut self, capacity: usize) {
+        self.layout_cache.set_capacity(capacity);
+    }
 
-        if self.font_data.is_empty() {
-            return empty_result;
+    pub fn process_text(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        options: &LayoutOptions,
+    ) -> LayoutResult {
+        if !self.has_font(options) || text.is_empty() {
+            return LayoutResult::default();
         }
 
+        let key = LayoutCacheKey::new(text, size_px, weight, options);
+        if let Some((result, _)) = self.cached_layout(&key) {
+            return result;
+        }
+
+        let shaped = self.shape_text(text, size_px, weight, options);
+        let result = self.build_layout(text, &shaped, size_px);
+        self.cache_layout(key, &shaped, size_px, &result, Vec::new());
+        result
+    }
+
+    /// A cached layout and its syllables, if every glyph is still where it was in the atlas
+    pub(crate) fn cached_layout(
+        &mut self,
+        key: &LayoutCacheKey,
+    ) -> Option<(LayoutResult, Vec<SyllableSpan>)> {
+        let atlas = &mut self.atlas;
+        self.layout_cache.get(
+            key,
+            |&(font, glyph, size, variation_id, synthesis, sideways), rect| {
+                atlas
+                    .get_glyph_info_with_variation(
+                        font,
+                        glyph,
+                        size,
+                        variation_id,
+                        synthesis,
+                        sideways,
+                    )
+                    .is_some_and(|info| info.rect == *rect)
+            },
+        )
+    }
+
+    /// Cache a layout built by `build_layout` from `shaped` at `size_px`, with the atlas entry of
+    /// each glyph so `cached_layout` can tell when one was evicted.
+    pub(crate) fn cache_layout(
+        &mut self,
+        key: LayoutCacheKey,
+        shaped: &ShapedText,
+        size_px: f32,
+        result: &LayoutResult,
+        syllables: Vec<SyllableSpan>,
+    ) {
+        let variation_ids: Vec<u32> = shaped
+            .variations
+            .iter()
+            .map(|values| self.variation_id(values))
+            .collect();
+        let glyphs: Vec<(GlyphCacheKey, Rect)> = shaped
+            .glyphs
+            .iter()
+            .zip(result.atlas_rects.chunks(4))
+            .map(|(glyph, rect)| {
+                let key = (
+                    shaped.fonts[glyph.font_index],
+                    glyph.glyph_id,
+                    size_px as u32,
+                    variation_ids[glyph.font_index],
+                    shaped.synthesis[glyph.font_index],
+                    glyph.sideways,
+                );
+                let rect = Rect {
+                    x: rect[0] as u32,
+                    y: rect[1] as u32,
+                    width: rect[2] as u32,
+                    height: rect[3] as u32,
+                };
+                (key, rect)
+            })
+            .collect();
+        // A glyph that didn't make it into the atlas (full, or failed to render) would stay
+        // missing from a cached copy, so such layouts are redone next time
+        if glyphs.iter().all(|(_, rect)| rect.width > 0) {
+            self.layout_cache
+                .insert(key, result.clone(), syllables, glyphs);
+        }
+    }
+
+    /// Shape text into positioned glyphs (visual order) without touching the atlas.
+    /// When some characters have no font in the stack and font discovery is on, fallbacks
+    /// for them are looked up among the system fonts, added to the stack and the text is
+    /// shaped again.
+    pub(crate) fn shape_text(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        options: &LayoutOptions,
+    ) -> ShapedText {
+        let shaped = self.shape_with_stack(text, size_px, weight, options);
+        if shaped.uncovered.is_empty() || self.discovery.is_none() {
+            return shaped;
+        }
         let text_chars: Vec<char> = text.chars().collect();
-        if text_chars.is_empty() {
-            return empty_result;
+        let missing: Vec<char> = shaped.uncovered.iter().map(|&i| text_chars[i]).collect();
+        if self.discover_fallbacks(&missing, options) {
+            self.shape_with_stack(text, size_px, weight, options)
+        } else {
+            shaped
+        }
+    }
+
+    /// Which chars of `text` the stack `options` selects can't render, without laying it
+    /// out, so the app can load a fallback or draw the line some other way.
+    ///
+    /// Only looks at the stack as it is, unless `discover` is set and font discovery is on:
+    /// then fonts found for the missing chars are added to the stack first, as a layout
+    /// would, and the layout cache is cleared if any were.
+    pub fn check_coverage(
+        &mut self,
+        text: &str,
+        options: &LayoutOptions,
+        discover: bool,
+    ) -> UncoveredChars {
+        let chars: Vec<char> = text.chars().collect();
+        let uncovered = |engine: &Self| {
+            let faces = engine.font_faces(&engine.stack_fonts(options));
+            Self::assign_fonts_to_chars(&chars, &faces).1
+        };
+
+        let mut offsets = uncovered(self);
+        if discover && !offsets.is_empty() {
+            let missing: Vec<char> = offsets.iter().map(|&i| chars[i]).collect();
+            if self.discover_fallbacks(&missing, options) {
+                offsets = uncovered(self);
+            }
+        }
+        UncoveredChars::from_offsets(text, &offsets)
+    }
+
+    /// Add system fonts covering `chars` to the fallbacks of the stack `options` selects.
+    /// Returns whether the stack changed.
+    fn discover_fallbacks(&mut self, chars: &[char], options: &LayoutOptions) -> bool {
+        let Some(discovery) = self.discovery.as_mut() else {
+            return false;
+        };
+        let found = discovery.fallbacks_for(chars, &mut self.fonts);
+
+        let name = self.stack_name(options).to_string();
+        let mut added = false;
+        self.fonts.edit_stack(&name, |stack| {
+            for id in found {
+                if stack.primary != Some(id) && !stack.fallbacks.contains(&id) {
+                    stack.fallbacks.push(id);
+                    added = true;
+                }
+            }
+        });
+        if added {
+            self.layout_cache.clear();
         }
+        added
+    }
+
+    fn shape_with_stack(
+        &self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        options: &LayoutOptions,
+    ) -> ShapedText {
+        let text_chars: Vec<char> = text.chars().collect();
 
-        // Quantize weight to reduce cache fragmentation (round to nearest 100)
-        let weight_key = ((weight / 100.0).round() * 100.0) as u32;
+        // Locale-specific forms and feature substitutions come out as different glyph IDs,
+        // so the atlas keys (font, glyph, size, variation) already keep them apart.
+        let language = self.resolve_language(options);
+        let features = options.shaping_features();
 
         info!("========= PROCESSING TEXT =========");
         info!("Input: \"{}\" ({} chars)", text, text_chars.len());
+        let fonts = self.stack_fonts(options);
+        let faces = self.font_faces(&fonts);
         info!(
             "Font tower: 1 primary + {} fallbacks",
-            self.fallback_fonts.len()
+            fonts.len().saturating_sub(1)
         );
 
+        // ===========================================
+        // Phase 0: Split into directional runs (UAX #9)
+        // ===========================================
+        let (bidi_runs, is_rtl) = bidi::visual_runs(text, options.direction);
+
         // ===========================================
         // Phase 1: Assign each character to a font
         // ===========================================
-        let font_assignments = self.assign_fonts_to_chars(&text_chars);
+        let (font_assignments, uncovered) = Self::assign_fonts_to_chars(&text_chars, &faces);
 
         // ===========================================
-        // Phase 2: Group into runs and shape each
+        // Phase 2: Itemize by script, group into runs (visual order) and shape each
         // ===========================================
-        let runs = Self::group_into_runs(&text_chars, &font_assignments);
+        let scripts = script::resolve_scripts(&text_chars);
+        let is_vertical = options.orientation == Orientation::Vertical;
+        let upright = if is_vertical {
+            Self::resolve_upright(&text_chars, &font_assignments, &faces)
+        } else {
+            vec![false; text_chars.len()]
+        };
+        let runs = Self::group_into_runs(
+            &text_chars,
+            &font_assignments,
+            &scripts,
+            &upright,
+            &bidi_runs,
+        );
 
         info!("Grouped into {} runs", runs.len());
 
-        let mut all_glyph_ids: Vec<u16> = Vec::new();
-        let mut all_positions: Vec<f32> = Vec::new();
-        let mut all_atlas_rects: Vec<f32> = Vec::new();
-        let mut all_glyph_offsets: Vec<f32> = Vec::new();
-        let mut all_font_indices: Vec<u8> = Vec::new();
+        // Axis values and synthesis for the primary font and every font a run uses; the
+        // same values go to the rasterizer through `ShapedText`
+        let mut variations: Vec<Vec<AxisValue>> = vec![Vec::new(); fonts.len()];
+        let mut synthesis = vec![Synthesis::default(); fonts.len()];
+        let mut resolved = vec![false; fonts.len()];
+        for font_idx in std::iter::once(0).chain(runs.iter().map(|run| run.font_index)) {
+            if font_idx >= fonts.len() || resolved[font_idx] {
+                continue;
+            }
+            resolved[font_idx] = true;
+            let Some(wrapper) = self.fonts.get(fonts[font_idx]) else {
+                continue;
+            };
+            let (data, face_index) = (&wrapper.font_data[..], wrapper.face_index);
+            if let Some(font) = swash::FontRef::from_index(data, face_index as usize) {
+                variations[font_idx] = variation::resolve(
+                    font,
+                    weight,
+                    size_px,
+                    options.style,
+                    options.instance.as_deref(),
+                    &options.variations,
+                    &wrapper.default_axes,
+                );
+            }
+            if let Some(face) = Face::from_slice(data, face_index) {
+                synthesis[font_idx] =
+                    Synthesis::resolve(&face, weight, options.style, &variations[font_idx]);
+            }
+        }
 
-        let mut x_cursor: f32 = 0.0;
-        let mut max_ascent: f32 = 0.0;
-        let mut max_descent: f32 = 0.0;
-        let mut max_height: f32 = 0.0;
+        // Decoration and x-height metrics come from the primary font even where fallback
+        // fonts render the text, like CSS's "first available font"
+        let metrics = fonts
+            .first()
+            .and_then(|&primary| self.fonts.source(primary))
+            .and_then(|(data, face_index)| Face::from_slice(data, face_index))
+            .map(|mut face| {
+                set_face_variations(&mut face, &variations[0]);
+                FontMetrics::from_face(&face, size_px)
+            })
+            .unwrap_or_default();
+
+        let mut shaped = ShapedText {
+            glyphs: Vec::new(),
+            ascent: 0.0,
+            descent: 0.0,
+            height: 0.0,
+            is_rtl,
+            is_vertical,
+            metrics,
+            variations: Vec::new(),
+            synthesis: Vec::new(),
+            fonts: Vec::new(),
+            uncovered,
+        };
 
         for run in runs {
             let run_text: String = run.chars.iter().collect();
@@ -244,252 +895,803 @@ impl TextEngine {
             } else {
                 format!("FALLBACK#{}", font_idx)
             };
-            info!("Run: font={} text=\"{}\"", font_name, run_text);
+            info!(
+                "Run: font={} script={:?} rtl={} upright={} text=\"{}\"",
+                font_name, run.script, run.rtl, run.upright, run_text
+            );
 
             // Get font data for this run
-            let font_data_ref: &[u8] = if font_idx == 0 {
-                &self.font_data
-            } else {
-                let fb_idx = font_idx - 1;
-                if fb_idx < self.fallback_fonts.len() {
-                    &self.fallback_fonts[fb_idx].font_data
-                } else {
-                    continue;
-                }
+            let Some((font_data_ref, face_index)) =
+                fonts.get(font_idx).and_then(|&id| self.fonts.source(id))
+            else {
+                continue;
             };
 
             // Create Face for shaping
-            let mut face = match Face::from_slice(font_data_ref, 0) {
+            let mut face = match Face::from_slice(font_data_ref, face_index) {
                 Some(f) => f,
                 None => continue,
             };
 
-            // Set font weight variation
-            face.set_variations(&[rustybuzz::Variation {
-                tag: rustybuzz::ttf_parser::Tag::from_bytes(b"wght"),
-                value: weight,
-            }]);
+            // Set variable font axes
+            set_face_variations(&mut face, &variations[font_idx]);
 
-            // Shape the run with its own font
+            // Shape the run with its own font.
+            // Clusters are char offsets into the whole text rather than UTF-8 byte offsets.
+            // RTL runs come back from rustybuzz in visual order, so x_cursor still advances left to right.
             let mut buffer = UnicodeBuffer::new();
-            buffer.push_str(&run_text);
-            let glyph_buffer = rustybuzz::shape(&face, &[], buffer);
+            for (i, &ch) in run.chars.iter().enumerate() {
+                buffer.add(ch, (run.start + i) as u32);
+            }
+            // Upright runs in a vertical line are shaped top to bottom with vertical
+            // metrics; sideways runs are shaped horizontally and rotated afterwards.
+            buffer.set_direction(if run.upright {
+                Direction::TopToBottom
+            } else if run.rtl {
+                Direction::RightToLeft
+            } else {
+                Direction::LeftToRight
+            });
+            if let Some(run_script) = run.script.and_then(script::to_shaping_script) {
+                buffer.set_script(run_script);
+            }
+            if let Some(ref lang) = language {
+                buffer.set_language(lang.clone());
+            }
+            let glyph_buffer = if run.upright {
+                // `vert` is on for vertical buffers already; `vrt2` also picks up rotated forms.
+                // User features come after it so they can still turn it off.
+                let mut vertical_features = vec![Feature::new(
+                    rustybuzz::ttf_parser::Tag::from_bytes(b"vrt2"),
+                    1,
+                    ..,
+                )];
+                vertical_features.extend_from_slice(&features);
+                rustybuzz::shape(&face, &vertical_features, buffer)
+            } else {
+                rustybuzz::shape(&face, &features, buffer)
+            };
             let glyph_infos = glyph_buffer.glyph_infos();
             let glyph_positions = glyph_buffer.glyph_positions();
 
             let units_per_em = face.units_per_em() as f32;
             let scale = size_px / units_per_em;
+            // Emboldened glyphs grow by this much, so advancing glyphs move over to make room
+            let embolden_px = synthesis[font_idx].embolden_px(size_px);
+            let extra_advance = |advance: i32| {
+                if advance != 0 {
+                    embolden_px
+                } else {
+                    0.0
+                }
+            };
 
             // Update max metrics
             let run_ascent = face.ascender() as f32 * scale;
             let run_descent = face.descender() as f32 * scale;
             let run_height = face.height() as f32 * scale;
-            if run_ascent > max_ascent {
-                max_ascent = run_ascent;
+            if run_ascent > shaped.ascent {
+                shaped.ascent = run_ascent;
             }
-            if run_descent.abs() > max_descent.abs() {
-                max_descent = run_descent;
+            if run_descent.abs() > shaped.descent.abs() {
+                shaped.descent = run_descent;
             }
-            if run_height > max_height {
-                max_height = run_height;
+            if run_height > shaped.height {
+                shaped.height = run_height;
             }
 
             for (info, gp) in glyph_infos.iter().zip(glyph_positions.iter()) {
-                let glyph_id = info.glyph_id as u16;
-
-                let glyph_info = if let Some(cached) = self.atlas.get_glyph_info_with_weight(
-                    font_idx,
-                    glyph_id,
-                    size_px as u32,
-                    weight_key,
-                ) {
-                    cached
+                let glyph = ShapedGlyph {
+                    glyph_id: info.glyph_id as u16,
+                    font_index: font_idx,
+                    cluster: info.cluster,
+                    x_advance: gp.x_advance as f32 * scale + extra_advance(gp.x_advance),
+                    y_advance: 0.0,
+                    x_offset: gp.x_offset as f32 * scale,
+                    y_offset: gp.y_offset as f32 * scale,
+                    sideways: false,
+                };
+                shaped.glyphs.push(if !is_vertical {
+                    glyph
+                } else if run.upright {
+                    // rustybuzz works y-up with offsets from the pen on the column's
+                    // center line to the glyph's horizontal origin
+                    ShapedGlyph {
+                        x_advance: 0.0,
+                        y_advance: -gp.y_advance as f32 * scale + extra_advance(gp.y_advance),
+                        y_offset: -glyph.y_offset,
+                        ..glyph
+                    }
                 } else {
-                    let sdf_result = if font_idx == 0 {
-                        self.font
-                            .as_mut()
-                            .map(|f| f.generate_sdf(glyph_id, size_px, weight))
-                    } else {
-                        self.fallback_fonts
-                            .get_mut(font_idx - 1)
-                            .map(|f| f.generate_sdf(glyph_id, size_px, weight))
-                    };
-
-                    if let Some((bitmap, w, h, xmin, ymin)) = sdf_result {
-                        if w > 0 && h > 0 {
-                            if let Some(alloc_rect) = self.atlas.allocate(w, h) {
-                                self.pending_uploads.push(PendingUpload {
-                                    x: alloc_rect.x,
-                                    y: alloc_rect.y,
-                                    width: w,
-                                    height: h,
-                                    data: bitmap,
-                                });
-                                let info = crate::atlas::GlyphInfo {
-                                    rect: alloc_rect,
-                                    x_bearing: xmin,
-                                    y_bearing: ymin,
-                                    last_used: 0, // Will be set by cache_glyph_with_weight
-                                };
-                                self.atlas.cache_glyph_with_weight(
-                                    font_idx,
-                                    glyph_id,
-                                    size_px as u32,
-                                    weight_key,
-                                    info,
-                                );
-                                info
-                            } else {
-                                crate::atlas::GlyphInfo {
-                                    rect: Rect {
-                                        x: 0,
-                                        y: 0,
-                                        width: 0,
-                                        height: 0,
-                                    },
-                                    x_bearing: 0.0,
-                                    y_bearing: 0.0,
-                                    last_used: 0,
-                                }
-                            }
-                        } else {
-                            crate::atlas::GlyphInfo {
-                                rect: Rect {
-                                    x: 0,
-                                    y: 0,
-                                    width: 0,
-                                    height: 0,
-                                },
-                                x_bearing: xmin,
-                                y_bearing: ymin,
-                                last_used: 0,
-                            }
-                        }
-                    } else {
-                        crate::atlas::GlyphInfo {
-                            rect: Rect {
-                                x: 0,
-                                y: 0,
-                                width: 0,
-                                height: 0,
-                            },
-                            x_bearing: 0.0,
-                            y_bearing: 0.0,
-                            last_used: 0,
-                        }
+                    // Turned clockwise: the glyph's x runs down the column and its
+                    // em box is centered on the column's center line
+                    ShapedGlyph {
+                        x_advance: 0.0,
+                        y_advance: glyph.x_advance,
+                        x_offset: glyph.y_offset - (run_ascent + run_descent) / 2.0,
+                        y_offset: glyph.x_offset,
+                        sideways: true,
+                        ..glyph
                     }
-                };
+                });
+            }
+        }
 
-                all_glyph_ids.push(glyph_id);
-                all_font_indices.push(font_idx as u8);
+        // Tracking goes on after shaping so it never splits a cluster
+        spacing::apply(
+            &mut shaped.glyphs,
+            &text_chars,
+            &scripts,
+            options.letter_spacing.to_px(size_px),
+            options.word_spacing.to_px(size_px),
+            is_vertical,
+        );
 
-                let x_pos = x_cursor + (gp.x_offset as f32 * scale);
-                let y_pos = gp.y_offset as f32 * scale;
-                all_positions.push(x_pos);
-                all_positions.push(y_pos);
+        shaped.variations = variations;
+        shaped.synthesis = synthesis;
+        shaped.fonts = fonts;
+        shaped
+    }
 
-                all_glyph_offsets.push(glyph_info.x_bearing);
-                all_glyph_offsets.push(glyph_info.y_bearing);
+    /// Turn shaped glyphs into a `LayoutResult`, rasterizing any glyph missing from the atlas.
+    pub(crate) fn build_layout(
+        &mut self,
+        text: &str,
+        shaped: &ShapedText,
+        size_px: f32,
+    ) -> LayoutResult {
+        // Char offset -> UTF-16 offset, with one extra entry for the end of text
+        let mut utf16_offsets: Vec<u32> = Vec::with_capacity(text.len() + 1);
+        let mut utf16_cursor: u32 = 0;
+        for ch in text.chars() {
+            utf16_offsets.push(utf16_cursor);
+            utf16_cursor += ch.len_utf16() as u32;
+        }
+        utf16_offsets.push(utf16_cursor);
 
-                x_cursor += gp.x_advance as f32 * scale;
+        let variation_ids: Vec<u32> = shaped
+            .variations
+            .iter()
+            .map(|values| self.variation_id(values))
+            .collect();
 
-                all_atlas_rects.push(glyph_info.rect.x as f32);
-                all_atlas_rects.push(glyph_info.rect.y as f32);
-                all_atlas_rects.push(glyph_info.rect.width as f32);
-                all_atlas_rects.push(glyph_info.rect.height as f32);
-            }
+        let glyph_count = shaped.glyphs.len();
+        let mut result = LayoutResult {
+            glyph_count,
+            glyph_ids: Vec::with_capacity(glyph_count),
+            positions: Vec::with_capacity(glyph_count * 2),
+            atlas_rects: Vec::with_capacity(glyph_count * 4),
+            glyph_offsets: Vec::with_capacity(glyph_count * 2),
+            ink_bounds: Vec::with_capacity(glyph_count * 4),
+            font_indices: Vec::with_capacity(glyph_count),
+            clusters: Vec::with_capacity(glyph_count),
+            clusters_utf16: Vec::with_capacity(glyph_count),
+            total_width: 0.0,
+            total_height: shaped.height,
+            ascent: shaped.ascent,
+            descent: shaped.descent,
+            is_rtl: shaped.is_rtl,
+            is_vertical: shaped.is_vertical,
+            metrics: shaped.metrics,
+            ink_top: 0.0,
+            ink_bottom: 0.0,
+            uncovered: UncoveredChars::from_offsets(text, &shaped.uncovered),
+        };
+
+        // A vertical column is one line height wide, with the pen on its center line
+        let mut x_cursor: f32 = if shaped.is_vertical {
+            shaped.height / 2.0
+        } else {
+            0.0
+        };
+        let mut y_cursor: f32 = 0.0;
+
+        for glyph in &shaped.glyphs {
+            let glyph_info = self.get_or_rasterize_glyph(
+                shaped.fonts[glyph.font_index],
+                glyph.glyph_id,
+                size_px,
+                &shaped.variations[glyph.font_index],
+                variation_ids[glyph.font_index],
+                shaped.synthesis[glyph.font_index],
+                glyph.sideways,
+            );
+
+            result.glyph_ids.push(glyph.glyph_id);
+            result.font_indices.push(glyph.font_index as u8);
+            result.clusters.push(glyph.cluster);
+            result
+                .clusters_utf16
+                .push(utf16_offsets[glyph.cluster as usize]);
+
+            let x_pos = x_cursor + glyph.x_offset;
+            let y_pos = y_cursor + glyph.y_offset;
+            result.positions.push(x_pos);
+            result.positions.push(y_pos);
+
+            result.glyph_offsets.push(glyph_info.x_bearing);
+            result.glyph_offsets.push(glyph_info.y_bearing);
+            result.ink_bounds.extend(glyph_info.ink_bounds);
+            let [_, ink_top, _, ink_bottom] = glyph_info.ink_bounds;
+            result.union_ink(y_pos + ink_top, y_pos + ink_bottom);
+
+            x_cursor += glyph.x_advance;
+            y_cursor += glyph.y_advance;
+
+            result.atlas_rects.push(glyph_info.rect.x as f32);
+            result.atlas_rects.push(glyph_info.rect.y as f32);
+            result.atlas_rects.push(glyph_info.rect.width as f32);
+            result.atlas_rects.push(glyph_info.rect.height as f32);
         }
 
-        LayoutResult {
-            glyph_count: all_glyph_ids.len(),
-            glyph_ids: all_glyph_ids,
-            positions: all_positions,
-            atlas_rects: all_atlas_rects,
-            glyph_offsets: all_glyph_offsets,
-            font_indices: all_font_indices,
-            total_width: x_cursor,
-            total_height: max_height,
-            ascent: max_ascent,
-            descent: max_descent,
-        }
-    }
-
-    /// Assign each character to a font based on glyph coverage.
-    fn assign_fonts_to_chars(&self, chars: &[char]) -> Vec<usize> {
-        let mut assignments = Vec::with_capacity(chars.len());
-        let primary_face = Face::from_slice(&self.font_data, 0);
-        let mut missing_chars: Vec<char> = Vec::new();
-
-        for &ch in chars {
-            // Try primary font first
-            if let Some(ref face) = primary_face {
-                if let Some(gid) = face.glyph_index(ch) {
-                    if gid.0 != 0 {
-                        assignments.push(0);
-                        continue;
-                    }
+        if shaped.is_vertical {
+            result.total_width = shaped.height;
+            result.total_height = y_cursor;
+        } else {
+            result.total_width = x_cursor;
+        }
+        result
+    }
+
+    /// Look up a glyph in the atlas, generating and uploading its SDF on a miss.
+    /// Sideways glyphs are cached separately with the bitmap turned 90° clockwise.
+    /// Glyphs that can't be rendered or placed get an empty rect.
+    /// `variation_id` is the interned id of `variations`, see `variation_id`.
+    #[allow(clippy::too_many_arguments)]
+    fn get_or_rasterize_glyph(
+        &mut self,
+        font_id: FontId,
+        glyph_id: u16,
+        size_px: f32,
+        variations: &[AxisValue],
+        variation_id: u32,
+        synthesis: Synthesis,
+        sideways: bool,
+    ) -> GlyphInfo {
+        if let Some(cached) = self.atlas.get_glyph_info_with_variation(
+            font_id,
+            glyph_id,
+            size_px as u32,
+            variation_id,
+            synthesis,
+            sideways,
+        ) {
+            return cached;
+        }
+
+        // Ink bounds come from the unhinted outline, turned like the bitmap when sideways
+        let ink_bounds = match self
+            .glyph_outline(font_id, glyph_id, size_px, variations, synthesis)
+            .and_then(|outline| outline.ink_bounds())
+        {
+            Some((xmin, ymin, xmax, ymax)) if sideways => [ymin, xmin, ymax, xmax],
+            Some((xmin, ymin, xmax, ymax)) => [xmin, -ymax, xmax, -ymin],
+            None => [0.0; 4],
+        };
+
+        let empty_rect = Rect {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        };
+
+        let sdf_result = self
+            .fonts
+            .get_mut(font_id)
+            .map(|f| f.generate_sdf(glyph_id, size_px, variations, synthesis));
+
+        let sdf_result = if sideways {
+            sdf_result.map(vertical::rotate_bitmap_clockwise)
+        } else {
+            sdf_result
+        };
+
+        let Some((bitmap, w, h, xmin, ymin)) = sdf_result else {
+            return GlyphInfo {
+                rect: empty_rect,
+                x_bearing: 0.0,
+                y_bearing: 0.0,
+                ink_bounds,
+                last_used: 0,
+            };
+        };
+
+        if w == 0 || h == 0 {
+            return GlyphInfo {
+                rect: empty_rect,
+                x_bearing: xmin,
+                y_bearing: ymin,
+                ink_bounds,
+                last_used: 0,
+            };
+        }
+
+        let Some(alloc_rect) = self.atlas.allocate(w, h) else {
+            return GlyphInfo {
+                rect: empty_rect,
+                x_bearing: 0.0,
+                y_bearing: 0.0,
+                ink_bounds,
+                last_used: 0,
+            };
+        };
+
+        self.pending_uploads.push(PendingUpload {
+            x: alloc_rect.x,
+            y: alloc_rect.y,
+            width: w,
+            height: h,
+            data: bitmap,
+        });
+        let info = GlyphInfo {
+            rect: alloc_rect,
+            x_bearing: xmin,
+            y_bearing: ymin,
+            ink_bounds,
+            last_used: 0, // Will be set by cache_glyph_with_variation
+        };
+        self.atlas.cache_glyph_with_variation(
+            font_id,
+            glyph_id,
+            size_px as u32,
+            variation_id,
+            synthesis,
+            sideways,
+            info,
+        );
+        info
+    }
+
+    /// Vector outline of a glyph from the font tower, see `FontWrapper::glyph_outline`.
+    pub(crate) fn glyph_outline(
+        &mut self,
+        font_id: FontId,
+        glyph_id: u16,
+        size_px: f32,
+        variations: &[AxisValue],
+        synthesis: Synthesis,
+    ) -> Option<GlyphOutline> {
+        self.fonts
+            .get_mut(font_id)?
+            .glyph_outline(glyph_id, size_px, variations, synthesis)
+    }
+
+    /// Small id for a set of axis values, so atlas keys stay `Copy`. Equal coordinates
+    /// always get the same id (until `clear`), and a font's defaults are 0.
+    fn variation_id(&mut self, variations: &[AxisValue]) -> u32 {
+        if variations.is_empty() {
+            return 0;
+        }
+        let coords: Vec<(u32, u32)> = variations
+            .iter()
+            .map(|&(tag, value)| (tag.0, value.to_bits()))
+            .collect();
+        let next_id = self.variation_ids.len() as u32 + 1;
+        *self.variation_ids.entry(coords).or_insert(next_id)
+    }
+
+    /// Faces of a stack's fonts, indexed like `font_index` (0 = primary, 1+ = fallback),
+    /// opened on first use. Fonts that fail to load or parse yield `None`.
+    pub(crate) fn font_faces(&self, fonts: &[FontId]) -> StackFaces<'_> {
+        self.fonts.faces(fonts)
+    }
+
+    /// Assign characters to fonts one grapheme cluster (UAX #29) at a time, so that
+    /// combining marks, ZWJ sequences, flags and conjuncts never straddle two fonts.
+    ///
+    /// A cluster goes to the first font (primary, then the locale chain for the line's
+    /// language, then the other fallbacks, see `stack_fonts`) that maps every codepoint
+    /// in it, default-ignorables aside. When no font covers the whole cluster, it goes to
+    /// the font covering the most codepoints, preferring one that has the base character,
+    /// with earlier fonts winning ties. A cluster no font covers at all stays on the
+    /// primary font and renders as .notdef.
+    ///
+    /// Emoji presentation (VS16, keycaps, Emoji_Presentation=Yes) prefers color fonts
+    /// and text presentation (VS15, or emoji like © that default to text) prefers the
+    /// others, so a primary font with a monochrome U+2764 doesn't shadow a loaded emoji
+    /// font. A font of the other kind only gets the cluster when no preferred font covers
+    /// it. Selectors belong to their base's cluster and always stay on the same font.
+    ///
+    /// Faces are opened as the search reaches them, so a long fallback chain is only
+    /// loaded as far as the text needs: up to the first preferred font that covers a
+    /// cluster, or all of it for a cluster that no preferred font covers.
+    ///
+    /// Also returns the offsets of the chars no font has a glyph for, in order.
+    fn assign_fonts_to_chars(chars: &[char], faces: &StackFaces) -> (Vec<usize>, Vec<usize>) {
+        let covers = |face: &Face, ch: char| face.glyph_index(ch).is_some_and(|gid| gid.0 != 0);
+        let is_color = |idx: usize| {
+            faces
+                .get(idx)
+                .is_some_and(|face| emoji::is_color_font(face))
+        };
+
+        let mut assignments = vec![0usize; chars.len()];
+        let mut missing: Vec<usize> = Vec::new();
+
+        for (start, end) in grapheme::cluster_ranges(chars) {
+            let required: Vec<char> = chars[start..end]
+                .iter()
+                .copied()
+                .filter(|&ch| !grapheme::is_default_ignorable(ch))
+                .collect();
+
+            // Walk the fonts in load order, keeping the first one of the wrong kind for
+            // this presentation in case no font of the right kind covers the cluster
+            let presentation = emoji::cluster_presentation(&chars[start..end]);
+            let mut full_match = None;
+            let mut other_kind = None;
+            for idx in 0..faces.len() {
+                let Some(face) = faces.get(idx) else {
+                    continue;
+                };
+                if !required.iter().all(|&ch| covers(face, ch)) {
+                    continue;
+                }
+                let preferred = match presentation {
+                    Presentation::Emoji => emoji::is_color_font(face),
+                    Presentation::Text => !emoji::is_color_font(face),
+                    Presentation::Any => true,
+                };
+                if preferred {
+                    full_match = Some(idx);
+                    break;
                 }
+                other_kind.get_or_insert(idx);
             }
 
-            // Try fallback fonts
-            let mut assigned = 0usize;
-            for (fb_idx, fb_wrapper) in self.fallback_fonts.iter().enumerate() {
-                if let Some(fb_face) = Face::from_slice(&fb_wrapper.font_data, 0) {
-                    if let Some(gid) = fb_face.glyph_index(ch) {
-                        if gid.0 != 0 {
-                            assigned = fb_idx + 1;
-                            break;
-                        }
-                    }
+            let font_index = full_match.or(other_kind).unwrap_or_else(|| {
+                // Every face gets opened here anyway, so rank them like the walk above
+                let mut order: Vec<usize> = (0..faces.len()).collect();
+                match presentation {
+                    Presentation::Emoji => order.sort_by_key(|&idx| !is_color(idx)),
+                    Presentation::Text => order.sort_by_key(|&idx| is_color(idx)),
+                    Presentation::Any => {}
                 }
-            }
+                let best = order
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(rank, &idx)| faces.get(idx).map(|face| (rank, idx, face)))
+                    .map(|(rank, idx, face)| {
+                        let has_base = covers(face, required[0]);
+                        let covered = required.iter().filter(|&&ch| covers(face, ch)).count();
+                        (rank, idx, has_base, covered)
+                    })
+                    .filter(|&(_, _, _, covered)| covered > 0)
+                    .max_by(|a, b| (a.2, a.3).cmp(&(b.2, b.3)).then(b.0.cmp(&a.0)))
+                    .map(|(_, idx, _, _)| idx)
+                    .unwrap_or(0);
 
-            if assigned == 0 {
-                // No font has this glyph!
-                missing_chars.push(ch);
-            }
-            assignments.push(assigned);
+                let face = faces.get(best);
+                missing.extend((start..end).filter(|&i| {
+                    !grapheme::is_default_ignorable(chars[i])
+                        && !face.is_some_and(|face| covers(face, chars[i]))
+                }));
+                best
+            });
+
+            assignments[start..end].fill(font_index);
         }
 
-        if !missing_chars.is_empty() {
+        if !missing.is_empty() {
             warn!(
                 "WARNING: {} chars have NO GLYPH in any font:",
-                missing_chars.len()
+                missing.len()
             );
-            for ch in &missing_chars {
-                warn!("  - '{}' (U+{:04X})", ch, *ch as u32);
+            for &i in &missing {
+                warn!("  - '{}' (U+{:04X})", chars[i], chars[i] as u32);
             }
         }
 
-        assignments
+        (assignments, missing)
     }
 
-    /// Group consecutive characters with the same font assignment into runs.
-    fn group_into_runs(chars: &[char], font_assignments: &[usize]) -> Vec<TextRun> {
-        if chars.is_empty() {
-            return Vec::new();
+    /// Decide per grapheme cluster whether it stands upright in a vertical line (UAX #50),
+    /// going by the cluster's first char and the font it was assigned.
+    fn resolve_upright(
+        chars: &[char],
+        font_assignments: &[usize],
+        faces: &StackFaces,
+    ) -> Vec<bool> {
+        let mut upright = vec![true; chars.len()];
+        for (start, end) in grapheme::cluster_ranges(chars) {
+            let face = faces.get(font_assignments[start]);
+            upright[start..end].fill(vertical::is_upright(face.map(|f| &**f), chars[start]));
         }
+        upright
+    }
 
+    /// Split each bidi run into runs of consecutive characters with the same font, script
+    /// and vertical orientation.
+    /// The result is in visual order: bidi runs already are, and the runs
+    /// inside an RTL bidi run are reversed.
+    fn group_into_runs(
+        chars: &[char],
+        font_assignments: &[usize],
+        scripts: &[Option<unicode_script::Script>],
+        upright: &[bool],
+        bidi_runs: &[BidiRun],
+    ) -> Vec<TextRun> {
         let mut runs = Vec::new();
-        let mut current_font = font_assignments[0];
-        let mut current_chars = vec![chars[0]];
 
-        for i in 1..chars.len() {
-            if font_assignments[i] == current_font {
-                current_chars.push(chars[i]);
-            } else {
-                runs.push(TextRun {
-                    chars: current_chars,
-                    font_index: current_font,
-                });
-                current_font = font_assignments[i];
-                current_chars = vec![chars[i]];
+        for bidi_run in bidi_runs {
+            let rtl = bidi_run.is_rtl();
+            let mut level_runs = Vec::new();
+            let mut current_start = bidi_run.start;
+
+            for i in (bidi_run.start + 1)..=bidi_run.end {
+                if i == bidi_run.end
+                    || font_assignments[i] != font_assignments[current_start]
+                    || scripts[i] != scripts[current_start]
+                    || upright[i] != upright[current_start]
+                {
+                    level_runs.push(TextRun {
+                        chars: chars[current_start..i].to_vec(),
+                        start: current_start,
+                        font_index: font_assignments[current_start],
+                        script: scripts[current_start],
+                        rtl,
+                        upright: upright[current_start],
+                    });
+                    current_start = i;
+                }
+            }
+
+            if rtl {
+                level_runs.reverse();
             }
+            runs.extend(level_runs);
         }
-        runs.push(TextRun {
-            chars: current_chars,
-            font_index: current_font,
-        });
         runs
     }
 }
+
+/// Apply resolved axis values to a shaping face.
+fn set_face_variations(face: &mut Face, variations: &[AxisValue]) {
+    let variations: Vec<rustybuzz::Variation> = variations
+        .iter()
+        .map(|&(tag, value)| rustybuzz::Variation { tag, value })
+        .collect();
+    face.set_variations(&variations);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_font::TestFont;
+    use tempfile::TempDir;
+
+    /// A registry of `fonts` written to files and registered by path, so none is opened
+    /// until a layout asks for it
+    fn lazy_registry(fonts: &[Vec<u8>]) -> (TempDir, FontRegistry, Vec<FontId>) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = FontRegistry::new();
+        let ids = fonts
+            .iter()
+            .enumerate()
+            .map(|(i, data)| {
+                let path = dir.path().join(format!("{}.ttf", i));
+                std::fs::write(&path, data).unwrap();
+                registry.register_path(path, 0, Vec::new())
+            })
+            .collect();
+        (dir, registry, ids)
+    }
+
+    fn assign(faces: &StackFaces, text: &str) -> Vec<usize> {
+        let chars: Vec<char> = text.chars().collect();
+        TextEngine::assign_fonts_to_chars(&chars, faces).0
+    }
+
+    #[test]
+    fn splits_runs_at_script_and_font_changes() {
+        let chars: Vec<char> = "ab αβ אב".chars().collect();
+        let scripts = script::resolve_scripts(&chars);
+        let fonts = [0, 0, 0, 0, 1, 1, 1, 1];
+        let (bidi_runs, _) = bidi::visual_runs("ab αβ אב", ParagraphDirection::Ltr);
+        let runs = TextEngine::group_into_runs(&chars, &fonts, &scripts, &[true; 8], &bidi_runs);
+        let runs: Vec<(usize, usize, bool)> = runs
+            .iter()
+            .map(|run| (run.start, run.font_index, run.rtl))
+            .collect();
+        // "β" changes font mid-word, the space after it stays Greek and the Hebrew runs RTL
+        assert_eq!(
+            runs,
+            [(0, 0, false), (3, 0, false), (4, 1, false), (6, 1, true)]
+        );
+    }
+
+    #[test]
+    fn resolves_the_shaping_language() {
+        let mut engine = TextEngine::new(512, 512);
+        let options = |language: Option<&str>| LayoutOptions {
+            language: language.map(str::to_string),
+            ..Default::default()
+        };
+        let language = |engine: &TextEngine, tag: Option<&str>| {
+            engine
+                .resolve_language(&options(tag))
+                .map(|l| l.as_str().to_string())
+        };
+
+        assert_eq!(language(&engine, None), None);
+        assert_eq!(
+            language(&engine, Some("zh-Hant")).as_deref(),
+            Some("zh-hant")
+        );
+        // Android's Locale.toString() form
+        assert_eq!(language(&engine, Some(" ja_JP ")).as_deref(), Some("ja-jp"));
+
+        // The engine default applies unless the call names a language
+        engine.set_default_language(Some("ko".to_string()));
+        assert_eq!(language(&engine, None).as_deref(), Some("ko"));
+        assert_eq!(language(&engine, Some("ja")).as_deref(), Some("ja"));
+    }
+
+    #[test]
+    fn shapes_with_the_language_tag() {
+        let font = TestFont::new("Test")
+            .chars("a", 500)
+            .localized(b"JAN ", 'a', 1000)
+            .build();
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(font, 0);
+        let mut width = |language: Option<&str>| {
+            let options = LayoutOptions {
+                language: language.map(str::to_string),
+                ..Default::default()
+            };
+            engine.process_text("a", 20.0, 400.0, &options).total_width
+        };
+        assert_eq!(width(None), 10.0);
+        assert_eq!(width(Some("ja")), 20.0);
+        assert_eq!(width(Some("ko")), 10.0);
+    }
+
+    #[test]
+    fn parses_options_json() {
+        let options = LayoutOptions::from_json(r#"{"language": "ja", "features": ["-liga"]}"#);
+        assert_eq!(options.language.as_deref(), Some("ja"));
+        assert_eq!(options.features, ["-liga"]);
+        assert_eq!(LayoutOptions::from_json(" "), LayoutOptions::default());
+        assert_eq!(
+            LayoutOptions::from_json("{\"language\": 3"),
+            LayoutOptions::default()
+        );
+    }
+
+    #[test]
+    fn keeps_each_cluster_on_one_font() {
+        let (_dir, registry, ids) = lazy_registry(&[
+            TestFont::new("Latin").chars("ae", 500).build(),
+            TestFont::new("Marks").chars("e\u{301}", 500).build(),
+        ]);
+        let faces = registry.faces(&ids);
+        // The primary font lacks the accent, so the whole "é" moves to the fallback
+        assert_eq!(assign(&faces, "ae\u{301}a"), [0, 1, 1, 0]);
+        // A ZWJ doesn't need a glyph of its own
+        assert_eq!(assign(&faces, "a\u{200D}e"), [0, 0, 0]);
+    }
+
+    #[test]
+    fn reports_chars_no_font_covers() {
+        let (_dir, registry, ids) = lazy_registry(&[
+            TestFont::new("Latin").chars("ae", 500).build(),
+            TestFont::new("Marks").chars("\u{301}", 500).build(),
+        ]);
+        let faces = registry.faces(&ids);
+        let chars: Vec<char> = "ae\u{301}x".chars().collect();
+        let (fonts, missing) = TextEngine::assign_fonts_to_chars(&chars, &faces);
+        // No font has both halves of "é": the one with its base wins, missing the accent
+        assert_eq!(fonts, [0, 0, 0, 0]);
+        assert_eq!(missing, [2, 3]);
+    }
+
+    #[test]
+    fn text_presentation_stops_at_the_first_covering_font() {
+        let (_dir, registry, ids) = lazy_registry(&[
+            TestFont::new("Text").chars("a©", 500).build(),
+            TestFont::new("Emoji").color().chars("©", 1000).build(),
+            TestFont::new("Other").chars("b", 500).build(),
+        ]);
+        let faces = registry.faces(&ids);
+        assert_eq!(assign(&faces, "a©"), [0, 0]);
+        assert!(faces.is_loaded(0));
+        assert!(!faces.is_loaded(1));
+        assert!(!faces.is_loaded(2));
+    }
+
+    #[test]
+    fn presentation_prefers_fonts_of_its_kind() {
+        let (_dir, registry, ids) = lazy_registry(&[
+            TestFont::new("Emoji")
+                .color()
+                .chars("©\u{2764}", 1000)
+                .build(),
+            TestFont::new("Text").chars("©\u{2764}", 500).build(),
+        ]);
+        let faces = registry.faces(&ids);
+        // © defaults to text presentation and skips the earlier color font
+        assert_eq!(assign(&faces, "©"), [1]);
+        // VS16 asks for the emoji, VS15 for the text glyph
+        assert_eq!(assign(&faces, "\u{2764}\u{FE0F}"), [0, 0]);
+        assert_eq!(assign(&faces, "\u{2764}\u{FE0E}"), [1, 1]);
+    }
+
+    #[test]
+    fn emoji_presentation_falls_back_to_a_text_font() {
+        let (_dir, registry, ids) = lazy_registry(&[
+            TestFont::new("Text").chars("\u{2764}", 500).build(),
+            TestFont::new("Emoji").color().chars("x", 1000).build(),
+        ]);
+        let faces = registry.faces(&ids);
+        assert_eq!(assign(&faces, "\u{2764}\u{FE0F}"), [0, 0]);
+    }
+
+    type Uncovered = (Vec<u32>, Vec<u32>, Vec<u32>);
+
+    fn uncovered(chars: &UncoveredChars) -> Uncovered {
+        (
+            chars.uncovered_codepoints.clone(),
+            chars.uncovered_offsets.clone(),
+            chars.uncovered_offsets_utf16.clone(),
+        )
+    }
+
+    #[test]
+    fn reports_uncovered_offsets_in_chars_and_utf16() {
+        // The emoji before "β" is one char but two UTF-16 units
+        let chars = UncoveredChars::from_offsets("😀aβ", &[2]);
+        assert_eq!(uncovered(&chars), (vec!['β' as u32], vec![2], vec![3]));
+
+        // Shifted by where the substring starts when appended
+        let mut all = UncoveredChars::from_offsets("β", &[0]);
+        all.append(&chars, 5, 6);
+        assert_eq!(
+            uncovered(&all),
+            (vec!['β' as u32; 2], vec![0, 7], vec![0, 9])
+        );
+    }
+
+    #[test]
+    fn layouts_report_uncovered_chars() {
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(TestFont::new("Test").chars("a", 500).build(), 0);
+        let result = engine.process_text("a😀z", 20.0, 400.0, &LayoutOptions::default());
+        assert_eq!(
+            uncovered(&result.uncovered),
+            (vec![0x1F600, 'z' as u32], vec![1, 2], vec![1, 3])
+        );
+    }
+
+    #[test]
+    fn checks_coverage_without_changing_the_stack() {
+        let dir = tempfile::tempdir().unwrap();
+        let fonts = dir.path().join("fonts");
+        std::fs::create_dir(&fonts).unwrap();
+        std::fs::write(
+            fonts.join("Greek.ttf"),
+            TestFont::new("Greek").chars("αβ", 500).build(),
+        )
+        .unwrap();
+
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(TestFont::new("Latin").chars("a", 500).build(), 0);
+        engine.set_font_discovery(Some(FontDiscovery::new(
+            vec![fonts],
+            Some(dir.path().join("index.json")),
+        )));
+        let options = LayoutOptions::default();
+
+        // Discovery could cover "α", but a plain check leaves the stack alone
+        for _ in 0..2 {
+            let missing = engine.check_coverage("aα", &options, false);
+            assert_eq!(missing.uncovered_offsets, [1]);
+            assert_eq!(engine.stack_fonts(&options).len(), 1);
+        }
+
+        let missing = engine.check_coverage("aα", &options, true);
+        assert!(missing.uncovered_offsets.is_empty());
+        assert_eq!(engine.stack_fonts(&options).len(), 2);
+    }
+}
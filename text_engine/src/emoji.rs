@@ -0,0 +1,83 @@
+use unicode_properties::emoji::{EmojiStatus, UnicodeEmoji};
+
+const VS15_TEXT: char = '\u{FE0E}';
+const VS16_EMOJI: char = '\u{FE0F}';
+const COMBINING_KEYCAP: char = '\u{20E3}';
+
+/// Presentation a grapheme cluster asks for when picking a font.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Presentation {
+    /// Color emoji glyph (VS16, keycaps, or a base with Emoji_Presentation=Yes)
+    Emoji,
+    /// Monochrome text glyph (VS15, or an emoji base that defaults to text like U+2764)
+    Text,
+    /// Not emoji at all, any font will do
+    Any,
+}
+
+/// Resolve the presentation of a grapheme cluster.
+/// Explicit variation selectors win over the base character's Emoji_Presentation property.
+pub fn cluster_presentation(cluster: &[char]) -> Presentation {
+    let Some(&base) = cluster.first() else {
+        return Presentation::Any;
+    };
+
+    if cluster.contains(&VS15_TEXT) {
+        return Presentation::Text;
+    }
+    if cluster.contains(&VS16_EMOJI) || cluster.contains(&COMBINING_KEYCAP) {
+        return Presentation::Emoji;
+    }
+
+    match base.emoji_status() {
+        EmojiStatus::EmojiPresentation
+        | EmojiStatus::EmojiPresentationAndModifierBase
+        | EmojiStatus::EmojiPresentationAndEmojiComponent
+        | EmojiStatus::EmojiPresentationAndModifierAndEmojiComponent => Presentation::Emoji,
+        EmojiStatus::NonEmoji | EmojiStatus::NonEmojiButEmojiComponent => Presentation::Any,
+        _ => Presentation::Text,
+    }
+}
+
+/// Whether a face carries color glyphs (COLR, CBDT, sbix or SVG), i.e. is an emoji font.
+pub fn is_color_font(face: &rustybuzz::ttf_parser::Face) -> bool {
+    let tables = face.tables();
+    tables.colr.is_some() || tables.cbdt.is_some() || tables.sbix.is_some() || tables.svg.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_font::TestFont;
+
+    fn presentation(cluster: &str) -> Presentation {
+        cluster_presentation(&cluster.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn resolves_presentation_from_the_base() {
+        assert_eq!(presentation("\u{1F600}"), Presentation::Emoji);
+        assert_eq!(presentation("\u{2764}"), Presentation::Text);
+        assert_eq!(presentation("©"), Presentation::Text);
+        assert_eq!(presentation("a"), Presentation::Any);
+        assert_eq!(presentation(""), Presentation::Any);
+    }
+
+    #[test]
+    fn variation_selectors_override_the_base() {
+        assert_eq!(presentation("\u{2764}\u{FE0F}"), Presentation::Emoji);
+        assert_eq!(presentation("\u{1F600}\u{FE0E}"), Presentation::Text);
+        // Keycaps are emoji even without VS16
+        assert_eq!(presentation("1\u{20E3}"), Presentation::Emoji);
+    }
+
+    #[test]
+    fn detects_color_fonts() {
+        let parse = |data: &[u8]| {
+            let face = rustybuzz::ttf_parser::Face::parse(data, 0).unwrap();
+            is_color_font(&face)
+        };
+        assert!(parse(&TestFont::new("Emoji").color().build()));
+        assert!(!parse(&TestFont::new("Text").build()));
+    }
+}
@@ -1,8 +1,13 @@
+use crate::synthesis::Synthesis;
+use crate::variation::AxisValue;
 use memmap2::Mmap;
+use rustybuzz::ttf_parser::{self, name_id, Face};
 use sdf_glyph_renderer::{clamp_to_u8, BitmapGlyph};
+use serde::{Deserialize, Serialize};
 use std::ops::Deref;
+use std::path::Path;
 use swash::scale::{Render, ScaleContext, Source};
-use swash::zeno::Format;
+use swash::zeno::{Command, Format, PathData};
 use swash::FontRef;
 
 /// Buffer size around the glyph for SDF spread (increased for shadow support)
@@ -12,6 +17,159 @@ const SDF_RADIUS: usize = 16;
 /// SDF cutoff for clamping to u8 (0.25 is standard for text rendering)
 const SDF_CUTOFF: f64 = 0.25;
 
+/// Path verbs of a `GlyphOutline`, with the number of points each one takes
+pub const VERB_MOVE: u8 = 0; // 1 point
+pub const VERB_LINE: u8 = 1; // 1 point
+pub const VERB_QUAD: u8 = 2; // 2 points: control, end
+pub const VERB_CUBIC: u8 = 3; // 3 points: control 1, control 2, end
+pub const VERB_CLOSE: u8 = 4; // no points
+
+/// Vector outline of a glyph in pixels, y up from the glyph origin
+#[derive(Clone, Debug, Default)]
+pub struct GlyphOutline {
+    pub verbs: Vec<u8>,
+    pub points: Vec<f32>, // x, y interleaved
+}
+
+impl GlyphOutline {
+    /// Tight bounding box of the path as (xmin, ymin, xmax, ymax), taking the extrema of
+    /// curves rather than their control points. None for an empty path.
+    pub fn ink_bounds(&self) -> Option<(f32, f32, f32, f32)> {
+        let mut bounds: Option<(f32, f32, f32, f32)> = None;
+        let mut include = |x: f32, y: f32| {
+            let (x0, y0, x1, y1) = bounds.unwrap_or((x, y, x, y));
+            bounds = Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y)));
+        };
+
+        let point = |i: usize| (self.points[i * 2], self.points[i * 2 + 1]);
+        let mut current = (0.0f32, 0.0f32);
+        let mut next = 0;
+        for &verb in &self.verbs {
+            match verb {
+                VERB_MOVE | VERB_LINE => {
+                    current = point(next);
+                    include(current.0, current.1);
+                    next += 1;
+                }
+                VERB_QUAD => {
+                    let (c, end) = (point(next), point(next + 1));
+                    for t in quad_extrema(current, c, end) {
+                        let mt = 1.0 - t;
+                        include(
+                            mt * mt * current.0 + 2.0 * mt * t * c.0 + t * t * end.0,
+                            mt * mt * current.1 + 2.0 * mt * t * c.1 + t * t * end.1,
+                        );
+                    }
+                    include(end.0, end.1);
+                    current = end;
+                    next += 2;
+                }
+                VERB_CUBIC => {
+                    let (c1, c2, end) = (point(next), point(next + 1), point(next + 2));
+                    for t in cubic_extrema(current, c1, c2, end) {
+                        let mt = 1.0 - t;
+                        let (a, b, c, d) =
+                            (mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t);
+                        include(
+                            a * current.0 + b * c1.0 + c * c2.0 + d * end.0,
+                            a * current.1 + b * c1.1 + c * c2.1 + d * end.1,
+                        );
+                    }
+                    include(end.0, end.1);
+                    current = end;
+                    next += 3;
+                }
+                _ => {}
+            }
+        }
+        bounds
+    }
+}
+
+/// Parameters in (0, 1) where a quadratic Bézier reaches an extremum on either axis
+fn quad_extrema(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32)) -> Vec<f32> {
+    let mut ts = Vec::with_capacity(2);
+    for (a, b, c) in [(p0.0, p1.0, p2.0), (p0.1, p1.1, p2.1)] {
+        let denominator = a - 2.0 * b + c;
+        if denominator != 0.0 {
+            let t = (a - b) / denominator;
+            if t > 0.0 && t < 1.0 {
+                ts.push(t);
+            }
+        }
+    }
+    ts
+}
+
+/// Parameters in (0, 1) where a cubic Bézier reaches an extremum on either axis
+fn cubic_extrema(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), p3: (f32, f32)) -> Vec<f32> {
+    let mut ts = Vec::with_capacity(4);
+    for (a, b, c, d) in [(p0.0, p1.0, p2.0, p3.0), (p0.1, p1.1, p2.1, p3.1)] {
+        // Roots of the derivative q2·t² + q1·t + q0
+        let q2 = 3.0 * (-a + 3.0 * b - 3.0 * c + d);
+        let q1 = 6.0 * (a - 2.0 * b + c);
+        let q0 = 3.0 * (b - a);
+        if q2.abs() < f32::EPSILON {
+            if q1 != 0.0 {
+                ts.push(-q0 / q1);
+            }
+            continue;
+        }
+        let discriminant = q1 * q1 - 4.0 * q2 * q0;
+        if discriminant >= 0.0 {
+            let root = discriminant.sqrt();
+            ts.push((-q1 + root) / (2.0 * q2));
+            ts.push((-q1 - root) / (2.0 * q2));
+        }
+    }
+    ts.retain(|&t| t > 0.0 && t < 1.0);
+    ts
+}
+
+/// One face of a font file, as listed by `list_faces`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FaceInfo {
+    pub index: u32, // Face index to load it with
+    pub family: String,
+    pub subfamily: String,
+    pub postscript_name: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub is_variable: bool,
+}
+
+/// First name record with the given ID that decodes to a string.
+fn face_name(face: &Face, name_id: u16) -> Option<String> {
+    face.names()
+        .into_iter()
+        .filter(|name| name.name_id == name_id)
+        .find_map(|name| name.to_string())
+}
+
+/// List the faces in a font file: every face of a TTC/OTC collection, or the single face
+/// of a plain TTF/OTF. Faces that fail to parse are left out.
+pub fn list_faces(data: &[u8]) -> Vec<FaceInfo> {
+    let count = ttf_parser::fonts_in_collection(data).unwrap_or(1);
+    (0..count)
+        .filter_map(|index| {
+            let face = Face::parse(data, index).ok()?;
+            Some(FaceInfo {
+                index,
+                family: face_name(&face, name_id::TYPOGRAPHIC_FAMILY)
+                    .or_else(|| face_name(&face, name_id::FAMILY))
+                    .unwrap_or_default(),
+                subfamily: face_name(&face, name_id::TYPOGRAPHIC_SUBFAMILY)
+                    .or_else(|| face_name(&face, name_id::SUBFAMILY))
+                    .unwrap_or_default(),
+                postscript_name: face_name(&face, name_id::POST_SCRIPT_NAME).unwrap_or_default(),
+                weight: face.weight().to_number(),
+                italic: face.is_italic(),
+                is_variable: face.is_variable(),
+            })
+        })
+        .collect()
+}
+
 /// Font data storage - supports both owned bytes and memory-mapped files
 pub enum FontData {
     Owned(Vec<u8>),
@@ -32,61 +190,71 @@ impl Deref for FontData {
 pub struct FontWrapper {
     pub font_data: FontData,
     pub _id: usize,
+    pub face_index: u32, // Face within a TTC/OTC collection, 0 for single fonts
+    pub default_axes: Vec<AxisValue>, // Axis values it was registered at, under the layout's own
     scale_context: ScaleContext,
 }
 
 impl FontWrapper {
-    pub fn from_bytes(bytes: &[u8], id: usize) -> Option<Self> {
+    pub fn from_bytes(bytes: &[u8], id: usize, face_index: u32) -> Option<Self> {
         // Verify font is valid
-        let _ = FontRef::from_index(bytes, 0)?;
+        let _ = FontRef::from_index(bytes, face_index as usize)?;
         Some(Self {
             font_data: FontData::Owned(bytes.to_vec()),
             _id: id,
+            face_index,
+            default_axes: Vec::new(),
             scale_context: ScaleContext::new(),
         })
     }
 
     /// Create FontWrapper from a memory-mapped file
-    pub fn from_mmap(mmap: Mmap, id: usize) -> Option<Self> {
+    pub fn from_mmap(mmap: Mmap, id: usize, face_index: u32) -> Option<Self> {
         // Verify font is valid
-        let _ = FontRef::from_index(&mmap, 0)?;
+        let _ = FontRef::from_index(&mmap, face_index as usize)?;
         Some(Self {
             font_data: FontData::Mapped(mmap),
             _id: id,
+            face_index,
+            default_axes: Vec::new(),
             scale_context: ScaleContext::new(),
         })
     }
 
-    /// Generate a Signed Distance Field for the given glyph with variable font weight support.
+    /// Generate a Signed Distance Field for the given glyph with the given variable font axes.
     /// Returns (rgba_data, width, height, xmin, ymin) where the SDF is stored in the alpha channel.
     /// RGB channels are set to 255 (white) for shader flexibility.
     /// xmin, ymin are the bearing offsets from glyph origin to bitmap top-left.
     ///
-    /// weight: Font weight (100-900, where 400=normal, 700=bold)
+    /// variations: Axis values from `variation::resolve`, the same ones the text was shaped with
+    /// synthesis: Faux bold/oblique for what the axes can't produce
     pub fn generate_sdf(
         &mut self,
         glyph_id: u16,
         size_px: f32,
-        weight: f32,
+        variations: &[AxisValue],
+        synthesis: Synthesis,
     ) -> (Vec<u8>, u32, u32, f32, f32) {
         // Create FontRef directly to avoid borrow conflicts
-        let font = match FontRef::from_index(&self.font_data, 0) {
+        let font = match FontRef::from_index(&self.font_data, self.face_index as usize) {
             Some(f) => f,
             None => return (vec![0, 0, 0, 0], 1, 1, 0.0, 0.0),
         };
 
-        // Build scaler with variable font weight support
+        // Build scaler with the variable font axes applied
         let mut scaler = self
             .scale_context
             .builder(font)
             .size(size_px)
             .hint(true)
-            .variations(&[("wght", weight)]) // Set font weight axis
+            .variations(variations.iter().map(|&(tag, value)| (tag.0, value)))
             .build();
 
         // Render the glyph to an alpha mask
         let image = Render::new(&[Source::Outline])
             .format(Format::Alpha)
+            .embolden(synthesis.embolden_strength(size_px))
+            .transform(synthesis.skew())
             .render(&mut scaler, glyph_id);
 
         let image = match image {
@@ -149,10 +317,9 @@ impl FontWrapper {
             );
 
             // Shadow: smoothstep falloff
-            let shadow_alpha = if sdf_value >= SHADOW_INNER_EDGE {
-                0.0 // Inside text - covered by text layer
-            } else if sdf_value <= SHADOW_OUTER_EDGE {
-                0.0 // At buffer edge
+            // Inside text (covered by text layer) or at buffer edge
+            let shadow_alpha = if sdf_value >= SHADOW_INNER_EDGE || sdf_value <= SHADOW_OUTER_EDGE {
+                0.0
             } else {
                 let t = (sdf_value - SHADOW_OUTER_EDGE) / (SHADOW_INNER_EDGE - SHADOW_OUTER_EDGE);
                 t * t * (3.0 - 2.0 * t) // smoothstep
@@ -180,6 +347,90 @@ impl FontWrapper {
             ymin,
         )
     }
+
+    /// Extract the outline of a glyph scaled to `size_px`, with the same variations and synthesis
+    /// as `generate_sdf` but unhinted, since it's meant to be drawn at any size.
+    /// Returns None for glyphs without an outline (bitmap-only emoji, fonts that fail to parse).
+    pub fn glyph_outline(
+        &mut self,
+        glyph_id: u16,
+        size_px: f32,
+        variations: &[AxisValue],
+        synthesis: Synthesis,
+    ) -> Option<GlyphOutline> {
+        let font = FontRef::from_index(&self.font_data, self.face_index as usize)?;
+        let mut scaler = self
+            .scale_context
+            .builder(font)
+            .size(size_px)
+            .hint(false)
+            .variations(variations.iter().map(|&(tag, value)| (tag.0, value)))
+            .build();
+        let mut outline = scaler.scale_outline(glyph_id)?;
+        synthesis.apply(&mut outline, size_px);
+
+        let mut result = GlyphOutline::default();
+        for command in outline.path().commands() {
+            match command {
+                Command::MoveTo(p) => {
+                    result.verbs.push(VERB_MOVE);
+                    result.points.extend([p.x, p.y]);
+                }
+                Command::LineTo(p) => {
+                    result.verbs.push(VERB_LINE);
+                    result.points.extend([p.x, p.y]);
+                }
+                Command::QuadTo(c, p) => {
+                    result.verbs.push(VERB_QUAD);
+                    result.points.extend([c.x, c.y, p.x, p.y]);
+                }
+                Command::CurveTo(c1, c2, p) => {
+                    result.verbs.push(VERB_CUBIC);
+                    result.points.extend([c1.x, c1.y, c2.x, c2.y, p.x, p.y]);
+                }
+                Command::Close => result.verbs.push(VERB_CLOSE),
+            }
+        }
+        Some(result)
+    }
+}
+
+/// Memory-map the file behind a file descriptor. The fd is duplicated, so the caller
+/// can close it right after.
+#[cfg(unix)]
+pub fn map_fd(fd: i32) -> Option<Mmap> {
+    use std::os::unix::io::FromRawFd;
+
+    // Duplicate the FD so we own it
+    let dup_fd = unsafe { libc::dup(fd) };
+    if dup_fd < 0 {
+        #[cfg(debug_assertions)]
+        eprintln!("[TextEngine] Failed to dup fd!");
+        return None;
+    }
+
+    // Create a File from the duplicated FD
+    let file = unsafe { std::fs::File::from_raw_fd(dup_fd) };
+
+    // Memory-map the file
+    match unsafe { Mmap::map(&file) } {
+        Ok(m) => Some(m),
+        Err(e) => {
+            #[cfg(debug_assertions)]
+            eprintln!("[TextEngine] Failed to mmap: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Memory-map a font file by path
+pub fn map_file(path: &Path) -> Option<Mmap> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| warn!("Failed to open font {}: {}", path.display(), e))
+        .ok()?;
+    unsafe { Mmap::map(&file) }
+        .map_err(|e| warn!("Failed to mmap font {}: {}", path.display(), e))
+        .ok()
 }
 
 /// Smoothstep function for smooth alpha transitions
@@ -187,3 +438,90 @@ fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
     let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
     t * t * (3.0 - 2.0 * t)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{LayoutOptions, TextEngine};
+    use crate::test_font::{self, TestFont};
+
+    fn outline(verbs: &[u8], points: &[f32]) -> GlyphOutline {
+        GlyphOutline {
+            verbs: verbs.to_vec(),
+            points: points.to_vec(),
+        }
+    }
+
+    #[test]
+    fn ink_bounds_reach_curve_extrema() {
+        // The control points stick out further than the curves reach
+        let quad = outline(
+            &[VERB_MOVE, VERB_QUAD, VERB_CLOSE],
+            &[0.0, 0.0, 5.0, 10.0, 10.0, 0.0],
+        );
+        assert_eq!(quad.ink_bounds(), Some((0.0, 0.0, 10.0, 5.0)));
+
+        let cubic = outline(
+            &[VERB_MOVE, VERB_CUBIC, VERB_CLOSE],
+            &[0.0, 0.0, 0.0, 10.0, 10.0, 10.0, 10.0, 0.0],
+        );
+        assert_eq!(cubic.ink_bounds(), Some((0.0, 0.0, 10.0, 7.5)));
+
+        let line = outline(&[VERB_MOVE, VERB_LINE], &[3.0, -1.0, -2.0, 4.0]);
+        assert_eq!(line.ink_bounds(), Some((-2.0, -1.0, 3.0, 4.0)));
+        assert_eq!(GlyphOutline::default().ink_bounds(), None);
+    }
+
+    #[test]
+    fn reports_ink_bounds_per_glyph() {
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(TestFont::new("Test").chars("a ", 500).build(), 0);
+        let result = engine.process_text("a a", 100.0, 400.0, &LayoutOptions::default());
+        // Boxes from 5 to 45 px across and 70 px up from the baseline, y down
+        assert_eq!(result.ink_bounds[..4], [5.0, -70.0, 45.0, 0.0]);
+        // A space has no ink
+        assert_eq!(result.ink_bounds[4..8], [0.0; 4]);
+        assert_eq!((result.ink_top, result.ink_bottom), (-70.0, 0.0));
+    }
+
+    fn collection() -> Vec<u8> {
+        test_font::collection(&[
+            TestFont::new("Serif").chars("a", 500).build(),
+            TestFont::new("Serif")
+                .weight(700)
+                .italic()
+                .chars("a", 600)
+                .build(),
+        ])
+    }
+
+    #[test]
+    fn lists_every_face_of_a_collection() {
+        let faces: Vec<(u32, String, String, u16, bool)> = list_faces(&collection())
+            .into_iter()
+            .map(|f| (f.index, f.family, f.subfamily, f.weight, f.italic))
+            .collect();
+        assert_eq!(
+            faces,
+            [
+                (0, "Serif".into(), "Regular".into(), 400, false),
+                (1, "Serif".into(), "Italic".into(), 700, true),
+            ]
+        );
+        // A plain font is a single face
+        assert_eq!(list_faces(&TestFont::new("Sans").build()).len(), 1);
+        assert!(list_faces(b"not a font").is_empty());
+    }
+
+    #[test]
+    fn loads_a_face_by_index() {
+        let data = collection();
+        assert!(FontWrapper::from_bytes(&data, 0, 1).is_some());
+        assert!(FontWrapper::from_bytes(&data, 0, 2).is_none());
+
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(data, 1);
+        let result = engine.process_text("a", 100.0, 400.0, &LayoutOptions::default());
+        assert_eq!(result.total_width, 60.0);
+    }
+}
@@ -0,0 +1,619 @@
+use crate::emoji::{self, Presentation};
+use crate::font::{self, FaceInfo};
+use crate::registry::{FontId, FontRegistry};
+use rustybuzz::ttf_parser::{self, Face};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Bumped whenever `IndexedFile` changes, so stale caches get rebuilt instead of misread
+const INDEX_VERSION: u32 = 1;
+/// Directory under the platform cache dir the index is kept in
+const CACHE_DIR_NAME: &str = "accompanist";
+const CACHE_FILE_NAME: &str = "font-index.json";
+/// Weight fallbacks are picked closest to
+const REGULAR_WEIGHT: u16 = 400;
+
+/// One face of an indexed font file: its names and style plus the characters it maps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexedFace {
+    #[serde(flatten)]
+    pub info: FaceInfo,
+    pub color: bool,           // Has color glyph tables (emoji)
+    coverage: Vec<(u32, u32)>, // Sorted, disjoint inclusive codepoint ranges
+}
+
+impl IndexedFace {
+    pub fn covers(&self, ch: char) -> bool {
+        let cp = ch as u32;
+        let i = self.coverage.partition_point(|&(_, end)| end < cp);
+        self.coverage.get(i).is_some_and(|&(start, _)| start <= cp)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct IndexedFile {
+    path: PathBuf,
+    modified: u64, // Seconds since the epoch; with the size, tells when to index again
+    size: u64,
+    faces: Vec<IndexedFace>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexCache {
+    version: u32,
+    files: Vec<IndexedFile>,
+}
+
+/// Every face found in a set of font directories.
+pub struct FontIndex {
+    files: Vec<IndexedFile>,
+}
+
+impl FontIndex {
+    /// Scan `font_dirs` recursively for TTF/OTF/TTC/OTC files. With a `cache_path`, files
+    /// whose size and modification time match the cached index are taken from it, and
+    /// the cache is rewritten when anything changed.
+    pub fn build(font_dirs: &[PathBuf], cache_path: Option<&Path>) -> Self {
+        let mut cached: HashMap<PathBuf, IndexedFile> = cache_path
+            .and_then(read_cache)
+            .map(|files| files.into_iter().map(|f| (f.path.clone(), f)).collect())
+            .unwrap_or_default();
+        let cached_count = cached.len();
+
+        let mut paths = Vec::new();
+        let mut visited = HashSet::new();
+        for dir in font_dirs {
+            collect_font_files(dir, &mut paths, &mut visited);
+        }
+
+        let mut files = Vec::with_capacity(paths.len());
+        let mut indexed = 0;
+        for path in paths {
+            let Some((modified, size)) = file_stamp(&path) else {
+                continue;
+            };
+            match cached.remove(&path) {
+                Some(file) if file.modified == modified && file.size == size => files.push(file),
+                _ => {
+                    indexed += 1;
+                    files.push(IndexedFile {
+                        faces: index_file(&path),
+                        path,
+                        modified,
+                        size,
+                    });
+                }
+            }
+        }
+
+        // Anything left in `cached` was deleted since
+        let changed = indexed > 0 || files.len() != cached_count;
+        info!(
+            "Font index: {} files, {} newly indexed",
+            files.len(),
+            indexed
+        );
+        let index = Self { files };
+        if let (true, Some(path)) = (changed, cache_path) {
+            index.write_cache(path);
+        }
+        index
+    }
+
+    /// Faces to add as fallbacks so that as many of `chars` as possible get a font, fewest
+    /// faces first: the face covering the most of what's left is taken until nothing more
+    /// can be covered. Ties go to color fonts when emoji are missing, then to upright faces
+    /// nearest the regular weight, then to the earlier file.
+    pub fn pick_fallbacks(&self, chars: &[char]) -> Vec<(&Path, &IndexedFace)> {
+        let mut missing: Vec<char> = chars.to_vec();
+        missing.sort_unstable();
+        missing.dedup();
+
+        let candidates: Vec<(&Path, &IndexedFace)> = self
+            .files
+            .iter()
+            .flat_map(|file| {
+                file.faces
+                    .iter()
+                    .map(move |face| (file.path.as_path(), face))
+            })
+            .collect();
+
+        let mut picked = Vec::new();
+        while !missing.is_empty() {
+            let wants_color = missing
+                .iter()
+                .any(|&ch| emoji::cluster_presentation(&[ch]) == Presentation::Emoji);
+            let best = candidates
+                .iter()
+                .enumerate()
+                .map(|(order, &(path, face))| {
+                    let covered = missing.iter().filter(|&&ch| face.covers(ch)).count();
+                    let key = (
+                        covered,
+                        wants_color && face.color,
+                        !face.info.italic,
+                        std::cmp::Reverse(face.info.weight.abs_diff(REGULAR_WEIGHT)),
+                        std::cmp::Reverse(order),
+                    );
+                    (key, path, face)
+                })
+                .filter(|(key, _, _)| key.0 > 0)
+                .max_by_key(|(key, _, _)| *key);
+            let Some((_, path, face)) = best else {
+                break;
+            };
+            missing.retain(|&ch| !face.covers(ch));
+            picked.push((path, face));
+        }
+        picked
+    }
+
+    fn write_cache(&self, path: &Path) {
+        let cache = IndexCache {
+            version: INDEX_VERSION,
+            files: self.files.clone(),
+        };
+        let result = serde_json::to_vec(&cache)
+            .map_err(std::io::Error::other)
+            .and_then(|json| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                // Written aside and renamed, so a concurrent reader never sees half a file
+                let temp = path.with_extension("tmp");
+                std::fs::write(&temp, json)?;
+                std::fs::rename(&temp, path)
+            });
+        if let Err(e) = result {
+            warn!("Failed to write font index {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Where to look for fonts and keep the index, and what the engine already took from it.
+pub struct FontDiscovery {
+    font_dirs: Vec<PathBuf>,
+    cache_path: Option<PathBuf>,
+    index: Option<FontIndex>, // Built by `build_index`, or the first time a character is missing
+    registered: HashMap<(PathBuf, u32), FontId>,
+    unavailable: HashSet<char>, // No indexed face covers these; not looked up again
+}
+
+impl FontDiscovery {
+    /// Discovery over `font_dirs` (the platform's standard directories if empty), with the
+    /// index cached at `cache_path` (the platform cache directory if None).
+    pub fn new(font_dirs: Vec<PathBuf>, cache_path: Option<PathBuf>) -> Self {
+        Self {
+            font_dirs: if font_dirs.is_empty() {
+                default_font_dirs()
+            } else {
+                font_dirs
+            },
+            cache_path: cache_path.or_else(default_cache_path),
+            index: None,
+            registered: HashMap::new(),
+            unavailable: HashSet::new(),
+        }
+    }
+
+    /// Scan the font directories now (reusing the cached index where it's current) rather
+    /// than when the first character is missing
+    pub fn build_index(&mut self) {
+        if self.index.is_none() {
+            self.index = Some(FontIndex::build(
+                &self.font_dirs,
+                self.cache_path.as_deref(),
+            ));
+        }
+    }
+
+    /// Pick faces for `chars` the stack lacks, registering each new one in `fonts` by path.
+    /// Returns the IDs to add to the stack, which may include faces registered for other
+    /// stacks before. Characters nothing covers are remembered and skipped from then on.
+    pub fn fallbacks_for(&mut self, chars: &[char], fonts: &mut FontRegistry) -> Vec<FontId> {
+        let chars: Vec<char> = chars
+            .iter()
+            .copied()
+            .filter(|ch| !self.unavailable.contains(ch))
+            .collect();
+        if chars.is_empty() {
+            return Vec::new();
+        }
+
+        let index = self
+            .index
+            .get_or_insert_with(|| FontIndex::build(&self.font_dirs, self.cache_path.as_deref()));
+        let picked = index.pick_fallbacks(&chars);
+        self.unavailable.extend(
+            chars
+                .iter()
+                .filter(|&&ch| !picked.iter().any(|(_, face)| face.covers(ch))),
+        );
+
+        picked
+            .into_iter()
+            .map(|(path, face)| {
+                let key = (path.to_path_buf(), face.info.index);
+                match self.registered.get(&key) {
+                    // Still there unless the app unregistered it
+                    Some(&id) if fonts.contains(id) => id,
+                    _ => {
+                        info!(
+                            "Discovered fallback {} ({}) in {}",
+                            face.info.family,
+                            face.info.subfamily,
+                            path.display()
+                        );
+                        let id =
+                            fonts.register_path(path.to_path_buf(), face.info.index, Vec::new());
+                        self.registered.insert(key, id);
+                        id
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Forget the fonts registered so far, after the engine dropped them
+    pub fn reset(&mut self) {
+        self.registered.clear();
+        self.unavailable.clear();
+    }
+}
+
+/// Standard font directories of the platform, user directories included
+pub fn default_font_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "windows") {
+        let windir = std::env::var_os("WINDIR").map_or_else(|| "C:\\Windows".into(), PathBuf::from);
+        dirs.push(windir.join("Fonts"));
+        if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(local).join("Microsoft\\Windows\\Fonts"));
+        }
+    } else if cfg!(target_os = "macos") {
+        dirs.push("/System/Library/Fonts".into());
+        dirs.push("/Library/Fonts".into());
+        dirs.extend(home.map(|home| home.join("Library/Fonts")));
+    } else {
+        dirs.push("/usr/share/fonts".into());
+        dirs.push("/usr/local/share/fonts".into());
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|home| home.join(".local/share")));
+        dirs.extend(data_home.map(|data| data.join("fonts")));
+        dirs.extend(home.map(|home| home.join(".fonts")));
+    }
+    dirs
+}
+
+/// Where the index is cached by default: the platform's per-user cache directory
+pub fn default_cache_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let cache_dir = if cfg!(target_os = "windows") {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library/Caches"))
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".cache")))
+    }?;
+    Some(cache_dir.join(CACHE_DIR_NAME).join(CACHE_FILE_NAME))
+}
+
+fn read_cache(path: &Path) -> Option<Vec<IndexedFile>> {
+    let json = std::fs::read(path).ok()?;
+    match serde_json::from_slice::<IndexCache>(&json) {
+        Ok(cache) if cache.version == INDEX_VERSION => Some(cache.files),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Ignoring unreadable font index {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn collect_font_files(dir: &Path, paths: &mut Vec<PathBuf>, visited: &mut HashSet<PathBuf>) {
+    // Symlinked directories could otherwise be walked twice, or forever
+    let Ok(canonical) = dir.canonicalize() else {
+        return;
+    };
+    if !visited.insert(canonical) {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_font_files(&path, paths, visited);
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                ["ttf", "otf", "ttc", "otc"]
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+        {
+            paths.push(path);
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some((modified, metadata.len()))
+}
+
+/// Names, style and coverage of every face in a font file; empty if it can't be read
+fn index_file(path: &Path) -> Vec<IndexedFace> {
+    let Some(data) = font::map_file(path) else {
+        return Vec::new();
+    };
+    font::list_faces(&data)
+        .into_iter()
+        .filter_map(|info| {
+            let face = Face::parse(&data, info.index).ok()?;
+            Some(IndexedFace {
+                color: emoji::is_color_font(&face),
+                coverage: coverage(&face),
+                info,
+            })
+        })
+        .collect()
+}
+
+/// Codepoints the face maps to a real glyph, as ranges
+fn coverage(face: &Face) -> Vec<(u32, u32)> {
+    let mut codepoints = Vec::new();
+    if let Some(cmap) = face.tables().cmap {
+        for subtable in cmap.subtables.into_iter().filter(|s| s.is_unicode()) {
+            subtable.codepoints(|cp| codepoints.push(cp));
+        }
+    }
+    codepoints.sort_unstable();
+    codepoints.dedup();
+
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for cp in codepoints {
+        let mapped = char::from_u32(cp)
+            .and_then(|ch| face.glyph_index(ch))
+            .is_some_and(|gid| gid != ttf_parser::GlyphId(0));
+        if !mapped {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == cp => *end = cp,
+            _ => ranges.push((cp, cp)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_font::TestFont;
+
+    // Family, weight, italic and coverage of an indexed face
+    type FaceSummary = (String, u16, bool, Vec<(u32, u32)>);
+
+    fn face(family: &str, weight: u16, italic: bool, color: bool, chars: &str) -> IndexedFace {
+        let mut coverage: Vec<(u32, u32)> = Vec::new();
+        for cp in chars.chars().map(|ch| ch as u32) {
+            match coverage.last_mut() {
+                Some((_, end)) if *end + 1 == cp => *end = cp,
+                _ => coverage.push((cp, cp)),
+            }
+        }
+        IndexedFace {
+            info: FaceInfo {
+                index: 0,
+                family: family.to_string(),
+                subfamily: String::new(),
+                postscript_name: String::new(),
+                weight,
+                italic,
+                is_variable: false,
+            },
+            color,
+            coverage,
+        }
+    }
+
+    fn index(faces: Vec<IndexedFace>) -> FontIndex {
+        FontIndex {
+            files: faces
+                .into_iter()
+                .map(|face| IndexedFile {
+                    path: PathBuf::from(format!("/fonts/{}.ttf", face.info.family)),
+                    modified: 0,
+                    size: 0,
+                    faces: vec![face],
+                })
+                .collect(),
+        }
+    }
+
+    fn families(picked: Vec<(&Path, &IndexedFace)>) -> Vec<String> {
+        picked
+            .into_iter()
+            .map(|(_, face)| face.info.family.clone())
+            .collect()
+    }
+
+    #[test]
+    fn covers_ranges() {
+        let face = face("a", 400, false, false, "abcxyz");
+        assert!(face.covers('a') && face.covers('c') && face.covers('x') && face.covers('z'));
+        assert!(!face.covers('d') && !face.covers('w') && !face.covers('0'));
+    }
+
+    #[test]
+    fn picks_fewest_faces() {
+        let index = index(vec![
+            face("greek", 400, false, false, "αβ"),
+            face("kana", 400, false, false, "あい"),
+            face("both", 400, false, false, "αβあ"),
+        ]);
+        // "both" covers three of the four, then "kana" the last one
+        assert_eq!(
+            families(index.pick_fallbacks(&['α', 'β', 'あ', 'い'])),
+            ["both", "kana"]
+        );
+        // Nothing covers 'z'; it's left out instead of stopping the search
+        assert_eq!(families(index.pick_fallbacks(&['z', 'α'])), ["greek"]);
+        assert!(index.pick_fallbacks(&['z']).is_empty());
+    }
+
+    #[test]
+    fn breaks_ties_by_style() {
+        let index = index(vec![
+            face("italic", 400, true, false, "αβ"),
+            face("bold", 700, false, false, "αβ"),
+            face("regular", 400, false, false, "αβ"),
+            face("regular2", 400, false, false, "αβ"),
+        ]);
+        // Upright beats italic, nearer 400 beats bold, and the earlier file wins the rest
+        assert_eq!(families(index.pick_fallbacks(&['α'])), ["regular"]);
+    }
+
+    #[test]
+    fn prefers_color_fonts_for_emoji() {
+        let index = index(vec![
+            face("mono", 400, false, false, "#😀"),
+            face("color", 400, false, true, "#😀"),
+        ]);
+        assert_eq!(families(index.pick_fallbacks(&['😀'])), ["color"]);
+        // Without emoji among the missing chars, color fonts get no preference
+        assert_eq!(families(index.pick_fallbacks(&['#'])), ["mono"]);
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let fonts = dir.path().join("fonts");
+        std::fs::create_dir_all(fonts.join("nested")).unwrap();
+        let kana = fonts.join("nested/Kana.otf");
+        let font = TestFont::new("Kana").weight(300).chars("あい", 500);
+        std::fs::write(&kana, font.build()).unwrap();
+        std::fs::write(
+            fonts.join("Greek-Italic.ttf"),
+            TestFont::new("Greek").italic().chars("αβγ", 500).build(),
+        )
+        .unwrap();
+        std::fs::write(fonts.join("readme.txt"), "not a font").unwrap();
+        let cache = dir.path().join("cache/font-index.json");
+
+        let index = FontIndex::build(std::slice::from_ref(&fonts), Some(&cache));
+        let summary = |index: &FontIndex| -> Vec<FaceSummary> {
+            index
+                .files
+                .iter()
+                .flat_map(|file| &file.faces)
+                .map(|face| {
+                    let info = &face.info;
+                    (
+                        info.family.clone(),
+                        info.weight,
+                        info.italic,
+                        face.coverage.clone(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            summary(&index),
+            [
+                ("Greek".to_string(), 400, true, vec![(0x3B1, 0x3B3)]),
+                (
+                    "Kana".to_string(),
+                    300,
+                    false,
+                    vec![(0x3042, 0x3042), (0x3044, 0x3044)]
+                ),
+            ]
+        );
+        assert!(cache.is_file());
+
+        // A second build takes unchanged files from the cache: prove it by editing the
+        // cached entry, which is only seen if the file isn't read again
+        let mut cached = read_cache(&cache).unwrap();
+        cached[1].faces[0].info.family = "From cache".to_string();
+        let json = serde_json::to_vec(&IndexCache {
+            version: INDEX_VERSION,
+            files: cached,
+        })
+        .unwrap();
+        std::fs::write(&cache, json).unwrap();
+        let index = FontIndex::build(std::slice::from_ref(&fonts), Some(&cache));
+        assert_eq!(summary(&index)[1].0, "From cache");
+
+        // A file of another size is indexed again, a deleted one dropped
+        let font = TestFont::new("Kana").weight(300).chars("あいう", 500);
+        std::fs::write(&kana, font.build()).unwrap();
+        std::fs::remove_file(fonts.join("Greek-Italic.ttf")).unwrap();
+        let index = FontIndex::build(std::slice::from_ref(&fonts), Some(&cache));
+        assert_eq!(
+            summary(&index),
+            [(
+                "Kana".to_string(),
+                300,
+                false,
+                vec![(0x3042, 0x3042), (0x3044, 0x3044), (0x3046, 0x3046)]
+            )]
+        );
+        assert_eq!(read_cache(&cache).unwrap().len(), 1);
+
+        // An index from another version is ignored
+        let json = serde_json::to_vec(&IndexCache {
+            version: INDEX_VERSION + 1,
+            files: Vec::new(),
+        })
+        .unwrap();
+        std::fs::write(&cache, json).unwrap();
+        assert!(read_cache(&cache).is_none());
+    }
+
+    #[test]
+    fn discovery_registers_each_face_once() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Greek.ttf"),
+            TestFont::new("Greek").chars("αβ", 500).build(),
+        )
+        .unwrap();
+        let mut discovery = FontDiscovery::new(
+            vec![dir.path().to_path_buf()],
+            Some(dir.path().join("index.json")),
+        );
+        discovery.build_index();
+        let mut fonts = FontRegistry::new();
+
+        let first = discovery.fallbacks_for(&['α', 'z'], &mut fonts);
+        assert_eq!(first.len(), 1);
+        assert!(fonts.get(first[0]).is_some());
+        assert_eq!(discovery.fallbacks_for(&['β'], &mut fonts), first);
+        // 'z' is known to be missing now
+        assert!(discovery.fallbacks_for(&['z'], &mut fonts).is_empty());
+
+        // An unregistered face is registered again under a new ID
+        fonts.unregister(first[0]);
+        let again = discovery.fallbacks_for(&['α'], &mut fonts);
+        assert_eq!(again.len(), 1);
+        assert_ne!(again, first);
+    }
+}
@@ -0,0 +1,71 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Split text into extended grapheme clusters (UAX #29) as char offset ranges.
+/// Combining sequences, emoji ZWJ sequences, flags and Indic conjuncts each come
+/// out as a single range.
+pub fn cluster_ranges(chars: &[char]) -> Vec<(usize, usize)> {
+    let text: String = chars.iter().collect();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for grapheme in text.graphemes(true) {
+        let end = start + grapheme.chars().count();
+        ranges.push((start, end));
+        start = end;
+    }
+    ranges
+}
+
+/// Default_Ignorable_Code_Point chars that fonts routinely leave unmapped
+/// (joiners, variation selectors, bidi controls, tags). Shaping hides them,
+/// so they don't count against a font's coverage of a cluster.
+pub fn is_default_ignorable(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{061C}'
+            | '\u{115F}'..='\u{1160}'
+            | '\u{17B4}'..='\u{17B5}'
+            | '\u{180B}'..='\u{180F}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{206F}'
+            | '\u{3164}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FEFF}'
+            | '\u{FFA0}'
+            | '\u{1BCA0}'..='\u{1BCA3}'
+            | '\u{1D173}'..='\u{1D17A}'
+            | '\u{E0000}'..='\u{E0FFF}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(text: &str) -> Vec<(usize, usize)> {
+        cluster_ranges(&text.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn keeps_sequences_in_one_cluster() {
+        // e + combining acute, then a ZWJ family, then a flag
+        assert_eq!(
+            ranges("e\u{301}\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{1F1EF}\u{1F1F5}"),
+            [(0, 2), (2, 7), (7, 9)]
+        );
+        // A Devanagari conjunct (क्ष) with a vowel sign
+        assert_eq!(ranges("\u{915}\u{94D}\u{937}\u{93F}a"), [(0, 4), (4, 5)]);
+        assert_eq!(ranges(""), []);
+    }
+
+    #[test]
+    fn ignorable_chars() {
+        assert!(is_default_ignorable('\u{200D}'));
+        assert!(is_default_ignorable('\u{FE0F}'));
+        assert!(is_default_ignorable('\u{E0067}'));
+        assert!(!is_default_ignorable(' '));
+        assert!(!is_default_ignorable('\u{301}'));
+    }
+}
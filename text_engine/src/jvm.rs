@@ -1,15 +1,63 @@
 use jni::objects::{JByteBuffer, JObject, JString};
-use jni::sys::{jboolean, jbyteArray, jfloat, jint};
+use jni::sys::{jboolean, jbyteArray, jfloat, jint, jintArray, jobjectArray};
 use jni::JNIEnv;
 use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
-use crate::core::TextEngine;
+use crate::core::{LayoutOptions, TextEngine};
+use crate::font;
+use crate::font_discovery::FontDiscovery;
+use crate::registry::{FontId, DEFAULT_FONT_STACK};
+use crate::ruby::RubyAnnotation;
 
 // Global singleton for now, or use a handle map for multiple instances.
 // For simplicity in this demo, a global instance protected by a Mutex.
 pub static ENGINE: Lazy<Mutex<TextEngine>> = Lazy::new(|| Mutex::new(TextEngine::new(2048, 2048)));
 
+/// Parse layout options passed from Kotlin as a JSON object.
+/// Empty or malformed input falls back to the defaults.
+fn parse_layout_options(env: &JNIEnv, options: JString) -> LayoutOptions {
+    let json: String = env
+        .get_string(options)
+        .map(|s| s.into())
+        .unwrap_or_default();
+    LayoutOptions::from_json(&json)
+}
+
+/// Read a font stack name passed from Kotlin; empty or null means the default stack.
+fn read_stack_name(env: &JNIEnv, name: JString) -> String {
+    let name: String = env.get_string(name).map(|s| s.into()).unwrap_or_default();
+    if name.is_empty() {
+        DEFAULT_FONT_STACK.to_string()
+    } else {
+        name
+    }
+}
+
+/// Read font IDs passed from Kotlin as an IntArray, dropping negative ones.
+fn read_font_ids(env: &JNIEnv, font_ids: jintArray) -> Vec<FontId> {
+    let len = env.get_array_length(font_ids).unwrap_or(0).max(0) as usize;
+    let mut raw = vec![0 as jint; len];
+    if len > 0 && env.get_int_array_region(font_ids, 0, &mut raw).is_err() {
+        raw.clear();
+    }
+    raw.iter()
+        .filter(|&&id| id >= 0)
+        .map(|&id| id as FontId)
+        .collect()
+}
+
+/// Read paths passed from Kotlin as an Array<String>.
+fn read_paths(env: &JNIEnv, paths: jobjectArray) -> Vec<PathBuf> {
+    let len = env.get_array_length(paths).unwrap_or(0).max(0);
+    (0..len)
+        .filter_map(|i| env.get_object_array_element(paths, i).ok())
+        .filter_map(|path| env.get_string(JString::from(path)).ok())
+        .map(|path| PathBuf::from(String::from(path)))
+        .collect()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_init(
     _env: JNIEnv,
@@ -29,10 +77,11 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
     env: JNIEnv,
     _this: JObject,
     bytes: jbyteArray,
+    face_index: jint,
 ) {
     let byte_vec = env.convert_byte_array(bytes).unwrap_or_default();
     let mut engine = ENGINE.lock().unwrap();
-    engine.load_font(byte_vec);
+    engine.load_font(byte_vec, face_index.max(0) as u32);
 }
 
 #[no_mangle]
@@ -40,10 +89,11 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
     env: JNIEnv,
     _this: JObject,
     bytes: jbyteArray,
+    face_index: jint,
 ) {
     let byte_vec = env.convert_byte_array(bytes).unwrap_or_default();
     let mut engine = ENGINE.lock().unwrap();
-    engine.load_fallback_font(byte_vec);
+    engine.load_fallback_font(byte_vec, face_index.max(0) as u32);
 }
 
 /// Load a fallback font from a file descriptor (Android only).
@@ -53,11 +103,12 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
     _env: JNIEnv,
     _this: JObject,
     fd: jint,
+    face_index: jint,
 ) -> jboolean {
     let mut engine = ENGINE.lock().unwrap();
     #[cfg(unix)]
     {
-        if engine.load_fallback_font_from_fd(fd) {
+        if engine.load_fallback_font_from_fd(fd, face_index.max(0) as u32) {
             1
         } else {
             0
@@ -65,11 +116,97 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
     }
     #[cfg(not(unix))]
     {
-        let _ = fd;
+        let _ = (fd, face_index);
         0 // Not supported on non-Unix platforms
     }
 }
 
+/// List the faces of a font file (several for a TTC/OTC collection) as a JSON array.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_listFontFaces<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    bytes: jbyteArray,
+) -> JString<'local> {
+    let byte_vec = env.convert_byte_array(bytes).unwrap_or_default();
+    let faces = font::list_faces(&byte_vec);
+    let json = serde_json::to_string(&faces).unwrap_or_else(|_| "[]".to_string());
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("[]").unwrap())
+}
+
+/// List the faces of a font file behind a file descriptor (Android only), without
+/// copying it.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_listFontFacesFd<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    fd: jint,
+) -> JString<'local> {
+    #[cfg(unix)]
+    let faces = font::map_fd(fd).map_or_else(Vec::new, |mmap| font::list_faces(&mmap));
+    #[cfg(not(unix))]
+    let faces: Vec<font::FaceInfo> = {
+        let _ = fd;
+        Vec::new() // Not supported on non-Unix platforms
+    };
+    let json = serde_json::to_string(&faces).unwrap_or_else(|_| "[]".to_string());
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("[]").unwrap())
+}
+
+/// Build font stacks from an Android font config such as /system/etc/fonts.xml, with the
+/// font files looked up in `font_dirs` in order. Returns the number of stacks built.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_loadSystemFonts(
+    env: JNIEnv,
+    _this: JObject,
+    config_path: JString,
+    font_dirs: jobjectArray,
+) -> jint {
+    let config_path: String = env
+        .get_string(config_path)
+        .map(|s| s.into())
+        .unwrap_or_default();
+    let font_dirs = read_paths(&env, font_dirs);
+
+    let mut engine = ENGINE.lock().unwrap();
+    engine.load_system_fonts(Path::new(&config_path), &font_dirs) as jint
+}
+
+/// Turn fallback discovery among the installed fonts on or off. An empty `font_dirs`
+/// scans the standard directories and an empty `cache_path` keeps the index in the
+/// platform cache directory. The index is built before the engine is locked, so layouts
+/// on other threads carry on during the scan.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_setFontDiscovery(
+    env: JNIEnv,
+    _this: JObject,
+    enabled: jboolean,
+    font_dirs: jobjectArray,
+    cache_path: JString,
+) {
+    let font_dirs = read_paths(&env, font_dirs);
+    let cache_path: String = env
+        .get_string(cache_path)
+        .map(|s| s.into())
+        .unwrap_or_default();
+
+    let discovery = (enabled != 0).then(|| {
+        let cache_path = (!cache_path.is_empty()).then(|| PathBuf::from(cache_path));
+        let mut discovery = FontDiscovery::new(font_dirs, cache_path);
+        discovery.build_index();
+        discovery
+    });
+
+    let mut engine = ENGINE.lock().unwrap();
+    engine.set_font_discovery(discovery);
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_clearFallbackFonts(
     _env: JNIEnv,
@@ -79,6 +216,120 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
     engine.clear_fallback_fonts();
 }
 
+/// Register a font for use in font stacks. Returns its stable ID, or -1 if it doesn't parse.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_registerFont(
+    env: JNIEnv,
+    _this: JObject,
+    bytes: jbyteArray,
+    face_index: jint,
+) -> jint {
+    let byte_vec = env.convert_byte_array(bytes).unwrap_or_default();
+    let mut engine = ENGINE.lock().unwrap();
+    engine
+        .register_font(byte_vec, face_index.max(0) as u32)
+        .map_or(-1, |id| id as jint)
+}
+
+/// Register a font from a file descriptor (Android only), memory-mapped rather than copied.
+/// Returns its stable ID, or -1 on failure.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_registerFontFd(
+    _env: JNIEnv,
+    _this: JObject,
+    fd: jint,
+    face_index: jint,
+) -> jint {
+    let mut engine = ENGINE.lock().unwrap();
+    #[cfg(unix)]
+    {
+        engine
+            .register_font_from_fd(fd, face_index.max(0) as u32)
+            .map_or(-1, |id| id as jint)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (engine, fd, face_index);
+        -1 // Not supported on non-Unix platforms
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_unregisterFont(
+    _env: JNIEnv,
+    _this: JObject,
+    font_id: jint,
+) -> jboolean {
+    if font_id < 0 {
+        return 0;
+    }
+    let mut engine = ENGINE.lock().unwrap();
+    engine.unregister_font(font_id as usize) as jboolean
+}
+
+/// Create or replace a named font stack from registered font IDs, primary first.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_setFontStack(
+    env: JNIEnv,
+    _this: JObject,
+    name: JString,
+    font_ids: jintArray,
+) {
+    let name = read_stack_name(&env, name);
+    let font_ids = read_font_ids(&env, font_ids);
+
+    let mut engine = ENGINE.lock().unwrap();
+    engine.set_font_stack(&name, &font_ids);
+}
+
+/// Set a stack's fallback chain for a language/script tag such as "ja" or "zh-Hant";
+/// an empty array removes it. Returns false if the tag is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_setLocaleFallbacks(
+    env: JNIEnv,
+    _this: JObject,
+    locale: JString,
+    font_ids: jintArray,
+    stack: JString,
+) -> jboolean {
+    let locale: String = env.get_string(locale).map(|s| s.into()).unwrap_or_default();
+    let font_ids = read_font_ids(&env, font_ids);
+    let stack = read_stack_name(&env, stack);
+
+    let mut engine = ENGINE.lock().unwrap();
+    engine.set_locale_fallbacks(&stack, &locale, &font_ids) as jboolean
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_removeFontStack(
+    env: JNIEnv,
+    _this: JObject,
+    name: JString,
+) -> jboolean {
+    let name = read_stack_name(&env, name);
+    let mut engine = ENGINE.lock().unwrap();
+    engine.remove_font_stack(&name) as jboolean
+}
+
+/// Set the default BCP-47 language tag for shaping. An empty string clears it.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_setDefaultLanguage(
+    env: JNIEnv,
+    _this: JObject,
+    language: JString,
+) {
+    let language: String = env
+        .get_string(language)
+        .map(|s| s.into())
+        .unwrap_or_default();
+    let mut engine = ENGINE.lock().unwrap();
+    engine.set_default_language(if language.is_empty() {
+        None
+    } else {
+        Some(language)
+    });
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_processText<
     'local,
@@ -88,11 +339,223 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
     text: JString<'local>,
     size_fn: jfloat,
     weight: jfloat,
+    options: JString<'local>,
+) -> JString<'local> {
+    let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let layout_options = parse_layout_options(&env, options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_text(&text_str, size_fn, weight, &layout_options);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Report which chars of `text` the font stack selected by `options` can't render, as JSON
+/// (`uncovered_codepoints`, `uncovered_offsets`, `uncovered_offsets_utf16`). Only with
+/// `discover` set may font discovery add fonts to the stack first.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_checkCoverage<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    text: JString<'local>,
+    options: JString<'local>,
+    discover: jboolean,
 ) -> JString<'local> {
     let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let layout_options = parse_layout_options(&env, options);
 
     let mut engine = ENGINE.lock().unwrap();
-    let result = engine.process_text(&text_str, size_fn, weight);
+    let result = engine.check_coverage(&text_str, &layout_options, discover != 0);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Lay out a paragraph wrapped to `max_width` and return it as JSON:
+/// the usual layout fields plus `line_height` and a `lines` array.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_processParagraph<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    text: JString<'local>,
+    size_px: jfloat,
+    max_width: jfloat,
+    weight: jfloat,
+    options: JString<'local>,
+) -> JString<'local> {
+    let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let layout_options = parse_layout_options(&env, options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_paragraph(&text_str, size_px, weight, max_width, &layout_options);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Lay out text like `processText` but return vector glyph outlines as JSON
+/// (`verbs`, `points`, per-glyph `verb_starts`/`point_starts`) instead of atlas entries.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_processTextOutlines<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    text: JString<'local>,
+    size_px: jfloat,
+    weight: jfloat,
+    options: JString<'local>,
+) -> JString<'local> {
+    let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let layout_options = parse_layout_options(&env, options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_text_outlines(&text_str, size_px, weight, &layout_options);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Shape a line and return the caret x for every UTF-16 offset as JSON (`carets`),
+/// without generating glyphs.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_getCaretPositions<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    text: JString<'local>,
+    size_px: jfloat,
+    weight: jfloat,
+    options: JString<'local>,
+) -> JString<'local> {
+    let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let layout_options = parse_layout_options(&env, options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_carets(&text_str, size_px, weight, &layout_options);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Shape a line and return the caret offset closest to `x` as JSON
+/// (`offset`, `offset_utf16`, `caret_x`, `is_rtl`), without generating glyphs.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_hitTest<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    text: JString<'local>,
+    size_px: jfloat,
+    x: jfloat,
+    weight: jfloat,
+    options: JString<'local>,
+) -> JString<'local> {
+    let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let layout_options = parse_layout_options(&env, options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.hit_test(&text_str, size_px, weight, x, &layout_options);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Shape a whole lyric line once and return it as JSON: the usual layout fields plus
+/// a `syllables` array with each syllable's glyph range, `x` and `width`.
+/// `boundaries` holds the UTF-16 offsets between syllables (n - 1 for n syllables).
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_processSyllables<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    text: JString<'local>,
+    size_px: jfloat,
+    boundaries: jintArray,
+    weight: jfloat,
+    options: JString<'local>,
+) -> JString<'local> {
+    let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let len = env.get_array_length(boundaries).unwrap_or(0).max(0) as usize;
+    let mut raw = vec![0 as jint; len];
+    if len > 0 && env.get_int_array_region(boundaries, 0, &mut raw).is_err() {
+        raw.clear();
+    }
+    let boundaries_utf16: Vec<u32> = raw.iter().map(|&b| b.max(0) as u32).collect();
+    let layout_options = parse_layout_options(&env, options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_syllables(
+        &text_str,
+        size_px,
+        weight,
+        &boundaries_utf16,
+        &layout_options,
+    );
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Lay out text with ruby annotations and return it as JSON with `base` and `ruby`
+/// layers plus the placed `spans`. `annotations` is a JSON array of
+/// `{start, end, text, align}` objects; malformed input lays out the base text alone.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_processRuby<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+    text: JString<'local>,
+    size_px: jfloat,
+    ruby_size_px: jfloat,
+    annotations: JString<'local>,
+    weight: jfloat,
+    options: JString<'local>,
+) -> JString<'local> {
+    let text_str: String = env.get_string(text).map(|s| s.into()).unwrap_or_default();
+    let annotations_json: String = env
+        .get_string(annotations)
+        .map(|s| s.into())
+        .unwrap_or_default();
+    let ruby_annotations: Vec<RubyAnnotation> = serde_json::from_str(&annotations_json)
+        .unwrap_or_else(|e| {
+            warn!("Invalid ruby annotations {}: {}", annotations_json, e);
+            Vec::new()
+        });
+    let layout_options = parse_layout_options(&env, options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_ruby(
+        &text_str,
+        size_px,
+        ruby_size_px,
+        weight,
+        &ruby_annotations,
+        &layout_options,
+    );
 
     let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
 
@@ -156,6 +619,32 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
         .unwrap_or_else(|_| env.new_string("{}").unwrap())
 }
 
+/// Layout cache counters as JSON: `hits`, `misses`, `invalidations`, `entries`, `capacity`.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_getLayoutCacheStats<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _this: JObject<'local>,
+) -> JString<'local> {
+    let engine = ENGINE.lock().unwrap();
+    let json =
+        serde_json::to_string(&engine.layout_cache_stats()).unwrap_or_else(|_| "{}".to_string());
+    env.new_string(&json)
+        .unwrap_or_else(|_| env.new_string("{}").unwrap())
+}
+
+/// Set how many layouts `processText` keeps; 0 turns the cache off.
+#[no_mangle]
+pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextEngine_setLayoutCacheCapacity(
+    _env: JNIEnv,
+    _this: JObject,
+    capacity: jint,
+) {
+    let mut engine = ENGINE.lock().unwrap();
+    engine.set_layout_cache_capacity(capacity.max(0) as usize);
+}
+
 /// Clear all cached data and reset the engine.
 /// Call this when done with text rendering to free memory.
 #[no_mangle]
@@ -170,7 +659,7 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
 // Simple base64 encoder (no padding for simplicity)
 fn base64_encode(data: &[u8]) -> String {
     const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-    let mut result = String::with_capacity((data.len() + 2) / 3 * 4);
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
 
     for chunk in data.chunks(3) {
         let b0 = chunk[0] as usize;
@@ -212,6 +701,12 @@ fn write_f32(buf: &mut [u8], offset: usize, val: f32) {
     buf[offset..offset + 4].copy_from_slice(&bytes);
 }
 
+/// Helper to write u32 to buffer at offset
+fn write_u32(buf: &mut [u8], offset: usize, val: u32) {
+    let bytes = val.to_ne_bytes();
+    buf[offset..offset + 4].copy_from_slice(&bytes);
+}
+
 /// Helper to write u16 to buffer at offset
 fn write_u16(buf: &mut [u8], offset: usize, val: u16) {
     let bytes = val.to_ne_bytes();
@@ -221,7 +716,7 @@ fn write_u16(buf: &mut [u8], offset: usize, val: u16) {
 /// Process text and write layout results directly into a DirectByteBuffer.
 /// Returns the number of glyphs written, or -1 on error.
 ///
-/// Buffer layout (per glyph, 28 bytes each):
+/// Buffer layout (per glyph, 36 bytes each):
 /// - offset 0:  u16  glyph_id
 /// - offset 2:  u16  reserved (padding)
 /// - offset 4:  f32  x_position
@@ -230,6 +725,8 @@ fn write_u16(buf: &mut [u8], offset: usize, val: u16) {
 /// - offset 16: f32  atlas_y (v in atlas, normalized 0-1)
 /// - offset 20: f32  atlas_w (width in atlas, normalized 0-1)
 /// - offset 24: f32  atlas_h (height in atlas, normalized 0-1)
+/// - offset 28: u32  cluster (char offset in the input text)
+/// - offset 32: u32  cluster_utf16 (UTF-16 code unit offset in the input text)
 ///
 /// Header (16 bytes):
 /// - offset 0:  i32  glyph_count
@@ -243,6 +740,7 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
     text: JString,
     size_px: jfloat,
     weight: jfloat,
+    options: JString,
     buffer: JByteBuffer,
 ) -> jint {
     // Get text string
@@ -250,6 +748,7 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
         Ok(s) => s.into(),
         Err(_) => return -1,
     };
+    let layout_options = parse_layout_options(&env, options);
 
     // Get direct buffer as mutable slice
     let buf: &mut [u8] = match env.get_direct_buffer_address(buffer) {
@@ -261,11 +760,11 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
 
     // Process text
     let mut engine = ENGINE.lock().unwrap();
-    let result = engine.process_text(&text_str, size_px, weight);
+    let result = engine.process_text(&text_str, size_px, weight, &layout_options);
 
     // Calculate required size
     let header_size = 16; // 4 i32/f32 values
-    let glyph_size = 28; // per glyph data
+    let glyph_size = 36; // per glyph data
     let required_size = header_size + result.glyph_count * glyph_size;
 
     if buffer_capacity < required_size {
@@ -288,7 +787,7 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
         let offset = header_size + i * glyph_size;
 
         // Glyph ID (u16)
-        write_u16(buf, offset, result.glyph_ids[i] as u16);
+        write_u16(buf, offset, result.glyph_ids[i]);
 
         // Reserved padding (u16)
         write_u16(buf, offset + 2, 0);
@@ -316,6 +815,10 @@ pub unsafe extern "C" fn Java_com_mocharealm_accompanist_lyrics_text_NativeTextE
             offset + 24,
             result.atlas_rects[rect_idx + 3] / atlas_h_f,
         );
+
+        // Cluster offsets (u32 char offset, u32 UTF-16 offset)
+        write_u32(buf, offset + 28, result.clusters[i]);
+        write_u32(buf, offset + 32, result.clusters_utf16[i]);
     }
 
     result.glyph_count as jint
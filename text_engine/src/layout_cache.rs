@@ -0,0 +1,301 @@
+use crate::atlas::{GlyphCacheKey, Rect};
+use crate::core::{LayoutOptions, LayoutResult};
+use crate::syllable::SyllableSpan;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Default number of layouts kept by `LayoutCache`
+pub const DEFAULT_LAYOUT_CACHE_CAPACITY: usize = 256;
+
+/// Everything a `process_text` or `process_syllables` result depends on besides the
+/// engine's fonts, language and atlas, which clear the cache when they change.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct LayoutCacheKey {
+    text: String,
+    size_px: u32, // f32 bits
+    weight: u32,  // f32 bits
+    options: LayoutOptions,
+    boundaries: Option<Vec<u32>>, // Syllable boundaries; None for `process_text`
+}
+
+impl LayoutCacheKey {
+    pub fn new(text: &str, size_px: f32, weight: f32, options: &LayoutOptions) -> Self {
+        Self {
+            text: text.to_string(),
+            size_px: size_px.to_bits(),
+            weight: weight.to_bits(),
+            options: options.clone(),
+            boundaries: None,
+        }
+    }
+
+    /// Key of a `process_syllables` line split at `boundaries_utf16`
+    pub fn syllables(
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        boundaries_utf16: &[u32],
+        options: &LayoutOptions,
+    ) -> Self {
+        Self {
+            boundaries: Some(boundaries_utf16.to_vec()),
+            ..Self::new(text, size_px, weight, options)
+        }
+    }
+}
+
+struct CachedLayout {
+    result: LayoutResult,
+    syllables: Vec<SyllableSpan>, // Empty for `process_text` layouts
+    glyphs: Vec<(GlyphCacheKey, Rect)>, // Atlas entry of each glyph, to spot evictions
+    last_used: u64,
+}
+
+/// Hit/miss counters of the layout cache, as reported over JNI.
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct LayoutCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64, // Hits dropped because atlas entries they used were evicted
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// LRU cache of finished layouts, so repeated `process_text` and `process_syllables`
+/// calls for the same text skip shaping and atlas lookups.
+pub struct LayoutCache {
+    entries: HashMap<LayoutCacheKey, CachedLayout>,
+    capacity: usize,
+    access_counter: u64,
+    stats: LayoutCacheStats,
+}
+
+impl LayoutCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+            access_counter: 0,
+            stats: LayoutCacheStats::default(),
+        }
+    }
+
+    /// Look up a layout. `is_resident` is asked whether each atlas entry the layout uses
+    /// is still at the same rect (and should refresh its LRU timestamp); if any has been
+    /// evicted or moved, the layout is dropped and the lookup counts as a miss.
+    pub fn get(
+        &mut self,
+        key: &LayoutCacheKey,
+        mut is_resident: impl FnMut(&GlyphCacheKey, &Rect) -> bool,
+    ) -> Option<(LayoutResult, Vec<SyllableSpan>)> {
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+
+        let resident = entry
+            .glyphs
+            .iter()
+            .all(|(glyph_key, rect)| is_resident(glyph_key, rect));
+        if !resident {
+            self.entries.remove(key);
+            self.stats.invalidations += 1;
+            self.stats.misses += 1;
+            return None;
+        }
+
+        self.access_counter += 1;
+        entry.last_used = self.access_counter;
+        self.stats.hits += 1;
+        Some((entry.result.clone(), entry.syllables.clone()))
+    }
+
+    /// Store a layout (and its syllables, if any) along with the atlas entry of each of
+    /// its glyphs, evicting the least recently used layout if the cache is full.
+    pub fn insert(
+        &mut self,
+        key: LayoutCacheKey,
+        result: LayoutResult,
+        syllables: Vec<SyllableSpan>,
+        glyphs: Vec<(GlyphCacheKey, Rect)>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.evict_oldest();
+        }
+
+        self.access_counter += 1;
+        self.entries.insert(
+            key,
+            CachedLayout {
+                result,
+                syllables,
+                glyphs,
+                last_used: self.access_counter,
+            },
+        );
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.entries.remove(&oldest);
+        }
+    }
+
+    /// Change how many layouts are kept (0 disables caching), dropping the oldest ones.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.evict_oldest();
+        }
+    }
+
+    /// Drop every layout, keeping the counters
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn stats(&self) -> LayoutCacheStats {
+        LayoutCacheStats {
+            entries: self.entries.len(),
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthesis::Synthesis;
+
+    fn key(text: &str) -> LayoutCacheKey {
+        LayoutCacheKey::new(text, 32.0, 400.0, &LayoutOptions::default())
+    }
+
+    /// A layout told apart by its glyph count, with one glyph at `rect`
+    fn layout(glyph_count: usize, rect: Rect) -> (LayoutResult, Vec<(GlyphCacheKey, Rect)>) {
+        let result = LayoutResult {
+            glyph_count,
+            ..Default::default()
+        };
+        let glyph = (0, glyph_count as u16, 32, 0, Synthesis::default(), false);
+        (result, vec![(glyph, rect)])
+    }
+
+    fn rect(x: u32) -> Rect {
+        Rect {
+            x,
+            y: 0,
+            width: 10,
+            height: 10,
+        }
+    }
+
+    fn insert(cache: &mut LayoutCache, text: &str, glyph_count: usize) {
+        let (result, glyphs) = layout(glyph_count, rect(0));
+        cache.insert(key(text), result, Vec::new(), glyphs);
+    }
+
+    fn get(cache: &mut LayoutCache, text: &str) -> Option<usize> {
+        cache
+            .get(&key(text), |_, _| true)
+            .map(|(result, _)| result.glyph_count)
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = LayoutCache::new(2);
+        insert(&mut cache, "a", 1);
+        insert(&mut cache, "b", 2);
+        // Using "a" makes "b" the oldest
+        assert_eq!(get(&mut cache, "a"), Some(1));
+        insert(&mut cache, "c", 3);
+        assert_eq!(get(&mut cache, "b"), None);
+        assert_eq!(get(&mut cache, "a"), Some(1));
+        assert_eq!(get(&mut cache, "c"), Some(3));
+
+        // Replacing an entry doesn't evict another
+        insert(&mut cache, "c", 4);
+        assert_eq!(get(&mut cache, "a"), Some(1));
+        assert_eq!(get(&mut cache, "c"), Some(4));
+
+        // Shrinking drops the oldest first
+        cache.set_capacity(1);
+        assert_eq!(get(&mut cache, "a"), None);
+        assert_eq!(get(&mut cache, "c"), Some(4));
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let mut cache = LayoutCache::new(0);
+        insert(&mut cache, "a", 1);
+        assert_eq!(get(&mut cache, "a"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = LayoutCache::new(4);
+        assert_eq!(get(&mut cache, "a"), None);
+        insert(&mut cache, "a", 1);
+        insert(&mut cache, "b", 2);
+        get(&mut cache, "a");
+        get(&mut cache, "a");
+        get(&mut cache, "b");
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (3, 1, 0));
+        assert_eq!((stats.entries, stats.capacity), (2, 4));
+
+        // Clearing drops the entries but keeps the counters
+        cache.clear();
+        assert_eq!(get(&mut cache, "a"), None);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 2, 0));
+    }
+
+    #[test]
+    fn rejects_layouts_with_moved_glyphs() {
+        let mut cache = LayoutCache::new(4);
+        let (result, glyphs) = layout(1, rect(0));
+        let glyph = glyphs[0].0;
+        cache.insert(key("a"), result, Vec::new(), glyphs);
+
+        // The glyph is still resident at the same rect
+        let atlas = |at: Rect| move |k: &GlyphCacheKey, r: &Rect| *k == glyph && *r == at;
+        assert!(cache.get(&key("a"), atlas(rect(0))).is_some());
+
+        // It was evicted and rendered again elsewhere: the layout points at stale pixels
+        assert!(cache.get(&key("a"), atlas(rect(20))).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (1, 1, 1));
+        assert_eq!(stats.entries, 0);
+        // It's gone, not just skipped
+        assert!(cache.get(&key("a"), atlas(rect(0))).is_none());
+        assert_eq!(cache.stats().invalidations, 1);
+    }
+
+    #[test]
+    fn keeps_syllable_layouts_apart() {
+        let mut cache = LayoutCache::new(4);
+        let options = LayoutOptions::default();
+        let syllables_key = LayoutCacheKey::syllables("ab", 32.0, 400.0, &[1], &options);
+        let spans = vec![SyllableSpan::default(), SyllableSpan::default()];
+        let (result, glyphs) = layout(2, rect(0));
+        cache.insert(syllables_key.clone(), result, spans, glyphs);
+
+        assert_eq!(get(&mut cache, "ab"), None);
+        let other = LayoutCacheKey::syllables("ab", 32.0, 400.0, &[], &options);
+        assert!(cache.get(&other, |_, _| true).is_none());
+        let (result, syllables) = cache.get(&syllables_key, |_, _| true).unwrap();
+        assert_eq!((result.glyph_count, syllables.len()), (2, 2));
+    }
+}
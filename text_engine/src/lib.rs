@@ -1,11 +1,32 @@
 #[macro_use]
 extern crate log;
 
+mod android_fonts;
 mod atlas;
+mod bidi;
+mod caret;
 mod core;
+mod emoji;
 mod font;
+mod font_discovery;
+mod grapheme;
 mod jvm;
+mod layout_cache;
+mod locale;
+mod metrics;
 mod native;
+mod outline;
+mod paragraph;
+mod registry;
+mod ruby;
+mod script;
+mod spacing;
+mod syllable;
+mod synthesis;
+#[cfg(test)]
+mod test_font;
+mod variation;
+mod vertical;
 
 /// Initialize logger - call this early from JNI init
 #[cfg(target_os = "android")]
@@ -0,0 +1,182 @@
+/// The parts of a BCP-47 tag that locale fallback rules look at: language, script and region.
+/// Extensions, variants and private use subtags are dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locale {
+    language: Option<String>, // Lowercase; None for "und" or a bare script tag
+    script: Option<String>,   // Title case, e.g. "Hant"
+    region: Option<String>,   // Uppercase, e.g. "TW" or "419"
+}
+
+impl Locale {
+    /// Parse a tag such as "ja", "zh-Hant", "zh_TW", "und-Arab" or just "Hant".
+    /// Case doesn't matter and `_` works as a separator too. Returns None for an empty
+    /// or malformed tag.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut subtags = tag.trim().split(['-', '_']).peekable();
+        let is_alpha = |s: &str, len: std::ops::RangeInclusive<usize>| {
+            len.contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphabetic())
+        };
+
+        let first = subtags.next()?;
+        let language = if is_alpha(first, 2..=3) {
+            let language = first.to_ascii_lowercase();
+            (language != "und").then_some(language)
+        } else if is_alpha(first, 4..=4) {
+            return Some(Self {
+                language: None,
+                script: Some(title_case(first)),
+                region: None,
+            });
+        } else {
+            return None;
+        };
+
+        let script = subtags.next_if(|s| is_alpha(s, 4..=4)).map(title_case);
+        let region = subtags
+            .next_if(|s| {
+                is_alpha(s, 2..=2) || (s.len() == 3 && s.chars().all(|c| c.is_ascii_digit()))
+            })
+            .map(|s| s.to_ascii_uppercase());
+
+        Some(Self {
+            language,
+            script,
+            region,
+        })
+    }
+
+    /// The explicit script, or the one the language is almost always written in where that
+    /// decides which Han glyphs to use: Traditional for Chinese in Taiwan, Hong Kong and Macau,
+    /// Simplified for other Chinese, and the Japanese and Korean mixes.
+    fn likely_script(&self) -> Option<&str> {
+        if let Some(script) = &self.script {
+            return Some(script);
+        }
+        match (self.language.as_deref()?, self.region.as_deref()) {
+            ("zh", Some("TW" | "HK" | "MO")) => Some("Hant"),
+            ("zh", _) => Some("Hans"),
+            ("ja", _) => Some("Jpan"),
+            ("ko", _) => Some("Kore"),
+            _ => None,
+        }
+    }
+
+    /// How specifically `rule` applies to text in this locale, or None if it doesn't.
+    ///
+    /// Every subtag the rule has must match: the script against the likely one, so "zh-Hant"
+    /// covers "zh-TW". Region counts most, then script, then language, so "und-Hant" beats
+    /// "zh" for Traditional Chinese text.
+    pub fn match_rule(&self, rule: &Locale) -> Option<u32> {
+        let mut score = 0;
+        if let Some(language) = &rule.language {
+            if self.language.as_ref() != Some(language) {
+                return None;
+            }
+            score += 1;
+        }
+        if let Some(script) = &rule.script {
+            if self.likely_script() != Some(script.as_str()) {
+                return None;
+            }
+            score += 2;
+        }
+        if let Some(region) = &rule.region {
+            if self.region.as_ref() != Some(region) {
+                return None;
+            }
+            score += 4;
+        }
+        Some(score)
+    }
+}
+
+fn title_case(subtag: &str) -> String {
+    let lower = subtag.to_ascii_lowercase();
+    let mut chars = lower.chars();
+    chars
+        .next()
+        .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).unwrap()
+    }
+
+    fn score(text: &str, rule: &str) -> Option<u32> {
+        locale(text).match_rule(&locale(rule))
+    }
+
+    fn parts(tag: &str) -> (Option<String>, Option<String>, Option<String>) {
+        let Locale {
+            language,
+            script,
+            region,
+        } = locale(tag);
+        (language, script, region)
+    }
+
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn parses_tags() {
+        assert_eq!(parts("ja"), (some("ja"), None, None));
+        assert_eq!(parts("ja_JP"), (some("ja"), None, some("JP")));
+        assert_eq!(parts("ZH-hant-tw"), (some("zh"), some("Hant"), some("TW")));
+        assert_eq!(parts("es-419"), (some("es"), None, some("419")));
+        assert_eq!(parts("und-Arab"), (None, some("Arab"), None));
+        assert_eq!(parts("Hant"), (None, some("Hant"), None));
+        // Variants and extensions are dropped
+        assert_eq!(
+            parts("de-DE-1996-u-co-phonebk"),
+            (some("de"), None, some("DE"))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        for tag in ["", "  ", "j", "japanese", "12", "-ja"] {
+            assert!(Locale::parse(tag).is_none(), "{:?}", tag);
+        }
+    }
+
+    #[test]
+    fn scores_more_specific_rules_higher() {
+        // Exact tag, then language + script, then language only
+        assert_eq!(score("zh-Hant-TW", "zh-Hant-TW"), Some(7));
+        assert_eq!(score("zh-Hant-TW", "zh-Hant"), Some(3));
+        assert_eq!(score("zh-Hant-TW", "zh"), Some(1));
+        assert!(score("zh-Hant-TW", "zh-Hant") > score("zh-Hant-TW", "zh"));
+        // A script-only rule beats a language-only one
+        assert_eq!(score("zh-Hant-TW", "und-Hant"), Some(2));
+        assert_eq!(score("ja", "und"), Some(0));
+    }
+
+    #[test]
+    fn rejects_rules_that_disagree() {
+        assert_eq!(score("zh-Hant-TW", "zh-Hans"), None);
+        assert_eq!(score("zh-Hant-TW", "zh-Hant-HK"), None);
+        assert_eq!(score("ja", "zh"), None);
+        // A rule's region has to be matched, not just left open by the text
+        assert_eq!(score("zh-Hant", "zh-Hant-TW"), None);
+    }
+
+    #[test]
+    fn infers_the_script_of_han_languages() {
+        assert_eq!(score("zh-TW", "zh-Hant"), Some(3));
+        assert_eq!(score("zh_HK", "und-Hant"), Some(2));
+        assert_eq!(score("zh-CN", "zh-Hans"), Some(3));
+        assert_eq!(score("zh", "zh-Hant"), None);
+        assert_eq!(score("ja_JP", "und-Jpan"), Some(2));
+        assert_eq!(score("ko", "und-Kore"), Some(2));
+        // An explicit script wins over the region's
+        assert_eq!(score("zh-Hans-HK", "zh-Hant"), None);
+        assert_eq!(score("en", "und-Latn"), None);
+    }
+}
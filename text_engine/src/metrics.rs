@@ -0,0 +1,133 @@
+use rustybuzz::ttf_parser::Face;
+use serde::{Deserialize, Serialize};
+
+/// Font-wide metrics of a layout's primary font, in pixels.
+///
+/// Like `ascent` and `descent`, everything is measured up from the baseline, so
+/// descents and underline positions are usually negative. Positions are the top edge
+/// of the stroke, as in the `post` and `OS/2` tables.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct FontMetrics {
+    pub cap_height: f32,
+    pub x_height: f32,
+    pub line_gap: f32,
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    pub strikeout_position: f32,
+    pub strikeout_thickness: f32,
+    pub typo_ascent: f32,
+    pub typo_descent: f32,
+    pub typo_line_gap: f32,
+    pub win_ascent: f32,
+    pub win_descent: f32,
+}
+
+/// Height of a letter's outline above the baseline, for fonts whose `OS/2` predates
+/// cap height and x-height.
+fn letter_height(face: &Face, letter: char) -> Option<i16> {
+    let glyph = face.glyph_index(letter)?;
+    face.glyph_bounding_box(glyph).map(|bbox| bbox.y_max)
+}
+
+impl FontMetrics {
+    /// Read the metrics of `face` at `size_px`, with its current variations applied where
+    /// the font has `MVAR` deltas. Values the font leaves out are derived from the ones it
+    /// has, the way browsers do.
+    pub fn from_face(face: &Face, size_px: f32) -> Self {
+        let units_per_em = face.units_per_em() as f32;
+        let scale = size_px / units_per_em;
+
+        let x_height = face
+            .x_height()
+            .filter(|&h| h > 0)
+            .or_else(|| letter_height(face, 'x'))
+            .map_or(0.0, f32::from);
+        let cap_height = face
+            .capital_height()
+            .filter(|&h| h > 0)
+            .or_else(|| letter_height(face, 'H'))
+            .map_or(0.0, f32::from);
+
+        let (underline_position, underline_thickness) = face
+            .underline_metrics()
+            .filter(|m| m.thickness > 0)
+            .map_or((-units_per_em / 10.0, units_per_em / 20.0), |m| {
+                (f32::from(m.position), f32::from(m.thickness))
+            });
+        let (strikeout_position, strikeout_thickness) =
+            face.strikeout_metrics().filter(|m| m.thickness > 0).map_or(
+                ((x_height + underline_thickness) / 2.0, underline_thickness),
+                |m| (f32::from(m.position), f32::from(m.thickness)),
+            );
+
+        let os2 = face.tables().os2;
+        FontMetrics {
+            cap_height: cap_height * scale,
+            x_height: x_height * scale,
+            line_gap: f32::from(face.line_gap()) * scale,
+            underline_position: underline_position * scale,
+            underline_thickness: underline_thickness * scale,
+            strikeout_position: strikeout_position * scale,
+            strikeout_thickness: strikeout_thickness * scale,
+            typo_ascent: f32::from(face.typographic_ascender().unwrap_or(face.ascender())) * scale,
+            typo_descent: f32::from(face.typographic_descender().unwrap_or(face.descender()))
+                * scale,
+            typo_line_gap: f32::from(face.typographic_line_gap().unwrap_or(face.line_gap()))
+                * scale,
+            win_ascent: f32::from(os2.map_or(face.ascender(), |os2| os2.windows_ascender()))
+                * scale,
+            win_descent: f32::from(os2.map_or(face.descender(), |os2| os2.windows_descender()))
+                * scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{LayoutOptions, TextEngine};
+    use crate::test_font::TestFont;
+
+    fn metrics(font: TestFont) -> FontMetrics {
+        let data = font.build();
+        FontMetrics::from_face(&Face::parse(&data, 0).unwrap(), 100.0)
+    }
+
+    #[test]
+    fn reads_metrics_in_px() {
+        let m = metrics(TestFont::new("Test").heights(500, 650));
+        assert_eq!((m.x_height, m.cap_height), (50.0, 65.0));
+        assert_eq!((m.underline_position, m.underline_thickness), (-10.0, 5.0));
+        assert_eq!((m.strikeout_position, m.strikeout_thickness), (30.0, 5.0));
+        assert_eq!((m.typo_ascent, m.typo_descent), (80.0, -20.0));
+        assert_eq!((m.win_ascent, m.win_descent), (80.0, -20.0));
+        assert_eq!(m.line_gap, 0.0);
+    }
+
+    #[test]
+    fn measures_letters_without_os2_heights() {
+        // Glyph boxes are 700 units tall
+        let m = metrics(TestFont::new("Test").chars("xH", 500));
+        assert_eq!((m.x_height, m.cap_height), (70.0, 70.0));
+        assert_eq!(metrics(TestFont::new("Test")).x_height, 0.0);
+    }
+
+    #[test]
+    fn layouts_carry_the_primary_font_metrics() {
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(
+            TestFont::new("Test")
+                .heights(500, 650)
+                .chars("a", 500)
+                .build(),
+            0,
+        );
+        let result = engine.process_text("a", 20.0, 400.0, &LayoutOptions::default());
+        assert_eq!(
+            (result.metrics.x_height, result.metrics.cap_height),
+            (10.0, 13.0)
+        );
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["cap_height"], 13.0);
+    }
+}
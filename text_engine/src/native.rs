@@ -1,3 +1,4 @@
+use crate::core::LayoutOptions;
 use crate::jvm::ENGINE;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
@@ -9,13 +10,66 @@ pub extern "C" fn text_engine_process(input: *const c_char) -> *mut c_char {
 
     let mut engine = ENGINE.lock().unwrap();
     // Use a fixed size for C-API demo or pass it in
-    let result = engine.process_text(input_str, 24.0, 400.0);
+    let result = engine.process_text(input_str, 24.0, 400.0, &LayoutOptions::default());
 
     let output = format!("Processed: {} metrics", result.glyph_count);
 
     CString::new(output).unwrap().into_raw()
 }
 
+/// Parse a JSON options object (see `LayoutOptions::from_json`); null means the defaults.
+fn read_layout_options(options: *const c_char) -> LayoutOptions {
+    if options.is_null() {
+        return LayoutOptions::default();
+    }
+    let options_str = unsafe { CStr::from_ptr(options) }.to_str().unwrap_or("");
+    LayoutOptions::from_json(options_str)
+}
+
+/// Lay out text and return the full `LayoutResult` as a JSON string.
+/// `options` is a JSON object with the same keys as the JNI `processText` options
+/// (may be null). Free the result with `text_engine_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn text_engine_process_with_options(
+    input: *const c_char,
+    size_px: f32,
+    weight: f32,
+    options: *const c_char,
+) -> *mut c_char {
+    let c_str = unsafe { CStr::from_ptr(input) };
+    let input_str = c_str.to_str().unwrap_or("");
+
+    let layout_options = read_layout_options(options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_text(input_str, size_px, weight, &layout_options);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    CString::new(json).unwrap().into_raw()
+}
+
+/// Lay out text and return its glyph outlines (`OutlineResult`) as a JSON string.
+/// Arguments as for `text_engine_process_with_options`; free the result with
+/// `text_engine_free_string`.
+#[unsafe(no_mangle)]
+pub extern "C" fn text_engine_process_outlines(
+    input: *const c_char,
+    size_px: f32,
+    weight: f32,
+    options: *const c_char,
+) -> *mut c_char {
+    let c_str = unsafe { CStr::from_ptr(input) };
+    let input_str = c_str.to_str().unwrap_or("");
+
+    let layout_options = read_layout_options(options);
+
+    let mut engine = ENGINE.lock().unwrap();
+    let result = engine.process_text_outlines(input_str, size_px, weight, &layout_options);
+
+    let json = serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string());
+    CString::new(json).unwrap().into_raw()
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn text_engine_free_string(ptr: *mut c_char) {
     if !ptr.is_null() {
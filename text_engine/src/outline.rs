@@ -0,0 +1,116 @@
+use crate::core::{LayoutOptions, TextEngine};
+use serde::Serialize;
+
+/// Vector outlines for every glyph of a layout, ready to be drawn as paths.
+///
+/// Glyphs come in the same order as the `LayoutResult` of `process_text` for the same
+/// arguments. Points are in pixels in the drawing frame of the layout: x to the right
+/// from its left edge and y down from its top, so the baseline sits at `ascent`
+/// (vertical layouts start at the column's top-left corner instead).
+#[derive(Serialize, Default)]
+pub struct OutlineResult {
+    pub glyph_count: usize,
+    pub glyph_ids: Vec<u16>,
+    pub clusters_utf16: Vec<u32>,
+    pub verbs: Vec<u8>, // 0 = move (1 point), 1 = line (1), 2 = quad (2), 3 = cubic (3), 4 = close (0)
+    pub points: Vec<f32>, // x, y interleaved
+    pub verb_starts: Vec<u32>, // Index of each glyph's first verb, plus a final entry for the end
+    pub point_starts: Vec<u32>, // Index of each glyph's first point (x, y pair), plus a final entry
+    pub total_width: f32,
+    pub total_height: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub is_vertical: bool,
+}
+
+impl TextEngine {
+    /// Lay out text like `process_text`, but return glyph outlines instead of atlas entries.
+    /// Nothing is rasterized, so this suits very large sizes and export. Glyphs with no
+    /// outline (bitmap emoji) get an empty path.
+    pub fn process_text_outlines(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        options: &LayoutOptions,
+    ) -> OutlineResult {
+        if !self.has_font(options) || text.is_empty() {
+            return OutlineResult::default();
+        }
+
+        let shaped = self.shape_text(text, size_px, weight, options);
+
+        let mut utf16_offsets: Vec<u32> = Vec::with_capacity(text.len() + 1);
+        let mut utf16_cursor: u32 = 0;
+        for ch in text.chars() {
+            utf16_offsets.push(utf16_cursor);
+            utf16_cursor += ch.len_utf16() as u32;
+        }
+        utf16_offsets.push(utf16_cursor);
+
+        let mut result = OutlineResult {
+            glyph_count: shaped.glyphs.len(),
+            ascent: shaped.ascent,
+            descent: shaped.descent,
+            is_vertical: shaped.is_vertical,
+            ..Default::default()
+        };
+
+        // Same pen walk as `build_layout`
+        let baseline = if shaped.is_vertical {
+            0.0
+        } else {
+            shaped.ascent
+        };
+        let mut x_cursor: f32 = if shaped.is_vertical {
+            shaped.height / 2.0
+        } else {
+            0.0
+        };
+        let mut y_cursor: f32 = 0.0;
+
+        for glyph in &shaped.glyphs {
+            result.glyph_ids.push(glyph.glyph_id);
+            result
+                .clusters_utf16
+                .push(utf16_offsets[glyph.cluster as usize]);
+            result.verb_starts.push(result.verbs.len() as u32);
+            result.point_starts.push((result.points.len() / 2) as u32);
+
+            let origin_x = x_cursor + glyph.x_offset;
+            let origin_y = baseline + y_cursor + glyph.y_offset;
+            x_cursor += glyph.x_advance;
+            y_cursor += glyph.y_advance;
+
+            let Some(outline) = self.glyph_outline(
+                shaped.fonts[glyph.font_index],
+                glyph.glyph_id,
+                size_px,
+                &shaped.variations[glyph.font_index],
+                shaped.synthesis[glyph.font_index],
+            ) else {
+                continue;
+            };
+
+            // Font space is y up; sideways glyphs are also turned 90° clockwise
+            for point in outline.points.chunks(2) {
+                let (px, py) = (point[0], point[1]);
+                let (dx, dy) = if glyph.sideways { (py, px) } else { (px, -py) };
+                result.points.push(origin_x + dx);
+                result.points.push(origin_y + dy);
+            }
+            result.verbs.extend(&outline.verbs);
+        }
+        result.verb_starts.push(result.verbs.len() as u32);
+        result.point_starts.push((result.points.len() / 2) as u32);
+
+        if shaped.is_vertical {
+            result.total_width = shaped.height;
+            result.total_height = y_cursor;
+        } else {
+            result.total_width = x_cursor;
+            result.total_height = shaped.height;
+        }
+        result
+    }
+}
@@ -0,0 +1,407 @@
+use crate::bidi::ParagraphDirection;
+use crate::core::{LayoutOptions, LayoutResult, TextEngine};
+use crate::vertical::Orientation;
+use serde::Serialize;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+/// One line of a wrapped paragraph.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ParagraphLine {
+    pub text_start: u32, // Char offset of the first char (inclusive)
+    pub text_end: u32,   // Char offset past the last char, including trailing whitespace
+    pub text_start_utf16: u32,
+    pub text_end_utf16: u32,
+    pub glyph_start: u32, // Range of this line's glyphs in the flat layout arrays
+    pub glyph_end: u32,
+    pub width: f32,    // Advance width, trailing whitespace excluded
+    pub top: f32,      // Top of the line box from the paragraph top
+    pub baseline: f32, // Baseline from the paragraph top
+}
+
+/// A paragraph wrapped to a maximum width.
+///
+/// The flattened `LayoutResult` holds the glyphs of every line. Positions are
+/// relative to each line's left edge, with y shifted by the line's `top`, so the
+/// whole paragraph can be drawn from a single origin. A line's visible glyphs span
+/// [0, `width`]; its trailing whitespace hangs outside that, to the right in LTR
+/// lines and to the left (at negative x) in RTL ones.
+#[derive(Serialize, Default)]
+pub struct ParagraphResult {
+    #[serde(flatten)]
+    pub layout: LayoutResult,
+    pub line_height: f32,
+    pub lines: Vec<ParagraphLine>,
+}
+
+/// Whether the char ends a line on its own (it's dropped from the shaped line text).
+fn is_line_terminator(ch: char) -> bool {
+    matches!(
+        ch,
+        '\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+/// UAX #14 break opportunities as (char offset, mandatory), ending at the text end.
+fn break_opportunities(text: &str) -> Vec<(usize, bool)> {
+    let mut byte_to_char = vec![0usize; text.len() + 1];
+    for (char_idx, (byte_idx, _)) in text.char_indices().enumerate() {
+        byte_to_char[byte_idx] = char_idx;
+    }
+    byte_to_char[text.len()] = text.chars().count();
+
+    linebreaks(text)
+        .map(|(byte_idx, op)| (byte_to_char[byte_idx], op == BreakOpportunity::Mandatory))
+        .collect()
+}
+
+/// Greedily pick line ranges (char offsets) so each line's visible width fits `max_width`.
+///
+/// Lines break at UAX #14 opportunities; trailing whitespace hangs past the edge.
+/// A segment wider than a whole line is split at cluster boundaries as a last resort.
+fn wrap_lines(
+    chars: &[char],
+    advances: &[f32],
+    cluster_starts: &[bool],
+    breaks: &[(usize, bool)],
+    max_width: f32,
+) -> Vec<(usize, usize)> {
+    let visible_width = |start: usize, end: usize| -> f32 {
+        let mut end = end;
+        while end > start && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        advances[start..end].iter().sum()
+    };
+
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut segment_start = 0;
+
+    for &(break_at, mandatory) in breaks {
+        if break_at <= segment_start && !mandatory {
+            continue;
+        }
+
+        let fits = visible_width(line_start, break_at) <= max_width;
+        if !fits && segment_start > line_start {
+            lines.push((line_start, segment_start));
+            line_start = segment_start;
+        }
+
+        // Emergency break: a single segment is wider than the whole line
+        while visible_width(line_start, break_at) > max_width {
+            let mut split = line_start + 1;
+            let mut width = advances[line_start];
+            while split < break_at {
+                if cluster_starts[split] && width + advances[split] > max_width {
+                    break;
+                }
+                width += advances[split];
+                split += 1;
+            }
+            if split >= break_at {
+                break;
+            }
+            lines.push((line_start, split));
+            line_start = split;
+        }
+
+        if mandatory {
+            lines.push((line_start, break_at));
+            line_start = break_at;
+        }
+        segment_start = break_at;
+    }
+
+    if line_start < chars.len() {
+        lines.push((line_start, chars.len()));
+    }
+    lines
+}
+
+impl TextEngine {
+    /// Lay out a paragraph, wrapping it at UAX #14 line break opportunities so that
+    /// no line is wider than `max_width` (unless a single cluster already is).
+    ///
+    /// Each line is shaped and bidi-reordered on its own, using the paragraph
+    /// direction resolved from the whole text. Lines are always horizontal;
+    /// a vertical `orientation` is ignored here.
+    pub fn process_paragraph(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        max_width: f32,
+        options: &LayoutOptions,
+    ) -> ParagraphResult {
+        if !self.has_font(options) || text.is_empty() {
+            return ParagraphResult::default();
+        }
+
+        let options = &LayoutOptions {
+            orientation: Orientation::Horizontal,
+            ..options.clone()
+        };
+        let chars: Vec<char> = text.chars().collect();
+
+        // Measure logical advances per char from one shaping pass over the whole text
+        let shaped = self.shape_text(text, size_px, weight, options);
+        let mut advances = vec![0.0f32; chars.len()];
+        let mut cluster_starts = vec![false; chars.len()];
+        for glyph in &shaped.glyphs {
+            advances[glyph.cluster as usize] += glyph.x_advance;
+            cluster_starts[glyph.cluster as usize] = true;
+        }
+
+        let breaks = break_opportunities(text);
+        let line_ranges = wrap_lines(&chars, &advances, &cluster_starts, &breaks, max_width);
+
+        // Keep one paragraph direction for every line instead of re-detecting per line
+        let mut line_options = options.clone();
+        if line_options.direction == ParagraphDirection::Auto {
+            line_options.direction = if shaped.is_rtl {
+                ParagraphDirection::Rtl
+            } else {
+                ParagraphDirection::Ltr
+            };
+        }
+
+        let mut utf16_offsets: Vec<u32> = Vec::with_capacity(chars.len() + 1);
+        let mut utf16_cursor: u32 = 0;
+        for ch in &chars {
+            utf16_offsets.push(utf16_cursor);
+            utf16_cursor += ch.len_utf16() as u32;
+        }
+        utf16_offsets.push(utf16_cursor);
+
+        let line_height = shaped.height;
+        let mut result = ParagraphResult {
+            layout: LayoutResult {
+                ascent: shaped.ascent,
+                descent: shaped.descent,
+                is_rtl: shaped.is_rtl,
+                metrics: shaped.metrics,
+                ..Default::default()
+            },
+            line_height,
+            lines: Vec::with_capacity(line_ranges.len()),
+        };
+
+        for (line_index, &(start, end)) in line_ranges.iter().enumerate() {
+            let mut content_end = end;
+            while content_end > start && is_line_terminator(chars[content_end - 1]) {
+                content_end -= 1;
+            }
+            let mut visible_end = content_end;
+            while visible_end > start && chars[visible_end - 1].is_whitespace() {
+                visible_end -= 1;
+            }
+
+            // Feature ranges count from the paragraph start, the line text from its own
+            let line_text: String = chars[start..content_end].iter().collect();
+            let range_options = line_options.for_range(start, content_end);
+            let line_shaped = self.shape_text(&line_text, size_px, weight, &range_options);
+            let line_layout = self.build_layout(&line_text, &line_shaped, size_px);
+
+            // Trailing whitespace is laid out but doesn't count towards the line width.
+            // Bidi reordering puts it at the visual end of the line, which is the left in
+            // RTL lines, so the line starts where its visible glyphs do.
+            let mut x_cursor = 0.0f32;
+            let mut visible: Option<(f32, f32)> = None;
+            for glyph in &line_shaped.glyphs {
+                if start + (glyph.cluster as usize) < visible_end {
+                    let (left, right) = visible.unwrap_or((x_cursor, x_cursor));
+                    visible = Some((left.min(x_cursor), right.max(x_cursor + glyph.x_advance)));
+                }
+                x_cursor += glyph.x_advance;
+            }
+            let (visible_left, visible_right) = visible.unwrap_or_default();
+
+            let top = line_index as f32 * line_height;
+            let glyph_start = result.layout.glyph_count as u32;
+            let layout = &mut result.layout;
+            layout.glyph_count += line_layout.glyph_count;
+            layout.glyph_ids.extend(&line_layout.glyph_ids);
+            layout.atlas_rects.extend(&line_layout.atlas_rects);
+            layout.glyph_offsets.extend(&line_layout.glyph_offsets);
+            layout.ink_bounds.extend(&line_layout.ink_bounds);
+            layout.font_indices.extend(&line_layout.font_indices);
+            layout
+                .clusters
+                .extend(line_layout.clusters.iter().map(|c| c + start as u32));
+            layout.clusters_utf16.extend(
+                line_layout
+                    .clusters_utf16
+                    .iter()
+                    .map(|c| c + utf16_offsets[start]),
+            );
+            for pos in line_layout.positions.chunks(2) {
+                layout.positions.push(pos[0] - visible_left);
+                layout.positions.push(pos[1] + top);
+            }
+            layout.union_ink(line_layout.ink_top + top, line_layout.ink_bottom + top);
+            layout
+                .uncovered
+                .append(&line_layout.uncovered, start as u32, utf16_offsets[start]);
+
+            let width = visible_right - visible_left;
+            layout.total_width = layout.total_width.max(width);
+
+            result.lines.push(ParagraphLine {
+                text_start: start as u32,
+                text_end: end as u32,
+                text_start_utf16: utf16_offsets[start],
+                text_end_utf16: utf16_offsets[end],
+                glyph_start,
+                glyph_end: result.layout.glyph_count as u32,
+                width,
+                top,
+                baseline: top + shaped.ascent,
+            });
+        }
+
+        result.layout.total_height = result.lines.len() as f32 * line_height;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grapheme;
+    use crate::test_font::TestFont;
+
+    const SIZE: f32 = 20.0;
+
+    /// Wrap `text` with every char 10 px wide
+    fn wrap(text: &str, max_width: f32) -> Vec<(usize, usize)> {
+        let chars: Vec<char> = text.chars().collect();
+        let advances = vec![10.0; chars.len()];
+        let mut cluster_starts = vec![false; chars.len()];
+        for (start, _) in grapheme::cluster_ranges(&chars) {
+            cluster_starts[start] = true;
+        }
+        let breaks = break_opportunities(text);
+        wrap_lines(&chars, &advances, &cluster_starts, &breaks, max_width)
+    }
+
+    #[test]
+    fn break_opportunities_use_char_offsets() {
+        // "é" is two bytes but one char
+        assert_eq!(
+            break_opportunities("é b\nc"),
+            [(2, false), (4, true), (5, true)]
+        );
+    }
+
+    #[test]
+    fn breaks_at_mandatory_breaks() {
+        assert_eq!(wrap("ab\ncd", 100.0), [(0, 3), (3, 5)]);
+        assert_eq!(wrap("ab\r\n\ncd", 100.0), [(0, 4), (4, 5), (5, 7)]);
+        assert_eq!(wrap("ab cd", 100.0), [(0, 5)]);
+    }
+
+    #[test]
+    fn hangs_trailing_whitespace() {
+        // The spaces stay on the first line even though they don't fit
+        assert_eq!(wrap("ab   cd", 20.0), [(0, 5), (5, 7)]);
+    }
+
+    #[test]
+    fn breaks_between_cjk_chars() {
+        assert_eq!(wrap("日本語の文章", 30.0), [(0, 3), (3, 6)]);
+        // Closing punctuation doesn't start a line: "語" moves down with it
+        assert_eq!(wrap("日本語。文章", 30.0), [(0, 2), (2, 5), (5, 6)]);
+    }
+
+    #[test]
+    fn splits_overlong_words() {
+        assert_eq!(
+            wrap("ab abcdefgh c", 40.0),
+            [(0, 3), (3, 7), (7, 12), (12, 13)]
+        );
+        // A single char wider than the line gets a line of its own
+        assert_eq!(wrap("ab", 5.0), [(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn never_splits_graphemes() {
+        // Five "é" as e + combining acute: emergency breaks fall between the pairs
+        let text = "e\u{301}".repeat(5);
+        assert_eq!(wrap(&text, 25.0), [(0, 2), (2, 4), (4, 6), (6, 8), (8, 10)]);
+    }
+
+    /// An engine whose font has 10 px wide Latin and Hebrew chars at `SIZE` and a 16 px
+    /// "fi" ligature
+    fn engine() -> TextEngine {
+        let font = TestFont::new("Test")
+            .chars("fi abcאבגד", 500)
+            .ligature("fi", 800, &[])
+            .build();
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(font, 0);
+        engine
+    }
+
+    fn line_glyphs(result: &ParagraphResult, line: usize) -> &[u16] {
+        let line = &result.lines[line];
+        &result.layout.glyph_ids[line.glyph_start as usize..line.glyph_end as usize]
+    }
+
+    #[test]
+    fn moves_feature_ranges_to_each_line() {
+        let mut engine = engine();
+        let options = LayoutOptions {
+            features: vec!["liga[3:5]=0".to_string()],
+            ..Default::default()
+        };
+        let result = engine.process_paragraph("fi fi", SIZE, 400.0, 30.0, &options);
+        assert_eq!(result.lines.len(), 2);
+        // The first line keeps its ligature, the second loses it to the range
+        assert_eq!(line_glyphs(&result, 0).len(), 2);
+        assert_eq!(result.lines[0].width, 16.0);
+        assert_eq!(line_glyphs(&result, 1).len(), 2);
+        assert_eq!(result.lines[1].width, 20.0);
+        assert_eq!(&result.layout.clusters[2..], [3, 4]);
+
+        // A range that only covers the first line leaves the second alone
+        let options = LayoutOptions {
+            features: vec!["liga[0:2]=0".to_string()],
+            ..Default::default()
+        };
+        let result = engine.process_paragraph("fi fi", SIZE, 400.0, 30.0, &options);
+        assert_eq!(line_glyphs(&result, 0).len(), 3);
+        assert_eq!(line_glyphs(&result, 1).len(), 1);
+    }
+
+    /// x of each glyph of a line, with its char offset
+    fn line_positions(result: &ParagraphResult, line: usize) -> Vec<(u32, f32)> {
+        let line = &result.lines[line];
+        (line.glyph_start as usize..line.glyph_end as usize)
+            .map(|i| (result.layout.clusters[i], result.layout.positions[i * 2]))
+            .collect()
+    }
+
+    #[test]
+    fn hangs_whitespace_at_the_visual_line_end() {
+        let mut engine = engine();
+        let options = LayoutOptions::default();
+
+        let result = engine.process_paragraph("ab cd", SIZE, 400.0, 30.0, &options);
+        assert_eq!(result.lines[0].width, 20.0);
+        assert_eq!(line_positions(&result, 0), [(0, 0.0), (1, 10.0), (2, 20.0)]);
+
+        // Right to left, the space ends up left of the visible glyphs
+        let result = engine.process_paragraph("אב גד", SIZE, 400.0, 30.0, &options);
+        assert!(result.layout.is_rtl);
+        assert_eq!(result.lines.len(), 2);
+        assert_eq!(result.lines[0].width, 20.0);
+        assert_eq!(
+            line_positions(&result, 0),
+            [(2, -10.0), (1, 0.0), (0, 10.0)]
+        );
+        assert_eq!(result.lines[1].width, 20.0);
+        assert_eq!(line_positions(&result, 1), [(4, 0.0), (3, 10.0)]);
+        assert_eq!(result.layout.total_width, 20.0);
+    }
+}
@@ -0,0 +1,483 @@
+use crate::font::{self, FontWrapper};
+use crate::locale::Locale;
+use crate::variation::AxisValue;
+use rustybuzz::Face;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Stable ID of a registered font. IDs are never reused, so atlas entries keyed by
+/// them stay valid while other fonts come and go.
+pub type FontId = usize;
+
+/// Stack used by the legacy `load_font`/`load_fallback_font` calls and by layouts that
+/// don't name one
+pub const DEFAULT_FONT_STACK: &str = "default";
+
+/// A family stack: the font text is set in, then the fonts tried for characters it lacks.
+#[derive(Clone, Debug, Default)]
+pub struct FontStack {
+    pub primary: Option<FontId>,
+    pub fallbacks: Vec<FontId>,
+    /// Fallbacks tried before the general ones for text in a matching language or script,
+    /// e.g. "ja" → Noto Sans JP, "zh-Hant" → Noto Sans TC
+    pub locale_fallbacks: Vec<LocaleFallback>,
+}
+
+/// A locale fallback rule: the language/script it applies to and its fonts, in order.
+#[derive(Clone, Debug)]
+pub struct LocaleFallback {
+    locale: Locale,
+    pub fonts: Vec<FontId>,
+}
+
+impl FontStack {
+    /// Primary then fallbacks, the order `font_index` counts in; empty without a primary.
+    /// The chain of the rule that best matches `locale` (if any) goes between the primary
+    /// and the general fallbacks. A font is only listed once, at its first position.
+    pub fn fonts_for(&self, locale: Option<&Locale>) -> Vec<FontId> {
+        let Some(primary) = self.primary else {
+            return Vec::new();
+        };
+        let locale_chain = locale
+            .and_then(|locale| {
+                self.locale_fallbacks
+                    .iter()
+                    .filter_map(|rule| Some((locale.match_rule(&rule.locale)?, rule)))
+                    // The first rule wins ties, so the order they were added in decides
+                    .rev()
+                    .max_by_key(|&(score, _)| score)
+            })
+            .map_or(&[][..], |(_, rule)| &rule.fonts[..]);
+
+        let mut fonts = vec![primary];
+        for &id in locale_chain.iter().chain(&self.fallbacks) {
+            if !fonts.contains(&id) {
+                fonts.push(id);
+            }
+        }
+        fonts
+    }
+
+    /// Set the chain for a language/script tag, replacing the rule with the same tag;
+    /// an empty chain removes the rule. Returns false if the tag doesn't parse.
+    pub fn set_locale_fallbacks(&mut self, tag: &str, fonts: Vec<FontId>) -> bool {
+        let Some(locale) = Locale::parse(tag) else {
+            return false;
+        };
+        let existing = self
+            .locale_fallbacks
+            .iter()
+            .position(|rule| rule.locale == locale);
+        match (existing, fonts.is_empty()) {
+            (Some(index), true) => {
+                self.locale_fallbacks.remove(index);
+            }
+            (Some(index), false) => self.locale_fallbacks[index].fonts = fonts,
+            (None, true) => {}
+            (None, false) => self.locale_fallbacks.push(LocaleFallback { locale, fonts }),
+        }
+        true
+    }
+
+    /// Append fonts to the chain for a language/script tag, creating the rule if needed.
+    /// Returns false if the tag doesn't parse.
+    pub fn extend_locale_fallbacks(&mut self, tag: &str, fonts: &[FontId]) -> bool {
+        let Some(locale) = Locale::parse(tag) else {
+            return false;
+        };
+        match self
+            .locale_fallbacks
+            .iter_mut()
+            .find(|rule| rule.locale == locale)
+        {
+            Some(rule) => rule.fonts.extend_from_slice(fonts),
+            None if fonts.is_empty() => {}
+            None => self.locale_fallbacks.push(LocaleFallback {
+                locale,
+                fonts: fonts.to_vec(),
+            }),
+        }
+        true
+    }
+
+    fn contains(&self, id: FontId) -> bool {
+        self.primary == Some(id)
+            || self.fallbacks.contains(&id)
+            || self
+                .locale_fallbacks
+                .iter()
+                .any(|rule| rule.fonts.contains(&id))
+    }
+
+    fn remove(&mut self, id: FontId) {
+        if self.primary == Some(id) {
+            self.primary = None;
+        }
+        self.fallbacks.retain(|&fallback| fallback != id);
+        for rule in &mut self.locale_fallbacks {
+            rule.fonts.retain(|&font| font != id);
+        }
+        self.locale_fallbacks.retain(|rule| !rule.fonts.is_empty());
+    }
+}
+
+// A font file to map on first use
+struct LazyFont {
+    path: PathBuf,
+    face_index: u32,
+    axes: Vec<AxisValue>,
+}
+
+struct RegisteredFont {
+    // Filled on first use for fonts registered by path; None if the file failed to load
+    wrapper: OnceCell<Option<FontWrapper>>,
+    lazy: Option<LazyFont>,
+    // Loaded through the legacy calls, whose callers never see the ID: dropped as soon
+    // as no stack uses it
+    anonymous: bool,
+}
+
+impl RegisteredFont {
+    fn load(&self, id: FontId) -> Option<&FontWrapper> {
+        self.wrapper
+            .get_or_init(|| {
+                let LazyFont {
+                    path,
+                    face_index,
+                    axes,
+                } = self.lazy.as_ref()?;
+                let mut wrapper = font::map_file(path)
+                    .and_then(|mmap| FontWrapper::from_mmap(mmap, id, *face_index));
+                match &mut wrapper {
+                    Some(wrapper) => {
+                        wrapper.default_axes = axes.clone();
+                        debug!("Mapped font #{}: {}", id, path.display());
+                    }
+                    None => warn!("Failed to load font #{}: {}", id, path.display()),
+                }
+                wrapper
+            })
+            .as_ref()
+    }
+}
+
+/// Every font the engine has loaded, and the named stacks built from them.
+/// All stacks render into the same atlas.
+#[derive(Default)]
+pub struct FontRegistry {
+    fonts: HashMap<FontId, RegisteredFont>,
+    next_id: FontId,
+    stacks: HashMap<String, FontStack>,
+}
+
+impl FontRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The ID the next registered font will get, for logging and `FontWrapper` IDs
+    pub fn next_id(&self) -> FontId {
+        self.next_id
+    }
+
+    /// Add a font, returning its ID. Anonymous fonts are freed once no stack refers to them.
+    pub fn register(&mut self, wrapper: FontWrapper, anonymous: bool) -> FontId {
+        self.insert(RegisteredFont {
+            wrapper: OnceCell::from(Some(wrapper)),
+            lazy: None,
+            anonymous,
+        })
+    }
+
+    /// Add a font file without opening it: it's memory-mapped the first time a layout
+    /// needs it, so large fallback lists cost nothing until used. `axes` become the
+    /// font's default variations.
+    pub fn register_path(
+        &mut self,
+        path: PathBuf,
+        face_index: u32,
+        axes: Vec<AxisValue>,
+    ) -> FontId {
+        self.insert(RegisteredFont {
+            wrapper: OnceCell::new(),
+            lazy: Some(LazyFont {
+                path,
+                face_index,
+                axes,
+            }),
+            anonymous: false,
+        })
+    }
+
+    fn insert(&mut self, font: RegisteredFont) -> FontId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.fonts.insert(id, font);
+        id
+    }
+
+    /// Remove a font and take it out of every stack. Returns false for unknown IDs.
+    pub fn unregister(&mut self, id: FontId) -> bool {
+        if self.fonts.remove(&id).is_none() {
+            return false;
+        }
+        for stack in self.stacks.values_mut() {
+            stack.remove(id);
+        }
+        true
+    }
+
+    pub fn contains(&self, id: FontId) -> bool {
+        self.fonts.contains_key(&id)
+    }
+
+    /// A font by ID, loading it first if it was registered by path
+    pub fn get(&self, id: FontId) -> Option<&FontWrapper> {
+        self.fonts.get(&id)?.load(id)
+    }
+
+    pub fn get_mut(&mut self, id: FontId) -> Option<&mut FontWrapper> {
+        let font = self.fonts.get_mut(&id)?;
+        font.load(id)?;
+        font.wrapper.get_mut()?.as_mut()
+    }
+
+    /// Raw data and face index of a font, loading it first if needed
+    pub fn source(&self, id: FontId) -> Option<(&[u8], u32)> {
+        self.get(id)
+            .map(|font| (&font.font_data[..], font.face_index))
+    }
+
+    /// Faces of `fonts`, opened on demand
+    pub fn faces<'a>(&'a self, fonts: &[FontId]) -> StackFaces<'a> {
+        StackFaces {
+            registry: self,
+            fonts: fonts.to_vec(),
+            faces: fonts.iter().map(|_| OnceCell::new()).collect(),
+        }
+    }
+
+    /// A stack by name, or None if it was never set
+    pub fn stack(&self, name: &str) -> Option<&FontStack> {
+        self.stacks.get(name)
+    }
+
+    /// Create or replace a stack's primary font and fallbacks, keeping its locale rules.
+    /// IDs that aren't registered are skipped.
+    pub fn set_stack(&mut self, name: &str, fonts: &[FontId]) {
+        let mut known = self.known_fonts(name, fonts).into_iter();
+        let stack = self.stacks.entry(name.to_string()).or_default();
+        stack.primary = known.next();
+        stack.fallbacks = known.collect();
+        self.release_unused();
+    }
+
+    /// Set a stack's fallback chain for a language/script tag (see
+    /// `FontStack::set_locale_fallbacks`), creating the stack if needed.
+    /// IDs that aren't registered are skipped. Returns false if the tag doesn't parse.
+    pub fn set_locale_fallbacks(&mut self, name: &str, tag: &str, fonts: &[FontId]) -> bool {
+        let known = self.known_fonts(name, fonts);
+        let set = self
+            .stacks
+            .entry(name.to_string())
+            .or_default()
+            .set_locale_fallbacks(tag, known);
+        self.release_unused();
+        set
+    }
+
+    fn known_fonts(&self, name: &str, fonts: &[FontId]) -> Vec<FontId> {
+        fonts
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let known = self.contains(id);
+                if !known {
+                    warn!("Font stack {:?}: no font with ID {}", name, id);
+                }
+                known
+            })
+            .collect()
+    }
+
+    /// Remove a stack; returns false if there was none by that name.
+    pub fn remove_stack(&mut self, name: &str) -> bool {
+        let removed = self.stacks.remove(name).is_some();
+        self.release_unused();
+        removed
+    }
+
+    /// Change a stack in place, creating it if needed
+    pub fn edit_stack(&mut self, name: &str, edit: impl FnOnce(&mut FontStack)) {
+        edit(self.stacks.entry(name.to_string()).or_default());
+        self.release_unused();
+    }
+
+    /// Free anonymous fonts that no stack uses any more
+    fn release_unused(&mut self) {
+        let stacks = &self.stacks;
+        self.fonts
+            .retain(|&id, font| !font.anonymous || stacks.values().any(|stack| stack.contains(id)));
+    }
+
+    /// Drop every font and stack. IDs keep counting up, so old IDs stay invalid.
+    pub fn clear(&mut self) {
+        self.fonts.clear();
+        self.stacks.clear();
+    }
+}
+
+/// Faces of a stack's fonts, indexed like `font_index` (0 = primary, 1+ = fallbacks).
+/// Each one is loaded and parsed the first time it's asked for, so fallbacks late in a
+/// long chain stay unmapped until some text needs them.
+pub struct StackFaces<'a> {
+    registry: &'a FontRegistry,
+    fonts: Vec<FontId>,
+    faces: Vec<OnceCell<Option<Face<'a>>>>,
+}
+
+impl<'a> StackFaces<'a> {
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    /// The face at `index`, or None if it's out of range or fails to load or parse
+    pub fn get(&self, index: usize) -> Option<&Face<'a>> {
+        self.faces
+            .get(index)?
+            .get_or_init(|| {
+                let (data, face_index) = self.registry.source(self.fonts[index])?;
+                Face::from_slice(data, face_index)
+            })
+            .as_ref()
+    }
+
+    /// Whether the face at `index` has been opened yet
+    #[cfg(test)]
+    pub fn is_loaded(&self, index: usize) -> bool {
+        self.faces
+            .get(index)
+            .is_some_and(|face| face.get().is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{LayoutOptions, TextEngine};
+    use crate::test_font::TestFont;
+
+    fn wrapper(registry: &FontRegistry) -> FontWrapper {
+        let data = TestFont::new("Test").chars("a", 500).build();
+        FontWrapper::from_bytes(&data, registry.next_id(), 0).unwrap()
+    }
+
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).unwrap()
+    }
+
+    #[test]
+    fn orders_stack_fonts_with_the_locale_chain_first() {
+        let mut stack = FontStack {
+            primary: Some(0),
+            fallbacks: vec![1, 2],
+            ..Default::default()
+        };
+        assert!(stack.set_locale_fallbacks("ja", vec![3, 1]));
+        assert!(stack.set_locale_fallbacks("zh-Hant", vec![4]));
+        assert!(!stack.set_locale_fallbacks("not a tag", vec![5]));
+
+        assert_eq!(stack.fonts_for(None), [0, 1, 2]);
+        // Listed once, at the locale chain's position
+        assert_eq!(stack.fonts_for(Some(&locale("ja-JP"))), [0, 3, 1, 2]);
+        assert_eq!(stack.fonts_for(Some(&locale("zh-TW"))), [0, 4, 1, 2]);
+        assert_eq!(stack.fonts_for(Some(&locale("zh-CN"))), [0, 1, 2]);
+
+        // An empty chain removes the rule, extending adds to it
+        assert!(stack.set_locale_fallbacks("ja", Vec::new()));
+        assert!(stack.extend_locale_fallbacks("zh-Hant", &[5]));
+        assert_eq!(stack.fonts_for(Some(&locale("ja"))), [0, 1, 2]);
+        assert_eq!(stack.fonts_for(Some(&locale("zh-Hant"))), [0, 4, 5, 1, 2]);
+        assert!(FontStack::default().fonts_for(None).is_empty());
+    }
+
+    #[test]
+    fn never_reuses_font_ids() {
+        let mut registry = FontRegistry::new();
+        let first = registry.register(wrapper(&registry), false);
+        let second = registry.register(wrapper(&registry), false);
+        assert_ne!(first, second);
+
+        registry.set_stack("lyrics", &[first, second, 99]);
+        let stack = registry.stack("lyrics").unwrap();
+        assert_eq!(
+            (stack.primary, &stack.fallbacks[..]),
+            (Some(first), &[second][..])
+        );
+
+        assert!(registry.unregister(first));
+        assert!(!registry.unregister(first));
+        assert_eq!(registry.stack("lyrics").unwrap().primary, None);
+
+        registry.clear();
+        assert!(registry.register(wrapper(&registry), false) > second);
+    }
+
+    #[test]
+    fn frees_anonymous_fonts_no_stack_uses() {
+        let mut registry = FontRegistry::new();
+        let anonymous = registry.register(wrapper(&registry), true);
+        let named = registry.register(wrapper(&registry), false);
+        registry.set_stack(DEFAULT_FONT_STACK, &[anonymous]);
+        assert!(registry.contains(anonymous));
+
+        registry.set_stack(DEFAULT_FONT_STACK, &[named]);
+        assert!(!registry.contains(anonymous));
+        assert!(registry.remove_stack(DEFAULT_FONT_STACK));
+        assert!(registry.contains(named));
+        assert!(!registry.remove_stack(DEFAULT_FONT_STACK));
+    }
+
+    #[test]
+    fn maps_path_fonts_on_first_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("font.ttf");
+        std::fs::write(&path, TestFont::new("Test").build()).unwrap();
+
+        let mut registry = FontRegistry::new();
+        let id = registry.register_path(path, 0, Vec::new());
+        let missing = registry.register_path(dir.path().join("missing.ttf"), 0, Vec::new());
+        let faces = registry.faces(&[id, missing]);
+        assert!(!faces.is_loaded(0));
+        assert!(faces.get(0).is_some());
+        assert!(faces.is_loaded(0));
+        assert!(faces.get(1).is_none());
+        assert!(registry.get(missing).is_none());
+    }
+
+    #[test]
+    fn lays_out_with_the_stack_a_call_names() {
+        let mut engine = TextEngine::new(512, 512);
+        let narrow = engine
+            .register_font(TestFont::new("Narrow").chars("a", 500).build(), 0)
+            .unwrap();
+        let wide = engine
+            .register_font(TestFont::new("Wide").chars("a", 1000).build(), 0)
+            .unwrap();
+        engine.set_font_stack(DEFAULT_FONT_STACK, &[narrow]);
+        engine.set_font_stack("wide", &[wide]);
+
+        let mut width = |stack: Option<&str>| {
+            let options = LayoutOptions {
+                font_stack: stack.map(str::to_string),
+                ..Default::default()
+            };
+            engine.process_text("a", 20.0, 400.0, &options).total_width
+        };
+        assert_eq!(width(None), 10.0);
+        assert_eq!(width(Some("wide")), 20.0);
+        // Unknown stacks fall back to the default one
+        assert_eq!(width(Some("missing")), 10.0);
+    }
+}
@@ -0,0 +1,382 @@
+use crate::core::{LayoutOptions, LayoutResult, ShapedGlyph, TextEngine, UncoveredChars};
+use crate::vertical::Orientation;
+use serde::{Deserialize, Serialize};
+
+/// How a ruby narrower than its base is placed over it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RubyAlign {
+    /// Keep the ruby tight and centre it over the base
+    #[default]
+    Center,
+    /// Spread the ruby clusters evenly over the base (1:2:1 spacing)
+    Distribute,
+}
+
+/// One annotation (furigana, pinyin, ...) over a range of the base text.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RubyAnnotation {
+    pub start: u32, // Char offset of the first annotated base char (inclusive)
+    pub end: u32,   // Char offset past the last annotated base char
+    pub text: String,
+    #[serde(default)]
+    pub align: RubyAlign,
+}
+
+/// Where an annotation ended up.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RubySpan {
+    pub start: u32, // Annotated base range (char offsets)
+    pub end: u32,
+    pub x: f32,           // Left edge of the base range, after widening
+    pub width: f32,       // Width of the base range, after widening
+    pub glyph_start: u32, // Range of this annotation's glyphs in the ruby layer
+    pub glyph_end: u32,
+    #[serde(flatten)]
+    pub uncovered: UncoveredChars, // Chars of the annotation's text no font covers, offsets into it
+}
+
+/// Base text with ruby laid out above it.
+///
+/// Both layers share one origin at the top-left corner: `ruby` positions are relative to
+/// the ruby baseline (`ruby.ascent` below the top) and `base` positions are shifted down by
+/// `ruby_height`, so each layer can be drawn from that origin with its own ascent.
+/// Ruby clusters are char offsets into the annotation's own text. Chars no font covers are
+/// listed per span rather than in the ruby layer, whose `uncovered` lists stay empty.
+#[derive(Serialize, Default)]
+pub struct RubyResult {
+    pub base: LayoutResult,
+    pub ruby: LayoutResult,
+    pub ruby_height: f32,
+    pub total_width: f32,
+    pub total_height: f32,
+    pub spans: Vec<RubySpan>,
+}
+
+/// Add `extra` width to the clusters of `glyphs` in the char range [start, end),
+/// split evenly between them and centred in each (half a share on both sides).
+fn spread_clusters(glyphs: &mut [ShapedGlyph], start: u32, end: u32, extra: f32) {
+    let mut clusters: Vec<u32> = glyphs
+        .iter()
+        .map(|g| g.cluster)
+        .filter(|c| (start..end).contains(c))
+        .collect();
+    clusters.sort_unstable();
+    clusters.dedup();
+    if clusters.is_empty() || extra <= 0.0 {
+        return;
+    }
+
+    let pad = extra / clusters.len() as f32 / 2.0;
+    for cluster in clusters {
+        let Some(last) = glyphs.iter().rposition(|g| g.cluster == cluster) else {
+            continue;
+        };
+        for glyph in glyphs.iter_mut().filter(|g| g.cluster == cluster) {
+            glyph.x_offset += pad;
+        }
+        glyphs[last].x_advance += pad * 2.0;
+    }
+}
+
+/// Left edge and width of the glyphs in the char range [start, end), walking the pen
+/// over the visual-order glyphs.
+fn range_extent(glyphs: &[ShapedGlyph], start: u32, end: u32) -> Option<(f32, f32)> {
+    let mut x_cursor = 0.0f32;
+    let mut extent: Option<(f32, f32)> = None;
+    for glyph in glyphs {
+        if (start..end).contains(&glyph.cluster) {
+            let (left, right) = extent.unwrap_or((x_cursor, x_cursor));
+            extent = Some((left.min(x_cursor), right.max(x_cursor + glyph.x_advance)));
+        }
+        x_cursor += glyph.x_advance;
+    }
+    extent.map(|(left, right)| (left, right - left))
+}
+
+impl TextEngine {
+    /// Lay out `text` at `size_px` with ruby annotations at `ruby_size_px` above it.
+    ///
+    /// A ruby wider than its base widens the base clusters instead of overhanging;
+    /// a narrower one is centred or distributed according to its `align`.
+    /// Overlapping or out-of-range annotations are skipped. Layout is horizontal,
+    /// and feature ranges in `options` only apply to the base text.
+    pub fn process_ruby(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        ruby_size_px: f32,
+        weight: f32,
+        annotations: &[RubyAnnotation],
+        options: &LayoutOptions,
+    ) -> RubyResult {
+        if !self.has_font(options) || text.is_empty() {
+            return RubyResult::default();
+        }
+
+        let base_options = LayoutOptions {
+            orientation: Orientation::Horizontal,
+            ..options.clone()
+        };
+        let ruby_options = LayoutOptions {
+            features: Vec::new(),
+            ..base_options.clone()
+        };
+
+        let char_count = text.chars().count() as u32;
+        let mut annotations: Vec<&RubyAnnotation> = annotations.iter().collect();
+        annotations.sort_by_key(|a| a.start);
+        let mut accepted: Vec<&RubyAnnotation> = Vec::with_capacity(annotations.len());
+        for annotation in annotations {
+            let overlaps = accepted
+                .last()
+                .is_some_and(|prev| prev.end > annotation.start);
+            if annotation.start >= annotation.end || annotation.end > char_count || overlaps {
+                warn!(
+                    "Skipping ruby {:?} over [{}, {})",
+                    annotation.text, annotation.start, annotation.end
+                );
+                continue;
+            }
+            accepted.push(annotation);
+        }
+
+        let mut base = self.shape_text(text, size_px, weight, &base_options);
+        let mut rubies = Vec::with_capacity(accepted.len());
+        for annotation in &accepted {
+            let mut ruby = self.shape_text(&annotation.text, ruby_size_px, weight, &ruby_options);
+            let base_width = range_extent(&base.glyphs, annotation.start, annotation.end)
+                .map_or(0.0, |(_, width)| width);
+            let ruby_width: f32 = ruby.glyphs.iter().map(|g| g.x_advance).sum();
+
+            if ruby_width > base_width {
+                spread_clusters(
+                    &mut base.glyphs,
+                    annotation.start,
+                    annotation.end,
+                    ruby_width - base_width,
+                );
+            } else if annotation.align == RubyAlign::Distribute {
+                spread_clusters(&mut ruby.glyphs, 0, u32::MAX, base_width - ruby_width);
+            }
+            rubies.push(ruby);
+        }
+
+        let ruby_ascent = rubies.iter().map(|r| r.ascent).fold(0.0, f32::max);
+        let ruby_descent = rubies.iter().map(|r| r.descent).fold(0.0, f32::min);
+        let ruby_height = rubies.iter().map(|r| r.height).fold(0.0, f32::max);
+
+        let mut result = RubyResult {
+            ruby: LayoutResult {
+                ascent: ruby_ascent,
+                descent: ruby_descent,
+                total_height: ruby_height,
+                is_rtl: base.is_rtl,
+                metrics: rubies.first().map(|r| r.metrics).unwrap_or_default(),
+                ..Default::default()
+            },
+            ruby_height,
+            spans: Vec::with_capacity(accepted.len()),
+            ..Default::default()
+        };
+
+        for (annotation, ruby) in accepted.iter().zip(&rubies) {
+            let (x, width) =
+                range_extent(&base.glyphs, annotation.start, annotation.end).unwrap_or_default();
+            let ruby_width: f32 = ruby.glyphs.iter().map(|g| g.x_advance).sum();
+            let ruby_x = x + (width - ruby_width) / 2.0;
+
+            let ruby_layout = self.build_layout(&annotation.text, ruby, ruby_size_px);
+            let glyph_start = result.ruby.glyph_count as u32;
+            let layer = &mut result.ruby;
+            layer.glyph_count += ruby_layout.glyph_count;
+            layer.glyph_ids.extend(&ruby_layout.glyph_ids);
+            layer.atlas_rects.extend(&ruby_layout.atlas_rects);
+            layer.glyph_offsets.extend(&ruby_layout.glyph_offsets);
+            layer.ink_bounds.extend(&ruby_layout.ink_bounds);
+            layer.font_indices.extend(&ruby_layout.font_indices);
+            layer.clusters.extend(&ruby_layout.clusters);
+            layer.clusters_utf16.extend(&ruby_layout.clusters_utf16);
+            for pos in ruby_layout.positions.chunks(2) {
+                layer.positions.push(pos[0] + ruby_x);
+                layer.positions.push(pos[1]);
+            }
+            layer.union_ink(ruby_layout.ink_top, ruby_layout.ink_bottom);
+            layer.total_width = layer.total_width.max(ruby_x + ruby_width);
+
+            result.spans.push(RubySpan {
+                start: annotation.start,
+                end: annotation.end,
+                x,
+                width,
+                glyph_start,
+                glyph_end: result.ruby.glyph_count as u32,
+                uncovered: ruby_layout.uncovered,
+            });
+        }
+
+        result.base = self.build_layout(text, &base, size_px);
+        for y in result.base.positions.iter_mut().skip(1).step_by(2) {
+            *y += ruby_height;
+        }
+        result.base.ink_top += ruby_height;
+        result.base.ink_bottom += ruby_height;
+        result.total_width = result.base.total_width.max(result.ruby.total_width);
+        result.total_height = ruby_height + result.base.total_height;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_font::TestFont;
+
+    fn glyph(cluster: u32, x_advance: f32) -> ShapedGlyph {
+        ShapedGlyph {
+            glyph_id: 1,
+            font_index: 0,
+            cluster,
+            x_advance,
+            y_advance: 0.0,
+            x_offset: 0.0,
+            y_offset: 0.0,
+            sideways: false,
+        }
+    }
+
+    fn annotation(start: u32, end: u32, text: &str, align: RubyAlign) -> RubyAnnotation {
+        RubyAnnotation {
+            start,
+            end,
+            text: text.to_string(),
+            align,
+        }
+    }
+
+    /// Base text at 20 px and ruby at 10 px, so base chars are 10 px wide and ruby chars 5 px
+    fn ruby(text: &str, annotations: &[RubyAnnotation]) -> RubyResult {
+        let font = TestFont::new("Test").chars("abcdxyzw", 500).build();
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(font, 0);
+        engine.process_ruby(
+            text,
+            20.0,
+            10.0,
+            400.0,
+            annotations,
+            &LayoutOptions::default(),
+        )
+    }
+
+    fn x_positions(layout: &LayoutResult) -> Vec<f32> {
+        layout.positions.iter().step_by(2).copied().collect()
+    }
+
+    #[test]
+    fn spreads_extra_width_over_clusters() {
+        // Cluster 1 has two glyphs: the pad goes before the first and after the last
+        let mut glyphs = vec![
+            glyph(0, 10.0),
+            glyph(1, 10.0),
+            glyph(1, 0.0),
+            glyph(2, 10.0),
+        ];
+        spread_clusters(&mut glyphs, 0, 2, 20.0);
+        let offsets: Vec<f32> = glyphs.iter().map(|g| g.x_offset).collect();
+        let advances: Vec<f32> = glyphs.iter().map(|g| g.x_advance).collect();
+        assert_eq!(offsets, [5.0, 5.0, 5.0, 0.0]);
+        assert_eq!(advances, [20.0, 10.0, 10.0, 10.0]);
+
+        // No room to give, nothing changes
+        spread_clusters(&mut glyphs, 0, 2, -5.0);
+        assert_eq!(glyphs[0].x_advance, 20.0);
+    }
+
+    #[test]
+    fn measures_ranges_in_visual_order() {
+        // Right to left: clusters 2, 1, 0 from the left
+        let glyphs = [glyph(2, 10.0), glyph(1, 10.0), glyph(0, 10.0)];
+        assert_eq!(range_extent(&glyphs, 0, 2), Some((10.0, 20.0)));
+        assert_eq!(range_extent(&glyphs, 2, 3), Some((0.0, 10.0)));
+        assert_eq!(range_extent(&glyphs, 3, 5), None);
+    }
+
+    #[test]
+    fn widens_the_base_under_a_wider_ruby() {
+        let result = ruby("ab", &[annotation(0, 1, "xyzw", RubyAlign::Center)]);
+        let span = &result.spans[0];
+        assert_eq!((span.x, span.width), (0.0, 20.0));
+        // "a" is centred in its widened advance and "b" moves over
+        assert_eq!(x_positions(&result.base), [5.0, 20.0]);
+        assert_eq!(x_positions(&result.ruby), [0.0, 5.0, 10.0, 15.0]);
+        assert_eq!(result.total_width, 30.0);
+    }
+
+    #[test]
+    fn centres_or_distributes_a_narrower_ruby() {
+        let centred = ruby("abcd", &[annotation(0, 4, "xy", RubyAlign::Center)]);
+        assert_eq!(x_positions(&centred.ruby), [15.0, 20.0]);
+
+        // 30 px spare over two clusters: 7.5 px on each side of each
+        let distributed = ruby("abcd", &[annotation(0, 4, "xy", RubyAlign::Distribute)]);
+        assert_eq!(x_positions(&distributed.ruby), [7.5, 27.5]);
+        assert_eq!(x_positions(&distributed.base), [0.0, 10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    fn skips_overlapping_and_empty_annotations() {
+        let result = ruby(
+            "abcd",
+            &[
+                annotation(1, 3, "xy", RubyAlign::Center),
+                annotation(0, 2, "zw", RubyAlign::Center),
+                annotation(3, 3, "x", RubyAlign::Center),
+                annotation(3, 5, "y", RubyAlign::Center),
+            ],
+        );
+        // Sorted by start, [0, 2) comes first and [1, 3) overlaps it
+        let ranges: Vec<(u32, u32)> = result.spans.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(ranges, [(0, 2)]);
+        assert_eq!(result.ruby.glyph_count, 2);
+    }
+
+    #[test]
+    fn stacks_the_ruby_layer_above_the_base() {
+        let result = ruby(
+            "abcd",
+            &[
+                annotation(0, 1, "x", RubyAlign::Center),
+                annotation(2, 4, "yz", RubyAlign::Center),
+            ],
+        );
+        assert!(result.ruby_height > 0.0);
+        assert_eq!(
+            result.total_height,
+            result.ruby_height + result.base.total_height
+        );
+        assert!(result
+            .base
+            .positions
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .all(|&y| y == result.ruby_height));
+        assert!(result
+            .ruby
+            .positions
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .all(|&y| y == 0.0));
+
+        // Each span's glyphs sit in the ruby layer in annotation order
+        let ranges: Vec<(u32, u32)> = result
+            .spans
+            .iter()
+            .map(|s| (s.glyph_start, s.glyph_end))
+            .collect();
+        assert_eq!(ranges, [(0, 1), (1, 3)]);
+        assert_eq!(x_positions(&result.ruby), [2.5, 25.0, 30.0]);
+    }
+}
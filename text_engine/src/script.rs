@@ -0,0 +1,76 @@
+use rustybuzz::ttf_parser::Tag;
+use unicode_script::{Script, UnicodeScript};
+
+/// Scripts that don't start a run on their own and take their neighbour's script instead.
+fn is_weak(script: Script) -> bool {
+    matches!(script, Script::Common | Script::Inherited | Script::Unknown)
+}
+
+/// Resolve the Unicode script of every char for run itemization.
+///
+/// Common/Inherited chars (spaces, punctuation, digits, combining marks) take the
+/// script of the preceding char, or of the following one when nothing precedes them.
+/// Entries stay `None` only when the whole text has no char with a real script.
+pub fn resolve_scripts(chars: &[char]) -> Vec<Option<Script>> {
+    let mut resolved: Vec<Option<Script>> = Vec::with_capacity(chars.len());
+    let mut last_strong: Option<Script> = None;
+
+    for &ch in chars {
+        let script = ch.script();
+        if !is_weak(script) {
+            last_strong = Some(script);
+        }
+        resolved.push(last_strong);
+    }
+
+    // Leading weak chars borrow the first real script that follows them
+    if let Some(first_strong) = resolved.iter().position(|s| s.is_some()) {
+        let script = resolved[first_strong];
+        for slot in &mut resolved[..first_strong] {
+            *slot = script;
+        }
+    }
+
+    resolved
+}
+
+/// Convert a Unicode script into the rustybuzz script used for shaping.
+pub fn to_shaping_script(script: Script) -> Option<rustybuzz::Script> {
+    let tag = Tag::from_bytes_lossy(script.short_name().as_bytes());
+    rustybuzz::Script::from_iso15924_tag(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scripts(text: &str) -> Vec<Option<Script>> {
+        resolve_scripts(&text.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn weak_chars_take_the_preceding_script() {
+        use Script::{Greek, Hiragana, Latin};
+        assert_eq!(
+            scripts("ab, αβ"),
+            [Latin, Latin, Latin, Latin, Greek, Greek].map(Some)
+        );
+        // A combining mark stays with its base
+        assert_eq!(scripts("あ\u{3099}"), [Hiragana, Hiragana].map(Some));
+    }
+
+    #[test]
+    fn leading_weak_chars_take_the_following_script() {
+        assert_eq!(scripts("1. α"), [Some(Script::Greek); 4]);
+        assert_eq!(scripts("12 "), [None; 3]);
+    }
+
+    #[test]
+    fn maps_to_shaping_scripts() {
+        assert_eq!(
+            to_shaping_script(Script::Arabic),
+            Some(rustybuzz::script::ARABIC)
+        );
+        assert_eq!(to_shaping_script(Script::Han), Some(rustybuzz::script::HAN));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::core::ShapedGlyph;
+use serde::Deserialize;
+use std::hash::{Hash, Hasher};
+use unicode_script::Script;
+
+/// A spacing length, either in pixels or relative to the font size.
+///
+/// Deserializes from a number (pixels) or a string such as `"2px"`, `"0.05em"` or `"-1.5"`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "SpacingValue")]
+pub enum Spacing {
+    Px(f32),
+    Em(f32),
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Spacing::Px(0.0)
+    }
+}
+
+impl Spacing {
+    pub fn to_px(self, size_px: f32) -> f32 {
+        match self {
+            Spacing::Px(px) => px,
+            Spacing::Em(em) => em * size_px,
+        }
+    }
+
+    fn bits(self) -> (u8, u32) {
+        match self {
+            Spacing::Px(px) => (0, px.to_bits()),
+            Spacing::Em(em) => (1, em.to_bits()),
+        }
+    }
+}
+
+// Compared and hashed bitwise, so layout options can key the layout cache
+impl PartialEq for Spacing {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits() == other.bits()
+    }
+}
+
+impl Eq for Spacing {}
+
+impl Hash for Spacing {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits().hash(state);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpacingValue {
+    Number(f32),
+    Text(String),
+}
+
+impl TryFrom<SpacingValue> for Spacing {
+    type Error = String;
+
+    fn try_from(value: SpacingValue) -> Result<Self, Self::Error> {
+        let text = match value {
+            SpacingValue::Number(px) => return Ok(Spacing::Px(px)),
+            SpacingValue::Text(text) => text,
+        };
+        let trimmed = text.trim();
+        let (number, unit): (&str, fn(f32) -> Spacing) =
+            if let Some(number) = trimmed.strip_suffix("em") {
+                (number, Spacing::Em)
+            } else if let Some(number) = trimmed.strip_suffix("px") {
+                (number, Spacing::Px)
+            } else {
+                (trimmed, Spacing::Px)
+            };
+        number
+            .trim()
+            .parse::<f32>()
+            .map(unit)
+            .map_err(|_| format!("invalid spacing {:?}, expected px or em", text))
+    }
+}
+
+/// Word-separator chars that take word spacing (the set CSS Text uses).
+fn is_word_separator(ch: char) -> bool {
+    matches!(
+        ch,
+        ' ' | '\u{00A0}' | '\u{1361}' | '\u{10100}' | '\u{10101}' | '\u{1039F}' | '\u{1091F}'
+    )
+}
+
+/// Scripts whose letters join up; letter spacing would tear them apart, so it's skipped.
+fn is_cursive(script: Option<Script>) -> bool {
+    matches!(
+        script,
+        Some(
+            Script::Arabic
+                | Script::Syriac
+                | Script::Mongolian
+                | Script::Nko
+                | Script::Mandaic
+                | Script::Manichaean
+                | Script::Psalter_Pahlavi
+                | Script::Adlam
+                | Script::Hanifi_Rohingya
+                | Script::Sogdian
+                | Script::Old_Uyghur
+        )
+    )
+}
+
+/// Add letter spacing after every cluster and word spacing after word separators,
+/// in pixels, along the line (x, or y for vertical layout).
+///
+/// The space goes on the advance of each cluster's last glyph in visual order, so
+/// ligatures and mark attachments within a cluster are untouched.
+pub fn apply(
+    glyphs: &mut [ShapedGlyph],
+    chars: &[char],
+    scripts: &[Option<Script>],
+    letter_spacing: f32,
+    word_spacing: f32,
+    vertical: bool,
+) {
+    if letter_spacing == 0.0 && word_spacing == 0.0 {
+        return;
+    }
+
+    for i in 0..glyphs.len() {
+        let cluster = glyphs[i].cluster;
+        let last_of_cluster = glyphs.get(i + 1).is_none_or(|next| next.cluster != cluster);
+        if !last_of_cluster {
+            continue;
+        }
+
+        let mut extra = 0.0;
+        if !is_cursive(scripts[cluster as usize]) {
+            extra += letter_spacing;
+        }
+        if is_word_separator(chars[cluster as usize]) {
+            extra += word_spacing;
+        }
+
+        let glyph = &mut glyphs[i];
+        if vertical {
+            glyph.y_advance += extra;
+        } else {
+            glyph.x_advance += extra;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(cluster: u32) -> ShapedGlyph {
+        ShapedGlyph {
+            glyph_id: 1,
+            font_index: 0,
+            cluster,
+            x_advance: 10.0,
+            y_advance: 0.0,
+            x_offset: 0.0,
+            y_offset: 0.0,
+            sideways: false,
+        }
+    }
+
+    fn parse(json: &str) -> Result<Spacing, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn parses_px_and_em() {
+        assert_eq!(parse("2").unwrap(), Spacing::Px(2.0));
+        assert_eq!(parse(r#""2px""#).unwrap(), Spacing::Px(2.0));
+        assert_eq!(parse(r#"" -1.5 ""#).unwrap(), Spacing::Px(-1.5));
+        assert_eq!(parse(r#""0.05em""#).unwrap(), Spacing::Em(0.05));
+        assert!(parse(r#""2pt""#).is_err());
+        assert_eq!(Spacing::Em(0.5).to_px(20.0), 10.0);
+    }
+
+    #[test]
+    fn spaces_after_each_cluster() {
+        // "fi" as one ligature glyph, a space, and "a" with a mark: clusters 0, 2, 3
+        let text: Vec<char> = "fi a\u{301}".chars().collect();
+        let scripts = vec![Some(Script::Latin); text.len()];
+        let mut glyphs = vec![glyph(0), glyph(2), glyph(3), glyph(3)];
+        glyphs[3].x_advance = 0.0;
+        apply(&mut glyphs, &text, &scripts, 1.0, 4.0, false);
+        let advances: Vec<f32> = glyphs.iter().map(|g| g.x_advance).collect();
+        assert_eq!(advances, [11.0, 15.0, 10.0, 1.0]);
+    }
+
+    #[test]
+    fn skips_letter_spacing_in_cursive_scripts() {
+        let text: Vec<char> = "با".chars().collect();
+        let scripts = vec![Some(Script::Arabic); 2];
+        // Visual order: the second letter comes first
+        let mut glyphs = vec![glyph(1), glyph(0)];
+        apply(&mut glyphs, &text, &scripts, 2.0, 0.0, false);
+        assert!(glyphs.iter().all(|g| g.x_advance == 10.0));
+    }
+
+    #[test]
+    fn spaces_down_vertical_lines() {
+        let text: Vec<char> = "ab".chars().collect();
+        let scripts = vec![Some(Script::Latin); 2];
+        let mut glyphs = vec![glyph(0), glyph(1)];
+        apply(&mut glyphs, &text, &scripts, 2.0, 0.0, true);
+        assert!(glyphs
+            .iter()
+            .all(|g| (g.x_advance, g.y_advance) == (10.0, 2.0)));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::bidi;
+use crate::core::{LayoutOptions, LayoutResult, TextEngine};
+use crate::layout_cache::LayoutCacheKey;
+use crate::vertical::Orientation;
+use serde::Serialize;
+
+/// Where one syllable of a lyric line ended up.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct SyllableSpan {
+    pub text_start: u32, // Char offset of the first char (inclusive)
+    pub text_end: u32,   // Char offset past the last char
+    pub text_start_utf16: u32,
+    pub text_end_utf16: u32,
+    /// Glyphs whose cluster starts in this syllable, as (start, end) index pairs in visual
+    /// order: one range, or several when bidi reordering splits the syllable
+    pub glyph_ranges: Vec<u32>,
+    pub x: f32,     // Left edge of the syllable, trailing whitespace included
+    pub width: f32, // Advance width of the syllable, trailing whitespace included
+}
+
+/// A lyric line shaped as a whole, split back into syllables.
+#[derive(Serialize, Default)]
+pub struct SyllableLineResult {
+    #[serde(flatten)]
+    pub layout: LayoutResult,
+    pub syllables: Vec<SyllableSpan>,
+}
+
+impl TextEngine {
+    /// Shape a whole lyric line once and report where each syllable landed, so kerning,
+    /// ligatures, Arabic joining and conjuncts work across syllable boundaries.
+    ///
+    /// `boundaries_utf16` are the UTF-16 offsets where one syllable ends and the next
+    /// begins, so n syllables take n - 1 offsets (an offset inside a surrogate pair
+    /// moves to the next char). A cluster that straddles a boundary, such as a ligature,
+    /// keeps its glyphs in the syllable it starts in, and its advance is split evenly
+    /// between its chars for the syllables' `x` and `width`. Layout is horizontal, and
+    /// results are kept in the layout cache like `process_text`'s.
+    pub fn process_syllables(
+        &mut self,
+        text: &str,
+        size_px: f32,
+        weight: f32,
+        boundaries_utf16: &[u32],
+        options: &LayoutOptions,
+    ) -> SyllableLineResult {
+        if !self.has_font(options) || text.is_empty() {
+            return SyllableLineResult::default();
+        }
+
+        let options = &LayoutOptions {
+            orientation: Orientation::Horizontal,
+            ..options.clone()
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let char_count = chars.len();
+
+        let mut utf16_offsets: Vec<u32> = Vec::with_capacity(char_count + 1);
+        let mut utf16_cursor: u32 = 0;
+        for ch in &chars {
+            utf16_offsets.push(utf16_cursor);
+            utf16_cursor += ch.len_utf16() as u32;
+        }
+        utf16_offsets.push(utf16_cursor);
+
+        // Syllable edges as char offsets from 0 to the text end. Out-of-order offsets are
+        // clamped rather than dropped so the syllables still match the caller's one for one.
+        let mut edges: Vec<usize> = Vec::with_capacity(boundaries_utf16.len() + 2);
+        edges.push(0);
+        for &boundary in boundaries_utf16 {
+            let edge = utf16_offsets
+                .partition_point(|&o| o < boundary)
+                .min(char_count);
+            edges.push(edge.max(edges[edges.len() - 1]));
+        }
+        edges.push(char_count);
+
+        let key = LayoutCacheKey::syllables(text, size_px, weight, boundaries_utf16, options);
+        if let Some((layout, syllables)) = self.cached_layout(&key) {
+            return SyllableLineResult { layout, syllables };
+        }
+
+        let shaped = self.shape_text(text, size_px, weight, options);
+
+        // Extent of every cluster, indexed by the char offset it starts at
+        let mut cluster_extents: Vec<Option<(f32, f32)>> = vec![None; char_count];
+        let mut x_cursor = 0.0f32;
+        for glyph in &shaped.glyphs {
+            let extent = &mut cluster_extents[glyph.cluster as usize];
+            let (left, right) = extent.unwrap_or((x_cursor, x_cursor));
+            *extent = Some((left.min(x_cursor), right.max(x_cursor + glyph.x_advance)));
+            x_cursor += glyph.x_advance;
+        }
+
+        let (bidi_runs, _) = bidi::visual_runs(text, options.direction);
+        let mut rtl = vec![false; char_count];
+        for run in &bidi_runs {
+            rtl[run.start..run.end].fill(run.is_rtl());
+        }
+
+        // Split each cluster's extent evenly between its chars, right to left in RTL runs.
+        // Chars before the first cluster (if any) sit at its leading edge with no width.
+        let mut char_extents = vec![(0.0f32, 0.0f32); char_count];
+        let cluster_starts: Vec<usize> = (0..char_count)
+            .filter(|&i| cluster_extents[i].is_some())
+            .collect();
+        for (k, &start) in cluster_starts.iter().enumerate() {
+            let end = cluster_starts.get(k + 1).copied().unwrap_or(char_count);
+            let (left, right) = cluster_extents[start].unwrap_or_default();
+            let step = (right - left) / (end - start) as f32;
+            for (j, extent) in char_extents[start..end].iter_mut().enumerate() {
+                *extent = if rtl[start] {
+                    (right - (j + 1) as f32 * step, right - j as f32 * step)
+                } else {
+                    (left + j as f32 * step, left + (j + 1) as f32 * step)
+                };
+            }
+            if k == 0 {
+                let edge = if rtl[start] { right } else { left };
+                char_extents[..start].fill((edge, edge));
+            }
+        }
+
+        let mut syllables = Vec::with_capacity(edges.len() - 1);
+        for pair in edges.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            let in_syllable = |cluster: u32| (start..end).contains(&(cluster as usize));
+
+            let (x, right) = if start == end {
+                // Empty syllable: a zero-width span at the next char's leading edge
+                let edge = match char_extents.get(start) {
+                    Some(&(_, right)) if rtl[start] => right,
+                    Some(&(left, _)) => left,
+                    None => x_cursor,
+                };
+                (edge, edge)
+            } else {
+                char_extents[start..end]
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(l, r), &(a, b)| {
+                        (l.min(a), r.max(b))
+                    })
+            };
+
+            let mut glyph_ranges: Vec<u32> = Vec::new();
+            for (i, glyph) in shaped.glyphs.iter().enumerate() {
+                if !in_syllable(glyph.cluster) {
+                    continue;
+                }
+                match glyph_ranges.last_mut() {
+                    Some(end) if *end == i as u32 => *end += 1,
+                    _ => glyph_ranges.extend([i as u32, i as u32 + 1]),
+                }
+            }
+            if glyph_ranges.is_empty() {
+                // Every char is inside a cluster that starts earlier; point at its glyph
+                let owner = cluster_starts
+                    .iter()
+                    .rev()
+                    .find(|&&s| s <= start)
+                    .and_then(|&s| shaped.glyphs.iter().position(|g| g.cluster as usize == s))
+                    .unwrap_or(0) as u32;
+                glyph_ranges.extend([owner, owner]);
+            }
+
+            syllables.push(SyllableSpan {
+                text_start: start as u32,
+                text_end: end as u32,
+                text_start_utf16: utf16_offsets[start],
+                text_end_utf16: utf16_offsets[end],
+                glyph_ranges,
+                x,
+                width: right - x,
+            });
+        }
+
+        let layout = self.build_layout(text, &shaped, size_px);
+        self.cache_layout(key, &shaped, size_px, &layout, syllables.clone());
+        SyllableLineResult { layout, syllables }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_font::TestFont;
+
+    /// Syllables of `text` split at `boundaries`, with every char 10 px wide
+    fn syllables(text: &str, boundaries: &[u32]) -> Vec<SyllableSpan> {
+        let font = TestFont::new("Test").chars("abאבג", 500).build();
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(font, 0);
+        engine
+            .process_syllables(text, 20.0, 400.0, boundaries, &LayoutOptions::default())
+            .syllables
+    }
+
+    #[test]
+    fn reports_one_glyph_range_per_syllable() {
+        let spans = syllables("ab", &[1]);
+        assert_eq!(spans[0].glyph_ranges, [0, 1]);
+        assert_eq!(spans[1].glyph_ranges, [1, 2]);
+        assert_eq!((spans[1].x, spans[1].width), (10.0, 10.0));
+    }
+
+    #[test]
+    fn splits_glyph_ranges_across_bidi_runs() {
+        // Shown as "a b ג א": the middle syllable's "b" and "א" aren't next to each other
+        let spans = syllables("abאג", &[1, 3]);
+        assert_eq!(spans[0].glyph_ranges, [0, 1]);
+        assert_eq!(spans[1].glyph_ranges, [1, 2, 3, 4]);
+        assert_eq!(spans[2].glyph_ranges, [2, 3]);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::variation::{AxisValue, FontStyle};
+use rustybuzz::ttf_parser::Face;
+use swash::scale::outline::Outline;
+use swash::zeno::Transform;
+
+/// Lowest requested weight that gets emboldened when the font can't vary its own
+const SYNTHETIC_BOLD_MIN_WEIGHT: f32 = 600.0;
+/// Fonts at least this heavy are already bold enough
+const SYNTHETIC_BOLD_MAX_FONT_WEIGHT: u16 = 500;
+/// How much a glyph grows (and its advance with it), as a fraction of the em, like FreeType
+const EMBOLDEN_EM_FRACTION: f32 = 1.0 / 24.0;
+/// Horizontal shift per unit of height for synthetic oblique, tan(14°) as in browsers
+const OBLIQUE_SKEW: f32 = 0.2493;
+
+/// Faux bold and oblique applied to a static font that can't produce the requested weight
+/// or style itself. Part of the glyph cache key, so synthesized glyphs get their own entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Synthesis {
+    pub embolden: bool,
+    pub oblique: bool,
+}
+
+impl Synthesis {
+    /// Decide what to synthesize for `face` given the request and the axis values
+    /// `variation::resolve` came up with for it.
+    ///
+    /// Bold is synthesized for weights of 600 and up when the font has no `wght` axis and is
+    /// itself 500 or lighter; oblique for italic and oblique styles when the font is upright
+    /// and neither `ital` nor `slnt` were set to anything but 0.
+    pub fn resolve(face: &Face, weight: f32, style: FontStyle, axes: &[AxisValue]) -> Self {
+        let axis_set = |tag: &[u8; 4]| {
+            axes.iter()
+                .any(|(axis, value)| axis.to_bytes() == *tag && *value != 0.0)
+        };
+        let has_wght = axes.iter().any(|(axis, _)| &axis.to_bytes() == b"wght");
+
+        Synthesis {
+            embolden: weight >= SYNTHETIC_BOLD_MIN_WEIGHT
+                && !has_wght
+                && face.weight().to_number() <= SYNTHETIC_BOLD_MAX_FONT_WEIGHT,
+            oblique: style != FontStyle::Normal
+                && !face.is_italic()
+                && !face.is_oblique()
+                && !axis_set(b"ital")
+                && !axis_set(b"slnt"),
+        }
+    }
+
+    /// Extra advance (and outline growth) of an emboldened glyph at `size_px`
+    pub fn embolden_px(&self, size_px: f32) -> f32 {
+        if self.embolden {
+            size_px * EMBOLDEN_EM_FRACTION
+        } else {
+            0.0
+        }
+    }
+
+    /// Argument to swash's `embolden`, which grows outlines by twice its strength to the
+    /// right and up, keeping the bottom-left corner in place
+    pub fn embolden_strength(&self, size_px: f32) -> f32 {
+        self.embolden_px(size_px) / 2.0
+    }
+
+    /// Shear leaning glyphs to the right about their baseline, in font space (y up)
+    pub fn skew(&self) -> Option<Transform> {
+        self.oblique
+            .then(|| Transform::new(1.0, 0.0, OBLIQUE_SKEW, 1.0, 0.0, 0.0))
+    }
+
+    /// Apply the synthesis to a scaled outline, the same way `Render` does for bitmaps
+    pub fn apply(&self, outline: &mut Outline, size_px: f32) {
+        if self.embolden {
+            let strength = self.embolden_strength(size_px);
+            outline.embolden(strength, strength);
+        }
+        if let Some(skew) = self.skew() {
+            outline.transform(&skew);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{LayoutOptions, TextEngine};
+    use crate::test_font::TestFont;
+    use rustybuzz::ttf_parser::Tag;
+
+    fn resolve(font: TestFont, weight: f32, style: FontStyle, axes: &[AxisValue]) -> Synthesis {
+        let data = font.build();
+        Synthesis::resolve(&Face::parse(&data, 0).unwrap(), weight, style, axes)
+    }
+
+    fn flags(synthesis: Synthesis) -> (bool, bool) {
+        (synthesis.embolden, synthesis.oblique)
+    }
+
+    #[test]
+    fn emboldens_light_static_fonts() {
+        let regular = || TestFont::new("Test");
+        assert_eq!(
+            flags(resolve(regular(), 700.0, FontStyle::Normal, &[])),
+            (true, false)
+        );
+        assert_eq!(
+            flags(resolve(regular(), 500.0, FontStyle::Normal, &[])),
+            (false, false)
+        );
+        // A bold face or a weight axis does the job itself
+        let bold = TestFont::new("Test").weight(700);
+        assert_eq!(
+            flags(resolve(bold, 900.0, FontStyle::Normal, &[])),
+            (false, false)
+        );
+        let wght = [(Tag::from_bytes(b"wght"), 700.0)];
+        assert_eq!(
+            flags(resolve(regular(), 700.0, FontStyle::Normal, &wght)),
+            (false, false)
+        );
+    }
+
+    #[test]
+    fn slants_upright_fonts() {
+        let upright = || TestFont::new("Test");
+        assert!(resolve(upright(), 400.0, FontStyle::Italic, &[]).oblique);
+        assert!(resolve(upright(), 400.0, FontStyle::Oblique, &[]).oblique);
+        assert!(
+            !resolve(
+                TestFont::new("Test").italic(),
+                400.0,
+                FontStyle::Italic,
+                &[]
+            )
+            .oblique
+        );
+        // A slant axis set to lean already
+        let slnt = [(Tag::from_bytes(b"slnt"), -10.0)];
+        assert!(!resolve(upright(), 400.0, FontStyle::Oblique, &slnt).oblique);
+        let upright_slnt = [(Tag::from_bytes(b"slnt"), 0.0)];
+        assert!(resolve(upright(), 400.0, FontStyle::Oblique, &upright_slnt).oblique);
+    }
+
+    #[test]
+    fn synthesized_glyphs_grow_and_lean() {
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(TestFont::new("Test").chars("a", 500).build(), 0);
+        let mut layout = |weight: f32, style: FontStyle| {
+            let options = LayoutOptions {
+                style,
+                ..Default::default()
+            };
+            engine.process_text("a", 48.0, weight, &options)
+        };
+
+        let regular = layout(400.0, FontStyle::Normal);
+        assert_eq!(regular.total_width, 24.0);
+        // One 24th of the em wider
+        assert_eq!(layout(700.0, FontStyle::Normal).total_width, 26.0);
+
+        // The top of the box leans right, so the ink reaches further without a wider advance
+        let oblique = layout(400.0, FontStyle::Oblique);
+        assert_eq!(oblique.total_width, 24.0);
+        assert!(oblique.ink_bounds[2] > regular.ink_bounds[2] + 5.0);
+    }
+}
@@ -0,0 +1,565 @@
+//! TrueType fonts and collections built in memory for tests: one box-shaped glyph per
+//! mapped char, plus optional ligatures (with GDEF carets), `vert` and language-specific
+//! alternates, variation axes with named instances and a color table.
+
+pub const UNITS_PER_EM: u16 = 1000;
+pub const ASCENDER: i16 = 800;
+pub const DESCENDER: i16 = -200;
+/// Top of every glyph's box outline; boxes sit on the baseline
+pub const GLYPH_HEIGHT: i16 = 700;
+/// Gap between a box and the edges of its advance
+pub const SIDE_BEARING: i16 = 50;
+
+fn u16s(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+fn u32s(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+/// Format 1 coverage table of sorted glyph IDs
+fn coverage(glyphs: &[u16]) -> Vec<u8> {
+    [u16s(&[1, glyphs.len() as u16]), u16s(glyphs)].concat()
+}
+
+/// Single substitution subtable (format 2) for (glyph, substitute) pairs
+fn single_subst(pairs: &[(u16, u16)]) -> Vec<u8> {
+    let mut pairs = pairs.to_vec();
+    pairs.sort_unstable();
+    let (glyphs, substitutes): (Vec<u16>, Vec<u16>) = pairs.into_iter().unzip();
+    [
+        u16s(&[2, 6 + 2 * glyphs.len() as u16, glyphs.len() as u16]),
+        u16s(&substitutes),
+        coverage(&glyphs),
+    ]
+    .concat()
+}
+
+struct Glyph {
+    ch: Option<char>,
+    advance: u16,
+    outline: bool,
+}
+
+struct Ligature {
+    components: Vec<u16>,
+    glyph: u16,
+    carets: Vec<i16>,
+}
+
+/// Builder for a test font. Glyph 0 is .notdef, then glyphs in the order they're added.
+pub struct TestFont {
+    family: String,
+    weight: u16,
+    italic: bool,
+    color: bool,
+    x_height: i16,
+    cap_height: i16,
+    glyphs: Vec<Glyph>,
+    ligatures: Vec<Ligature>,
+    vertical: Vec<(u16, u16)>,           // Glyph and its `vert` alternate
+    localized: Vec<([u8; 4], u16, u16)>, // Language system, glyph and its `locl` alternate
+    axes: Vec<([u8; 4], f32, f32, f32)>, // Tag, min, default, max
+    instances: Vec<(String, Vec<f32>)>,  // Name and coordinates of each named instance
+}
+
+impl TestFont {
+    pub fn new(family: &str) -> Self {
+        Self {
+            family: family.to_string(),
+            weight: 400,
+            italic: false,
+            color: false,
+            x_height: 0,
+            cap_height: 0,
+            glyphs: vec![Glyph {
+                ch: None,
+                advance: 500,
+                outline: true,
+            }],
+            ligatures: Vec::new(),
+            vertical: Vec::new(),
+            localized: Vec::new(),
+            axes: Vec::new(),
+            instances: Vec::new(),
+        }
+    }
+
+    pub fn weight(mut self, weight: u16) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    /// Add an (empty) SVG table, which makes it count as a color font
+    pub fn color(mut self) -> Self {
+        self.color = true;
+        self
+    }
+
+    /// OS/2 x-height and cap height; 0 leaves them out
+    pub fn heights(mut self, x_height: i16, cap_height: i16) -> Self {
+        self.x_height = x_height;
+        self.cap_height = cap_height;
+        self
+    }
+
+    /// Add a variation axis to `fvar`
+    pub fn axis(mut self, tag: &[u8; 4], min: f32, default: f32, max: f32) -> Self {
+        self.axes.push((*tag, min, default, max));
+        self
+    }
+
+    /// Add a named instance with a coordinate for each axis, in the order they were added
+    pub fn instance(mut self, name: &str, coords: &[f32]) -> Self {
+        self.instances.push((name.to_string(), coords.to_vec()));
+        self
+    }
+
+    /// Map every char of `chars` to a new glyph `advance` units wide. Whitespace gets
+    /// no outline.
+    pub fn chars(mut self, chars: &str, advance: u16) -> Self {
+        for ch in chars.chars() {
+            self.glyphs.push(Glyph {
+                ch: Some(ch),
+                advance,
+                outline: !ch.is_whitespace(),
+            });
+        }
+        self
+    }
+
+    /// Add a `liga` ligature for `text`, whose chars must be mapped already, with
+    /// caret positions for GDEF in font units (none when empty)
+    pub fn ligature(mut self, text: &str, advance: u16, carets: &[i16]) -> Self {
+        let components = text.chars().map(|ch| self.glyph(ch)).collect();
+        self.ligatures.push(Ligature {
+            components,
+            glyph: self.glyphs.len() as u16,
+            carets: carets.to_vec(),
+        });
+        self.glyphs.push(Glyph {
+            ch: None,
+            advance,
+            outline: true,
+        });
+        self
+    }
+
+    /// Add a `vert` alternate for a mapped char
+    pub fn vertical_alternate(mut self, ch: char) -> Self {
+        let glyph = self.glyph(ch);
+        self.vertical.push((glyph, self.glyphs.len() as u16));
+        self.glyphs.push(Glyph {
+            ch: None,
+            advance: self.glyphs[glyph as usize].advance,
+            outline: true,
+        });
+        self
+    }
+
+    /// Add a `locl` alternate for a mapped char, used under the OpenType language system
+    /// `language` (e.g. `b"JAN "`), `advance` units wide
+    pub fn localized(mut self, language: &[u8; 4], ch: char, advance: u16) -> Self {
+        let glyph = self.glyph(ch);
+        self.localized
+            .push((*language, glyph, self.glyphs.len() as u16));
+        self.glyphs.push(Glyph {
+            ch: None,
+            advance,
+            outline: true,
+        });
+        self
+    }
+
+    /// Glyph ID of a mapped char
+    pub fn glyph(&self, ch: char) -> u16 {
+        self.glyphs
+            .iter()
+            .position(|glyph| glyph.ch == Some(ch))
+            .unwrap_or_else(|| panic!("{:?} isn't mapped", ch)) as u16
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let glyph_count = self.glyphs.len() as u16;
+        let advance_max = self.glyphs.iter().map(|g| g.advance).max().unwrap_or(0);
+
+        let mut head = vec![0u8; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&UNITS_PER_EM.to_be_bytes());
+        head[38..40].copy_from_slice(&DESCENDER.to_be_bytes()); // yMin
+        head[40..42].copy_from_slice(&advance_max.to_be_bytes()); // xMax
+        head[42..44].copy_from_slice(&ASCENDER.to_be_bytes()); // yMax
+        head[50..52].copy_from_slice(&1u16.to_be_bytes()); // Long loca offsets
+
+        let mut hhea = vec![0u8; 36];
+        hhea[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[4..6].copy_from_slice(&ASCENDER.to_be_bytes());
+        hhea[6..8].copy_from_slice(&DESCENDER.to_be_bytes());
+        hhea[10..12].copy_from_slice(&advance_max.to_be_bytes());
+        hhea[34..36].copy_from_slice(&glyph_count.to_be_bytes()); // numberOfHMetrics
+
+        // Version 1.0, room for one four-point contour per glyph
+        let maxp = [
+            u32s(&[0x0001_0000]),
+            u16s(&[glyph_count, 4, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]),
+        ]
+        .concat();
+
+        let mut os2 = vec![0u8; 96];
+        os2[0..2].copy_from_slice(&2u16.to_be_bytes()); // Version
+        os2[4..6].copy_from_slice(&self.weight.to_be_bytes()); // usWeightClass
+        os2[6..8].copy_from_slice(&5u16.to_be_bytes()); // usWidthClass
+        os2[26..28].copy_from_slice(&50i16.to_be_bytes()); // yStrikeoutSize
+        os2[28..30].copy_from_slice(&300i16.to_be_bytes()); // yStrikeoutPosition
+        let fs_selection: u16 = if self.italic { 0x01 } else { 0x40 };
+        os2[62..64].copy_from_slice(&fs_selection.to_be_bytes());
+        os2[68..70].copy_from_slice(&ASCENDER.to_be_bytes()); // sTypoAscender
+        os2[70..72].copy_from_slice(&DESCENDER.to_be_bytes()); // sTypoDescender
+        os2[74..76].copy_from_slice(&(ASCENDER as u16).to_be_bytes()); // usWinAscent
+        os2[76..78].copy_from_slice(&(-DESCENDER as u16).to_be_bytes()); // usWinDescent
+        os2[86..88].copy_from_slice(&self.x_height.to_be_bytes()); // sxHeight
+        os2[88..90].copy_from_slice(&self.cap_height.to_be_bytes()); // sCapHeight
+
+        let mut post = vec![0u8; 32];
+        post[0..4].copy_from_slice(&0x0003_0000u32.to_be_bytes());
+        post[8..10].copy_from_slice(&(-100i16).to_be_bytes()); // underlinePosition
+        post[10..12].copy_from_slice(&50i16.to_be_bytes()); // underlineThickness
+
+        let mut hmtx = Vec::new();
+        let mut glyf = Vec::new();
+        let mut loca = u32s(&[0]);
+        for glyph in &self.glyphs {
+            let right = (glyph.advance as i16 - SIDE_BEARING).max(SIDE_BEARING + 1);
+            hmtx.extend(u16s(&[glyph.advance, SIDE_BEARING as u16]));
+            if glyph.outline {
+                // One contour: a box from the left to the right bearing, baseline to GLYPH_HEIGHT
+                let (left, top) = (SIDE_BEARING, GLYPH_HEIGHT);
+                glyf.extend(
+                    [1, left, 0, right, top, 3]
+                        .iter()
+                        .flat_map(|v: &i16| v.to_be_bytes()),
+                );
+                glyf.extend(u16s(&[0])); // No instructions
+                glyf.extend([1u8; 4]); // On-curve points, coordinates as 16-bit deltas
+                glyf.extend(
+                    [left, 0, right - left, 0, 0, top, 0, -top]
+                        .iter()
+                        .flat_map(|v: &i16| v.to_be_bytes()),
+                );
+            }
+            loca.extend(u32s(&[glyf.len() as u32]));
+        }
+
+        // Format 12 subtable for Windows Unicode full repertoire, one group per char
+        let mut mapped: Vec<(u32, u32)> = self
+            .glyphs
+            .iter()
+            .enumerate()
+            .filter_map(|(id, glyph)| Some((glyph.ch? as u32, id as u32)))
+            .collect();
+        mapped.sort_unstable();
+        let mut cmap = [u16s(&[0, 1, 3, 10]), u32s(&[12])].concat();
+        cmap.extend(u16s(&[12, 0]));
+        cmap.extend(u32s(&[
+            16 + 12 * mapped.len() as u32,
+            0,
+            mapped.len() as u32,
+        ]));
+        for (cp, id) in mapped {
+            cmap.extend(u32s(&[cp, cp, id]));
+        }
+
+        let subfamily = if self.italic { "Italic" } else { "Regular" };
+        // Instance names from ID 256 on
+        let names = [(1, self.family.as_str()), (2, subfamily)]
+            .into_iter()
+            .chain(
+                self.instances
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, _))| (256 + i as u16, name.as_str())),
+            );
+        let strings: Vec<(u16, Vec<u8>)> = names
+            .map(|(id, text)| (id, text.encode_utf16().flat_map(u16::to_be_bytes).collect()))
+            .collect();
+        let mut name = u16s(&[0, strings.len() as u16, 6 + 12 * strings.len() as u16]);
+        let mut offset = 0;
+        for (id, text) in &strings {
+            name.extend(u16s(&[3, 1, 0x409, *id, text.len() as u16, offset]));
+            offset += text.len() as u16;
+        }
+        for (_, text) in &strings {
+            name.extend(text);
+        }
+
+        let mut tables: Vec<([u8; 4], Vec<u8>)> = vec![
+            (*b"OS/2", os2),
+            (*b"cmap", cmap),
+            (*b"glyf", glyf),
+            (*b"head", head),
+            (*b"hhea", hhea),
+            (*b"hmtx", hmtx),
+            (*b"loca", loca),
+            (*b"maxp", maxp),
+            (*b"name", name),
+            (*b"post", post),
+        ];
+        if let Some(gsub) = self.gsub() {
+            tables.push((*b"GSUB", gsub));
+        }
+        if self.ligatures.iter().any(|lig| !lig.carets.is_empty()) {
+            tables.push((*b"GDEF", self.gdef()));
+        }
+        if !self.axes.is_empty() {
+            tables.push((*b"fvar", self.fvar()));
+        }
+        if self.color {
+            // Version 0 with an empty document list
+            tables.push((*b"SVG ", [u16s(&[0]), u32s(&[10, 0]), u16s(&[0])].concat()));
+        }
+        tables.sort_by_key(|(tag, _)| *tag);
+
+        let mut font = [u32s(&[0x0001_0000]), u16s(&[tables.len() as u16, 0, 0, 0])].concat();
+        let mut offset = 12 + 16 * tables.len();
+        let mut data = Vec::new();
+        for (tag, table) in &tables {
+            font.extend_from_slice(tag);
+            font.extend(u32s(&[0, offset as u32, table.len() as u32]));
+            let padded = (table.len() + 3) & !3;
+            data.extend(table);
+            data.resize(data.len() + padded - table.len(), 0);
+            offset += padded;
+        }
+        font.extend(data);
+        font
+    }
+
+    /// GSUB under the default script: `liga` and `vert` lookups for the ligatures and
+    /// vertical alternates in every language system, and a `locl` one in each language
+    /// system with alternates
+    fn gsub(&self) -> Option<Vec<u8>> {
+        let mut features: Vec<[u8; 4]> = Vec::new();
+        let mut lookups: Vec<Vec<u8>> = Vec::new();
+        if !self.ligatures.is_empty() {
+            features.push(*b"liga");
+            lookups.push([u16s(&[4, 0, 1, 8]), self.ligature_subst()].concat());
+        }
+        if !self.vertical.is_empty() {
+            features.push(*b"vert");
+            lookups.push([u16s(&[1, 0, 1, 8]), single_subst(&self.vertical)].concat());
+        }
+        let shared = features.len() as u16;
+
+        let mut languages: Vec<[u8; 4]> = self.localized.iter().map(|l| l.0).collect();
+        languages.sort_unstable();
+        languages.dedup();
+        for language in &languages {
+            let pairs: Vec<(u16, u16)> = self
+                .localized
+                .iter()
+                .filter(|l| l.0 == *language)
+                .map(|l| (l.1, l.2))
+                .collect();
+            features.push(*b"locl");
+            lookups.push([u16s(&[1, 0, 1, 8]), single_subst(&pairs)].concat());
+        }
+        if features.is_empty() {
+            return None;
+        }
+        let count = features.len() as u16;
+
+        // Script list: DFLT with a default LangSys that enables the shared features, and
+        // one LangSys per language adding its `locl`
+        let lang_count = languages.len() as u16;
+        let mut scripts = u16s(&[1]);
+        scripts.extend(b"DFLT");
+        scripts.extend(u16s(&[8, 4 + 6 * lang_count, lang_count]));
+        let mut offset = 4 + 6 * lang_count + 6 + 2 * shared;
+        for language in &languages {
+            scripts.extend(language);
+            scripts.extend(u16s(&[offset]));
+            offset += 6 + 2 * (shared + 1);
+        }
+        scripts.extend(u16s(&[0, 0xFFFF, shared]));
+        scripts.extend(u16s(&(0..shared).collect::<Vec<_>>()));
+        for i in 0..lang_count {
+            scripts.extend(u16s(&[0, 0xFFFF, shared + 1]));
+            scripts.extend(u16s(&(0..shared).chain([shared + i]).collect::<Vec<_>>()));
+        }
+
+        // Feature list: feature i uses lookup i
+        let mut feature_list = u16s(&[count]);
+        for (i, tag) in features.iter().enumerate() {
+            feature_list.extend(tag);
+            feature_list.extend(u16s(&[2 + 6 * count + 6 * i as u16]));
+        }
+        for i in 0..count {
+            feature_list.extend(u16s(&[0, 1, i]));
+        }
+
+        let mut lookup_list = u16s(&[count]);
+        let mut offset = 2 + 2 * count;
+        for lookup in &lookups {
+            lookup_list.extend(u16s(&[offset]));
+            offset += lookup.len() as u16;
+        }
+        for lookup in &lookups {
+            lookup_list.extend(lookup);
+        }
+
+        let scripts_offset = 10u16;
+        let features_offset = scripts_offset + scripts.len() as u16;
+        let lookups_offset = features_offset + feature_list.len() as u16;
+        Some(
+            [
+                u16s(&[1, 0, scripts_offset, features_offset, lookups_offset]),
+                scripts,
+                feature_list,
+                lookup_list,
+            ]
+            .concat(),
+        )
+    }
+
+    /// Ligature substitution subtable (format 1) for every ligature
+    fn ligature_subst(&self) -> Vec<u8> {
+        let mut first_glyphs: Vec<u16> =
+            self.ligatures.iter().map(|lig| lig.components[0]).collect();
+        first_glyphs.sort_unstable();
+        first_glyphs.dedup();
+
+        let sets: Vec<Vec<u8>> = first_glyphs
+            .iter()
+            .map(|&first| {
+                let ligatures: Vec<Vec<u8>> = self
+                    .ligatures
+                    .iter()
+                    .filter(|lig| lig.components[0] == first)
+                    .map(|lig| {
+                        [
+                            u16s(&[lig.glyph, lig.components.len() as u16]),
+                            u16s(&lig.components[1..]),
+                        ]
+                        .concat()
+                    })
+                    .collect();
+                let mut set = u16s(&[ligatures.len() as u16]);
+                let mut offset = 2 + 2 * ligatures.len() as u16;
+                for ligature in &ligatures {
+                    set.extend(u16s(&[offset]));
+                    offset += ligature.len() as u16;
+                }
+                [set, ligatures.concat()].concat()
+            })
+            .collect();
+
+        let coverage = coverage(&first_glyphs);
+        let coverage_offset = 6 + 2 * sets.len() as u16;
+        let mut subtable = u16s(&[1, coverage_offset, sets.len() as u16]);
+        let mut offset = coverage_offset + coverage.len() as u16;
+        for set in &sets {
+            subtable.extend(u16s(&[offset]));
+            offset += set.len() as u16;
+        }
+        [subtable, coverage, sets.concat()].concat()
+    }
+
+    /// fvar with the axes and named instances (without PostScript names)
+    fn fvar(&self) -> Vec<u8> {
+        let fixed = |value: f32| u32s(&[(value * 65536.0).round() as i32 as u32]);
+        let axis_count = self.axes.len() as u16;
+        let mut fvar = u16s(&[
+            1,
+            0,
+            16,
+            2,
+            axis_count,
+            20,
+            self.instances.len() as u16,
+            4 + 4 * axis_count,
+        ]);
+        for &(tag, min, default, max) in &self.axes {
+            fvar.extend(tag);
+            fvar.extend([fixed(min), fixed(default), fixed(max)].concat());
+            fvar.extend(u16s(&[0, 2])); // Flags, name ID
+        }
+        for (i, (_, coords)) in self.instances.iter().enumerate() {
+            fvar.extend(u16s(&[256 + i as u16, 0]));
+            fvar.extend(coords.iter().flat_map(|&c| fixed(c)));
+        }
+        fvar
+    }
+
+    /// GDEF with just a ligature caret list (coordinate carets)
+    fn gdef(&self) -> Vec<u8> {
+        let ligatures: Vec<&Ligature> = self
+            .ligatures
+            .iter()
+            .filter(|lig| !lig.carets.is_empty())
+            .collect();
+        let glyphs: Vec<u16> = ligatures.iter().map(|lig| lig.glyph).collect();
+        let lig_glyphs: Vec<Vec<u8>> = ligatures
+            .iter()
+            .map(|lig| {
+                let count = lig.carets.len() as u16;
+                let mut table = u16s(&[count]);
+                table.extend(u16s(
+                    &(0..count)
+                        .map(|i| 2 + 2 * count + 4 * i)
+                        .collect::<Vec<_>>(),
+                ));
+                for &caret in &lig.carets {
+                    table.extend(u16s(&[1, caret as u16]));
+                }
+                table
+            })
+            .collect();
+
+        let coverage = coverage(&glyphs);
+        let coverage_offset = 4 + 2 * lig_glyphs.len() as u16;
+        let mut list = u16s(&[coverage_offset, lig_glyphs.len() as u16]);
+        let mut offset = coverage_offset + coverage.len() as u16;
+        for lig_glyph in &lig_glyphs {
+            list.extend(u16s(&[offset]));
+            offset += lig_glyph.len() as u16;
+        }
+        [
+            u16s(&[1, 0, 0, 0, 12, 0]),
+            list,
+            coverage,
+            lig_glyphs.concat(),
+        ]
+        .concat()
+    }
+}
+
+/// A TTC collection of `fonts`, in order
+pub fn collection(fonts: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = b"ttcf".to_vec();
+    data.extend(u32s(&[0x0001_0000, fonts.len() as u32]));
+    let mut offset = 12 + 4 * fonts.len();
+    for font in fonts {
+        data.extend(u32s(&[offset as u32]));
+        offset += (font.len() + 3) & !3;
+    }
+    for font in fonts {
+        // Table offsets count from the start of the file
+        let base = data.len() as u32;
+        let mut font = font.clone();
+        let table_count = u16::from_be_bytes([font[4], font[5]]) as usize;
+        for record in (0..table_count).map(|i| 12 + 16 * i + 8) {
+            let table_offset = u32::from_be_bytes(font[record..record + 4].try_into().unwrap());
+            font[record..record + 4].copy_from_slice(&(table_offset + base).to_be_bytes());
+        }
+        data.extend(&font);
+        data.resize((data.len() + 3) & !3, 0);
+    }
+    data
+}
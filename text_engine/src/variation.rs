@@ -0,0 +1,255 @@
+use rustybuzz::ttf_parser::Tag;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use swash::FontRef;
+
+/// One resolved axis value, as passed to both rustybuzz and swash.
+pub type AxisValue = (Tag, f32);
+
+/// `slnt` for oblique text: 14° leaning right, the CSS default
+const OBLIQUE_SLANT: f32 = -14.0;
+
+/// Font style requested for a layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontStyle {
+    #[default]
+    Normal,
+    /// Uses the `ital` axis, else leans with `slnt`, else is synthesized
+    Italic,
+    /// Uses the `slnt` axis, else is synthesized
+    Oblique,
+}
+
+/// Variable font axis values requested for a layout, by tag, e.g. `{"wdth": 75, "slnt": -10}`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Variations(BTreeMap<String, f32>);
+
+impl Variations {
+    fn bits(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.0
+            .iter()
+            .map(|(tag, value)| (tag.as_str(), value.to_bits()))
+    }
+}
+
+// Compared and hashed bitwise, so layout options can key the layout cache
+impl PartialEq for Variations {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits().eq(other.bits())
+    }
+}
+
+impl Eq for Variations {}
+
+impl Hash for Variations {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for entry in self.bits() {
+            entry.hash(state);
+        }
+    }
+}
+
+/// Work out the axis values a font is shaped and rendered with.
+///
+/// Later sources win: the font's own `defaults` (from the system font config), then
+/// `weight` sets `wght`, `opsz` follows the pixel size, `style` sets `ital` or `slnt`, then
+/// the coordinates of the named `instance` from `fvar`, then the explicit `variations`.
+/// Only axes the font has are returned, clamped to their range and in `fvar` order,
+/// so a static font always comes back empty.
+pub fn resolve(
+    font: FontRef,
+    weight: f32,
+    size_px: f32,
+    style: FontStyle,
+    instance: Option<&str>,
+    variations: &Variations,
+    defaults: &[AxisValue],
+) -> Vec<AxisValue> {
+    let axes: Vec<_> = font.variations().collect();
+    if axes.is_empty() {
+        return Vec::new();
+    }
+
+    let has_ital = axes.iter().any(|axis| &axis.tag().to_be_bytes() == b"ital");
+    let mut values: Vec<Option<f32>> = axes
+        .iter()
+        .map(|axis| match (&axis.tag().to_be_bytes(), style) {
+            (b"wght", _) => Some(weight),
+            (b"opsz", _) => Some(size_px),
+            (b"ital", FontStyle::Italic) => Some(1.0),
+            (b"slnt", FontStyle::Oblique) => Some(OBLIQUE_SLANT),
+            (b"slnt", FontStyle::Italic) if !has_ital => Some(OBLIQUE_SLANT),
+            _ => defaults
+                .iter()
+                .find(|(tag, _)| tag.0 == axis.tag())
+                .map(|&(_, value)| value),
+        })
+        .collect();
+
+    if let Some(name) = instance {
+        let found = font.instances().find(|candidate| {
+            [candidate.name(None), candidate.postscript_name(None)]
+                .into_iter()
+                .flatten()
+                .any(|n| n.to_string().eq_ignore_ascii_case(name))
+        });
+        match found {
+            Some(found) => {
+                for (value, coord) in values.iter_mut().zip(found.values()) {
+                    *value = Some(coord);
+                }
+            }
+            None => warn!("Font has no named instance {:?}", name),
+        }
+    }
+
+    for (tag, &value) in &variations.0 {
+        let tag = Tag::from_bytes_lossy(tag.as_bytes());
+        match axes.iter().position(|axis| axis.tag() == tag.0) {
+            Some(index) => values[index] = Some(value),
+            None => debug!("Font has no {} axis", tag),
+        }
+    }
+
+    axes.iter()
+        .zip(values)
+        .filter_map(|(axis, value)| {
+            let value = value?.clamp(axis.min_value(), axis.max_value());
+            Some((Tag(axis.tag()), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_font::TestFont;
+
+    /// wght 100-900, wdth 50-200 and opsz 8-144, with two named instances
+    fn variable_font() -> Vec<u8> {
+        TestFont::new("Variable")
+            .axis(b"wght", 100.0, 400.0, 900.0)
+            .axis(b"wdth", 50.0, 100.0, 200.0)
+            .axis(b"opsz", 8.0, 12.0, 144.0)
+            .instance("Condensed Bold", &[700.0, 75.0, 12.0])
+            .instance("Wide Light", &[300.0, 150.0, 12.0])
+            .build()
+    }
+
+    type Request<'a> = (f32, f32, FontStyle, Option<&'a str>, &'a [(&'a str, f32)]);
+
+    /// Axis values as (tag, value) strings and numbers
+    fn resolve_for(data: &[u8], request: Request, defaults: &[AxisValue]) -> Vec<(String, f32)> {
+        let (weight, size_px, style, instance, variations) = request;
+        let variations = Variations(
+            variations
+                .iter()
+                .map(|&(tag, value)| (tag.to_string(), value))
+                .collect(),
+        );
+        let font = FontRef::from_index(data, 0).unwrap();
+        resolve(
+            font,
+            weight,
+            size_px,
+            style,
+            instance,
+            &variations,
+            defaults,
+        )
+        .into_iter()
+        .map(|(tag, value)| (tag.to_string(), value))
+        .collect()
+    }
+
+    fn axes(values: &[(&str, f32)]) -> Vec<(String, f32)> {
+        values
+            .iter()
+            .map(|&(tag, value)| (tag.to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn static_fonts_have_no_axes() {
+        let data = TestFont::new("Static").build();
+        let request = (700.0, 20.0, FontStyle::Italic, None, &[("wght", 500.0)][..]);
+        assert!(resolve_for(&data, request, &[]).is_empty());
+    }
+
+    #[test]
+    fn follows_weight_and_size() {
+        let data = variable_font();
+        let request = (700.0, 20.0, FontStyle::Normal, None, &[][..]);
+        assert_eq!(
+            resolve_for(&data, request, &[]),
+            axes(&[("wght", 700.0), ("opsz", 20.0)])
+        );
+        // Clamped to the axis ranges
+        let request = (1000.0, 4.0, FontStyle::Normal, None, &[][..]);
+        assert_eq!(
+            resolve_for(&data, request, &[]),
+            axes(&[("wght", 900.0), ("opsz", 8.0)])
+        );
+        // The font's own defaults fill the axes nothing else sets
+        let defaults = [(Tag::from_bytes(b"wdth"), 80.0)];
+        let request = (400.0, 20.0, FontStyle::Normal, None, &[][..]);
+        assert_eq!(
+            resolve_for(&data, request, &defaults),
+            axes(&[("wght", 400.0), ("wdth", 80.0), ("opsz", 20.0)])
+        );
+    }
+
+    #[test]
+    fn named_instances_and_explicit_axes_win() {
+        let data = variable_font();
+        let request = (
+            400.0,
+            20.0,
+            FontStyle::Normal,
+            Some("condensed bold"),
+            &[][..],
+        );
+        assert_eq!(
+            resolve_for(&data, request, &[]),
+            axes(&[("wght", 700.0), ("wdth", 75.0), ("opsz", 12.0)])
+        );
+        let request = (
+            400.0,
+            20.0,
+            FontStyle::Normal,
+            Some("Wide Light"),
+            &[("wdth", 90.0), ("GRAD", 1.0)][..],
+        );
+        assert_eq!(
+            resolve_for(&data, request, &[]),
+            axes(&[("wght", 300.0), ("wdth", 90.0), ("opsz", 12.0)])
+        );
+        // An unknown instance is ignored
+        let request = (400.0, 20.0, FontStyle::Normal, Some("Black"), &[][..]);
+        assert_eq!(
+            resolve_for(&data, request, &[]),
+            axes(&[("wght", 400.0), ("opsz", 20.0)])
+        );
+    }
+
+    #[test]
+    fn slants_with_ital_or_slnt() {
+        let slnt = TestFont::new("Slanted")
+            .axis(b"slnt", -20.0, 0.0, 0.0)
+            .build();
+        let request = (400.0, 20.0, FontStyle::Italic, None, &[][..]);
+        assert_eq!(resolve_for(&slnt, request, &[]), axes(&[("slnt", -14.0)]));
+
+        let both = TestFont::new("Italic")
+            .axis(b"ital", 0.0, 0.0, 1.0)
+            .axis(b"slnt", -20.0, 0.0, 0.0)
+            .build();
+        // Italic uses ital when there is one, oblique always slnt
+        let request = (400.0, 20.0, FontStyle::Italic, None, &[][..]);
+        assert_eq!(resolve_for(&both, request, &[]), axes(&[("ital", 1.0)]));
+        let request = (400.0, 20.0, FontStyle::Oblique, None, &[][..]);
+        assert_eq!(resolve_for(&both, request, &[]), axes(&[("slnt", -14.0)]));
+    }
+}
@@ -0,0 +1,143 @@
+use rustybuzz::ttf_parser::gsub::SubstitutionSubtable;
+use rustybuzz::ttf_parser::{Face, Tag};
+use serde::Deserialize;
+use unicode_vo::{char_orientation, Orientation as VerticalOrientation};
+
+/// Line orientation requested for a layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// Glyphs advance left to right (or right to left) along a horizontal baseline
+    #[default]
+    Horizontal,
+    /// Glyphs advance top to bottom down a single column
+    Vertical,
+}
+
+/// Whether a char stands upright in a vertical line, following UAX #50.
+///
+/// Tr chars (brackets, the long vowel mark, ...) stay upright only when the font has
+/// a `vert`/`vrt2` alternate for them; otherwise they're rotated like Latin text.
+pub fn is_upright(face: Option<&Face>, ch: char) -> bool {
+    match char_orientation(ch) {
+        VerticalOrientation::Upright | VerticalOrientation::TransformedOrUpright => true,
+        VerticalOrientation::Rotated => false,
+        VerticalOrientation::TransformedOrRotated => {
+            face.is_some_and(|face| has_vertical_alternate(face, ch))
+        }
+    }
+}
+
+/// Whether a single substitution in the font's `vert` or `vrt2` feature covers the char.
+fn has_vertical_alternate(face: &Face, ch: char) -> bool {
+    let Some(glyph) = face.glyph_index(ch) else {
+        return false;
+    };
+    let Some(gsub) = face.tables().gsub else {
+        return false;
+    };
+
+    let vert = Tag::from_bytes(b"vert");
+    let vrt2 = Tag::from_bytes(b"vrt2");
+    gsub.features
+        .into_iter()
+        .filter(|feature| feature.tag == vert || feature.tag == vrt2)
+        .flat_map(|feature| feature.lookup_indices)
+        .filter_map(|index| gsub.lookups.get(index))
+        .any(|lookup| {
+            lookup
+                .subtables
+                .into_iter::<SubstitutionSubtable>()
+                .any(|subtable| {
+                    matches!(subtable, SubstitutionSubtable::Single(_))
+                        && subtable.coverage().contains(glyph)
+                })
+        })
+}
+
+/// Rotate an RGBA glyph bitmap 90° clockwise for a sideways glyph in a vertical line.
+///
+/// Takes and returns `(rgba, width, height, xmin, ymin)` as produced by `generate_sdf`,
+/// with the bearings moved along so the bitmap still lines up with the glyph origin.
+pub fn rotate_bitmap_clockwise(
+    (rgba, width, height, xmin, ymin): (Vec<u8>, u32, u32, f32, f32),
+) -> (Vec<u8>, u32, u32, f32, f32) {
+    let (w, h) = (width as usize, height as usize);
+    let mut rotated = vec![0u8; rgba.len()];
+    for y in 0..h {
+        for x in 0..w {
+            // Source (x, y) lands in column (h - 1 - y), row x of the rotated image
+            let src = (y * w + x) * 4;
+            let dst = (x * h + (h - 1 - y)) * 4;
+            rotated[dst..dst + 4].copy_from_slice(&rgba[src..src + 4]);
+        }
+    }
+
+    // In y-up glyph space a clockwise turn maps (x, y) to (y, -x)
+    (rotated, height, width, ymin, -(xmin + width as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{LayoutOptions, TextEngine};
+    use crate::test_font::TestFont;
+
+    /// CJK and Latin chars 500 units wide, with a `vert` alternate for "ー" only
+    fn font() -> TestFont {
+        TestFont::new("Test")
+            .chars("漢ー〜a", 500)
+            .vertical_alternate('ー')
+    }
+
+    #[test]
+    fn upright_follows_uax_50() {
+        let data = font().build();
+        let face = Face::parse(&data, 0).unwrap();
+        assert!(is_upright(Some(&face), '漢'));
+        assert!(!is_upright(Some(&face), 'a'));
+        // Tr chars stand upright only with a vertical alternate
+        assert!(is_upright(Some(&face), 'ー'));
+        assert!(!is_upright(Some(&face), '〜'));
+        assert!(!is_upright(None, 'ー'));
+    }
+
+    #[test]
+    fn rotates_bitmaps_clockwise() {
+        // 2×1 bitmap: red then green, left to right
+        let rgba = vec![255, 0, 0, 255, 0, 255, 0, 255];
+        let (rotated, width, height, xmin, ymin) = rotate_bitmap_clockwise((rgba, 2, 1, 1.0, 3.0));
+        // Now 1×2, red on top
+        assert_eq!((width, height), (1, 2));
+        assert_eq!(rotated, [255, 0, 0, 255, 0, 255, 0, 255]);
+        assert_eq!((xmin, ymin), (3.0, -3.0));
+    }
+
+    #[test]
+    fn lays_out_a_column() {
+        let data = font().build();
+        // The alternate comes right after the mapped chars
+        let alternate = font().glyph('a') + 1;
+        let mut engine = TextEngine::new(512, 512);
+        engine.load_font(data, 0);
+        let options = LayoutOptions {
+            orientation: Orientation::Vertical,
+            ..Default::default()
+        };
+        let result = engine.process_text("漢ーa", 20.0, 400.0, &options);
+
+        assert!(result.is_vertical);
+        assert_eq!(result.glyph_ids[1], alternate);
+        // Upright glyphs advance a full em down the column, the sideways "a" its width
+        let ys: Vec<f32> = result
+            .positions
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .copied()
+            .collect();
+        assert_eq!(ys[1] - ys[0], 20.0);
+        assert_eq!(result.total_height, 50.0);
+        assert_eq!(result.total_width, 20.0);
+    }
+}